@@ -1,5 +1,32 @@
+use belc_ast::ParserError;
+use belc_codegen_vm::CodegenError;
+use belc_lexer::Span;
+
 #[derive(thiserror::Error, Debug)]
 pub enum SyntaxError {
     #[error(transparent)]
     Lexer(#[from] belc_lexer::LexerError),
 }
+
+/// Reasons a program doesn't compile
+#[derive(thiserror::Error, Debug)]
+pub enum CompileError {
+    #[error(transparent)]
+    Parser(#[from] ParserError),
+
+    #[error(transparent)]
+    Codegen(#[from] CodegenError),
+}
+
+/// Problems found in a program that still compiles
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum Warning {
+    #[error("integer overflow in `{0}`")]
+    IntegerOverflow(String),
+
+    #[error("division by zero in `{0}`")]
+    DivisionByZero(String),
+
+    #[error("unreachable code at {0}")]
+    UnreachableCode(Span),
+}
@@ -0,0 +1,413 @@
+//! Constant folding of the AST.
+//!
+//! Pure operations on integer and boolean literals are evaluated at compile
+//! time, with the same semantics as The Belalang VM. Operations that would
+//! fail at runtime are left in place, so the VM still raises the error, and
+//! are reported as a [`Warning`].
+//!
+//! `if` expressions with a constant condition are replaced by the branch that
+//! is taken.
+
+use belc_ast::{
+    BlockExpression, BooleanExpression, Expression, IfExpression, InfixExpression, IntegerLiteral, NullLiteral,
+    PrefixExpression, Program, Statement,
+};
+use belc_lexer::{InfixKind, PrefixKind, Span};
+
+use crate::error::Warning;
+
+/// Folds every constant expression in `program` in place, returning the
+/// warnings found along the way.
+pub fn fold_program(program: &mut Program) -> Vec<Warning> {
+    let mut folder = Folder::default();
+
+    for statement in &mut program.statements {
+        folder.statement(statement);
+    }
+
+    folder.warnings
+}
+
+/// A literal value the folder can compute with
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Literal {
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl Literal {
+    fn from_expression(expression: &Expression) -> Option<Self> {
+        match expression {
+            Expression::Integer(integer) => Some(Literal::Integer(integer.value)),
+            Expression::Boolean(boolean) => Some(Literal::Boolean(boolean.value)),
+            _ => None,
+        }
+    }
+
+    /// Truthiness as seen by `JUMP_IF_FALSE`
+    fn is_truthy(self) -> bool {
+        match self {
+            Literal::Integer(int) => int > 0,
+            Literal::Boolean(boolean) => boolean,
+        }
+    }
+}
+
+impl From<Literal> for Expression {
+    fn from(literal: Literal) -> Self {
+        match literal {
+            Literal::Integer(value) => Expression::Integer(IntegerLiteral { value }),
+            Literal::Boolean(value) => Expression::Boolean(BooleanExpression { value }),
+        }
+    }
+}
+
+/// Reasons an operation on literals fails, mirroring the VM's runtime errors
+enum Fault {
+    /// The operand types don't support the operation. The VM reports a type
+    /// error, which is not something the folder warns about.
+    Type,
+    Overflow,
+    DivisionByZero,
+}
+
+fn evaluate_infix(left: Literal, operator: &InfixKind, right: Literal) -> Result<Literal, Fault> {
+    use Literal::*;
+
+    let overflow = |result: Option<i64>| result.map(Integer).ok_or(Fault::Overflow);
+
+    match (left, operator, right) {
+        (Integer(a), InfixKind::Add, Integer(b)) => overflow(a.checked_add(b)),
+        (Integer(a), InfixKind::Sub, Integer(b)) => overflow(a.checked_sub(b)),
+        (Integer(a), InfixKind::Mul, Integer(b)) => overflow(a.checked_mul(b)),
+        (Integer(_), InfixKind::Div | InfixKind::Mod, Integer(0)) => Err(Fault::DivisionByZero),
+        (Integer(a), InfixKind::Div, Integer(b)) => overflow(a.checked_div(b)),
+        (Integer(a), InfixKind::Mod, Integer(b)) => overflow(a.checked_rem(b)),
+
+        (Integer(a), InfixKind::Eq, Integer(b)) => Ok(Boolean(a == b)),
+        (Boolean(a), InfixKind::Eq, Boolean(b)) => Ok(Boolean(a == b)),
+        (Integer(a), InfixKind::Ne, Integer(b)) => Ok(Boolean(a != b)),
+        (Boolean(a), InfixKind::Ne, Boolean(b)) => Ok(Boolean(a != b)),
+        (Integer(a), InfixKind::Lt, Integer(b)) => Ok(Boolean(a < b)),
+        (Integer(a), InfixKind::Le, Integer(b)) => Ok(Boolean(a <= b)),
+        (Integer(a), InfixKind::Gt, Integer(b)) => Ok(Boolean(a > b)),
+        (Integer(a), InfixKind::Ge, Integer(b)) => Ok(Boolean(a >= b)),
+
+        (Boolean(a), InfixKind::And, Boolean(b)) => Ok(Boolean(a && b)),
+        (Boolean(a), InfixKind::Or, Boolean(b)) => Ok(Boolean(a || b)),
+
+        (Integer(a), InfixKind::BitAnd, Integer(b)) => Ok(Integer(a & b)),
+        (Integer(a), InfixKind::BitOr, Integer(b)) => Ok(Integer(a | b)),
+        (Integer(a), InfixKind::BitXor, Integer(b)) => Ok(Integer(a ^ b)),
+        (Integer(a), InfixKind::ShiftLeft, Integer(b)) => {
+            overflow(u32::try_from(b).ok().and_then(|b| a.checked_shl(b)))
+        },
+        (Integer(a), InfixKind::ShiftRight, Integer(b)) => {
+            overflow(u32::try_from(b).ok().and_then(|b| a.checked_shr(b)))
+        },
+
+        _ => Err(Fault::Type),
+    }
+}
+
+fn evaluate_prefix(operator: &PrefixKind, right: Literal) -> Result<Literal, Fault> {
+    match (operator, right) {
+        (PrefixKind::Not, Literal::Boolean(boolean)) => Ok(Literal::Boolean(!boolean)),
+        (PrefixKind::Sub, Literal::Integer(int)) => int.checked_neg().map(Literal::Integer).ok_or(Fault::Overflow),
+        _ => Err(Fault::Type),
+    }
+}
+
+/// Returns `true` if `expression` can only evaluate to an integer, or fail.
+///
+/// Every arithmetic and bitwise operation in the VM produces an integer, so
+/// identities like `x + 0` can be simplified for these without changing which
+/// programs raise a type error.
+fn is_integer(expression: &Expression) -> bool {
+    match expression {
+        Expression::Integer(_) => true,
+        Expression::Prefix(prefix) => prefix.operator == PrefixKind::Sub,
+        Expression::Infix(infix) => matches!(
+            infix.operator,
+            InfixKind::Add
+                | InfixKind::Sub
+                | InfixKind::Mul
+                | InfixKind::Div
+                | InfixKind::Mod
+                | InfixKind::BitAnd
+                | InfixKind::BitOr
+                | InfixKind::BitXor
+                | InfixKind::ShiftLeft
+                | InfixKind::ShiftRight
+        ),
+        _ => false,
+    }
+}
+
+fn is_integer_literal(expression: &Expression, value: i64) -> bool {
+    matches!(expression, Expression::Integer(integer) if integer.value == value)
+}
+
+/// Span of the first statement of an `else` branch
+fn first_span(expression: &Expression) -> Option<Span> {
+    match expression {
+        Expression::Block(block) => block.statements.first().map(Statement::span),
+        Expression::If(r#if) => r#if
+            .consequence
+            .statements
+            .first()
+            .map(Statement::span)
+            .or_else(|| r#if.alternative.as_deref().and_then(first_span)),
+        _ => None,
+    }
+}
+
+fn take(expression: &mut Expression) -> Expression {
+    std::mem::replace(expression, Expression::Null(NullLiteral {}))
+}
+
+/// Applies algebraic identities that leave one of the operands, like `x * 1`.
+fn simplify_infix(infix: &mut InfixExpression) -> Option<Expression> {
+    let (left, right) = (&*infix.left, &*infix.right);
+
+    let keep_left = match infix.operator {
+        InfixKind::Add | InfixKind::Sub | InfixKind::BitOr | InfixKind::BitXor => is_integer_literal(right, 0),
+        InfixKind::ShiftLeft | InfixKind::ShiftRight => is_integer_literal(right, 0),
+        InfixKind::Mul | InfixKind::Div => is_integer_literal(right, 1),
+        _ => false,
+    };
+
+    let keep_right = match infix.operator {
+        InfixKind::Add | InfixKind::BitOr | InfixKind::BitXor => is_integer_literal(left, 0),
+        InfixKind::Mul => is_integer_literal(left, 1),
+        _ => false,
+    };
+
+    if keep_left && is_integer(left) {
+        Some(take(&mut infix.left))
+    } else if keep_right && is_integer(right) {
+        Some(take(&mut infix.right))
+    } else {
+        None
+    }
+}
+
+#[derive(Default)]
+struct Folder {
+    warnings: Vec<Warning>,
+}
+
+impl Folder {
+    fn statement(&mut self, statement: &mut Statement) {
+        match statement {
+            Statement::Expression(statement) => self.expression(&mut statement.expression),
+            Statement::Return(r#return) => self.expression(&mut r#return.return_value),
+            Statement::While(r#while) => {
+                self.expression(&mut r#while.condition);
+                self.block(&mut r#while.block);
+            },
+        }
+    }
+
+    fn block(&mut self, block: &mut BlockExpression) {
+        for statement in &mut block.statements {
+            self.statement(statement);
+        }
+    }
+
+    fn expression(&mut self, expression: &mut Expression) {
+        match expression {
+            Expression::Array(array) => {
+                for element in &mut array.elements {
+                    self.expression(element);
+                }
+            },
+            Expression::Var(var) => self.expression(&mut var.value),
+            Expression::Call(call) => {
+                self.expression(&mut call.function);
+                for arg in &mut call.args {
+                    self.expression(arg);
+                }
+            },
+            Expression::Index(index) => {
+                self.expression(&mut index.left);
+                self.expression(&mut index.index);
+            },
+            Expression::Function(function) => self.block(&mut function.body),
+            Expression::If(r#if) => {
+                self.expression(&mut r#if.condition);
+                self.block(&mut r#if.consequence);
+                if let Some(alternative) = &mut r#if.alternative {
+                    self.expression(alternative);
+                }
+            },
+            Expression::Infix(infix) => {
+                self.expression(&mut infix.left);
+                self.expression(&mut infix.right);
+            },
+            Expression::Prefix(prefix) => self.expression(&mut prefix.right),
+            Expression::Block(block) => self.block(block),
+            _ => {},
+        }
+
+        let folded = match expression {
+            Expression::Infix(infix) => self.infix(infix),
+            Expression::Prefix(prefix) => self.prefix(prefix),
+            Expression::If(r#if) => self.fold_if(r#if),
+            _ => None,
+        };
+
+        if let Some(folded) = folded {
+            *expression = folded;
+        }
+    }
+
+    fn infix(&mut self, infix: &mut InfixExpression) -> Option<Expression> {
+        let (Some(left), Some(right)) = (
+            Literal::from_expression(&infix.left),
+            Literal::from_expression(&infix.right),
+        ) else {
+            return simplify_infix(infix);
+        };
+
+        match evaluate_infix(left, &infix.operator, right) {
+            Ok(literal) => Some(literal.into()),
+            Err(fault) => {
+                self.report(fault, infix.to_string());
+                None
+            },
+        }
+    }
+
+    fn prefix(&mut self, prefix: &mut PrefixExpression) -> Option<Expression> {
+        let right = Literal::from_expression(&prefix.right)?;
+
+        match evaluate_prefix(&prefix.operator, right) {
+            Ok(literal) => Some(literal.into()),
+            Err(fault) => {
+                self.report(fault, prefix.to_string());
+                None
+            },
+        }
+    }
+
+    /// Replaces an `if` with a constant condition by the branch that is
+    /// taken, warning about the branch that is dropped.
+    fn fold_if(&mut self, r#if: &mut IfExpression) -> Option<Expression> {
+        let condition = Literal::from_expression(&r#if.condition)?;
+
+        let (branch, dropped) = if condition.is_truthy() {
+            let branch = Expression::Block(BlockExpression {
+                statements: std::mem::take(&mut r#if.consequence.statements),
+            });
+
+            (branch, r#if.alternative.as_deref().and_then(first_span))
+        } else {
+            let branch = match r#if.alternative.take() {
+                Some(alternative) => *alternative,
+                None => Expression::Null(NullLiteral {}),
+            };
+
+            (branch, r#if.consequence.statements.first().map(Statement::span))
+        };
+
+        if let Some(span) = dropped {
+            self.warnings.push(Warning::UnreachableCode(span));
+        }
+
+        // an empty block produces no value, unlike the `if` it replaces
+        match branch {
+            Expression::Block(block) if block.statements.is_empty() => Some(Expression::Null(NullLiteral {})),
+            branch => Some(branch),
+        }
+    }
+
+    fn report(&mut self, fault: Fault, expression: String) {
+        match fault {
+            Fault::Type => {},
+            Fault::Overflow => self.warnings.push(Warning::IntegerOverflow(expression)),
+            Fault::DivisionByZero => self.warnings.push(Warning::DivisionByZero(expression)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use belc_ast::{Parser, Program};
+    use belc_lexer::Lexer;
+
+    use super::fold_program;
+    use crate::error::Warning;
+
+    fn fold(input: &str) -> (Program, Vec<Warning>) {
+        let source = input.to_owned();
+        let lexer = Lexer::new(&source);
+        let mut parser = Parser::new(lexer);
+        let mut program = parser.parse_program().unwrap();
+
+        let warnings = fold_program(&mut program);
+
+        (program, warnings)
+    }
+
+    #[test]
+    fn folds_arithmetic() {
+        let (program, warnings) = fold("60 * 60 * 24; -(2 + 3); 7 % 4 << 2;");
+
+        assert_eq!(program.to_string(), "86400;-5;12;");
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn folds_comparisons_and_logic() {
+        let (program, _) = fold("1 < 2; 3 >= 4; true == !false; true && 1 != 1;");
+
+        assert_eq!(program.to_string(), "true;false;true;false;");
+    }
+
+    #[test]
+    fn keeps_non_constant_operands() {
+        let (program, _) = fold("x := 1; x + 2 * 3; 1 + true;");
+
+        assert_eq!(program.to_string(), "x := 1;;(x + 6);(1 + true);");
+    }
+
+    #[test]
+    fn simplifies_identities() {
+        let (program, _) = fold("x := 1; (x * 2) * 1; 0 + -x; x + 0;");
+
+        // `x` alone might not be an integer, so `x + 0` has to stay
+        assert_eq!(program.to_string(), "x := 1;;(x * 2);(-x);(x + 0);");
+    }
+
+    #[test]
+    fn reports_runtime_errors() {
+        let (program, warnings) = fold("9223372036854775807 + 1; 1 / (2 - 2); 1 << 64;");
+
+        assert_eq!(program.to_string(), "(9223372036854775807 + 1);(1 / 0);(1 << 64);");
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::IntegerOverflow("(9223372036854775807 + 1)".into()),
+                Warning::DivisionByZero("(1 / 0)".into()),
+                Warning::IntegerOverflow("(1 << 64)".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn folds_constant_if() {
+        let (program, warnings) =
+            fold("if (1 < 2) { 10 } else { 20 };\nif (false) { 10 };\nif (0) { 10 } else { 20 };");
+
+        assert_eq!(program.to_string(), "{ 10; };null;{ 20; };");
+        assert_eq!(
+            warnings.iter().map(Warning::to_string).collect::<Vec<_>>(),
+            [
+                "unreachable code at 1:26",
+                "unreachable code at 2:14",
+                "unreachable code at 3:10"
+            ]
+        );
+    }
+}
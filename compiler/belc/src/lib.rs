@@ -1,18 +1,75 @@
-#![feature(assert_matches)]
-
 use belc_ast::Parser;
 use belc_codegen_vm::Compiler;
+pub use belc_codegen_vm::Target;
 pub use belc_codegen_vm::disassembler::disassemble;
+pub use belc_codegen_vm::peephole::optimize;
 use belc_lexer::Lexer;
 use belvm_bytecode::Bytecode;
+use error::{CompileError, Warning};
 
 pub mod error;
+pub mod fold;
+pub mod reachability;
+
+pub fn compile(source: &String) -> Result<Bytecode, CompileError> {
+    Ok(compile_with_warnings(source)?.0)
+}
+
+/// Compiles `source` like [`compile`], also returning the warnings found
+/// along the way.
+pub fn compile_with_warnings(source: &String) -> Result<(Bytecode, Vec<Warning>), CompileError> {
+    compile_with_target(source, Target::Stack)
+}
 
-pub fn compile(source: &String) -> Bytecode {
+/// Compiles `source` for the instruction set of `target`, also returning the
+/// warnings found along the way.
+pub fn compile_with_target(source: &String, target: Target) -> Result<(Bytecode, Vec<Warning>), CompileError> {
     let lexer = Lexer::new(source);
     let mut parser = Parser::new(lexer);
-    let program = parser.parse_program().unwrap();
+    let mut program = parser.parse_program()?;
+
+    let mut warnings = fold::fold_program(&mut program);
+    warnings.extend(reachability::eliminate_dead_code(&mut program));
 
     let mut compiler = Compiler::default();
-    compiler.compile_program(program).unwrap()
+    compiler.target = target;
+
+    let bytecode = compiler.compile_program(program)?;
+
+    Ok((bytecode, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use belc_codegen_vm::CodegenError;
+
+    use super::*;
+
+    #[test]
+    fn syntax_errors_are_returned() {
+        let result = compile(&"x := ;".to_owned());
+        assert!(matches!(result, Err(CompileError::Parser(_))));
+    }
+
+    #[test]
+    fn unsupported_register_code_is_returned() {
+        let result = compile_with_target(&"f := fn(x) { x }; f(1);".to_owned(), Target::Register);
+        assert!(matches!(
+            result,
+            Err(CompileError::Codegen(CodegenError::Unsupported(_)))
+        ));
+    }
+
+    #[test]
+    fn limits_are_returned() {
+        // more locals than even a WIDE operand can address
+        let locals: String = (0..70_000).map(|i| format!("a{i} := 0; ")).collect();
+        let source = format!("f := fn() {{ {locals} 0 }};");
+
+        let result = compile(&source);
+        assert!(matches!(
+            result,
+            Err(CompileError::Codegen(CodegenError::LimitExceeded(_)))
+        ));
+    }
 }
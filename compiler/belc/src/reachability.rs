@@ -0,0 +1,204 @@
+//! Removal of code that can never run.
+//!
+//! Statements following a statement that always returns are dropped, and so
+//! are loops whose condition is a literal that is never true. Every piece of
+//! code removed is reported as a [`Warning::UnreachableCode`] pointing at its
+//! first statement.
+//!
+//! This runs after [`crate::fold`], which already replaces `if` expressions
+//! with a constant condition by the branch that is taken.
+
+use belc_ast::{BlockExpression, Expression, Program, Statement};
+
+use crate::error::Warning;
+
+/// Removes unreachable code from `program` in place, returning a warning for
+/// every piece of code removed.
+pub fn eliminate_dead_code(program: &mut Program) -> Vec<Warning> {
+    let mut analysis = Reachability::default();
+    analysis.statements(&mut program.statements);
+    analysis.warnings
+}
+
+/// Returns `true` if `expression` is a literal `JUMP_IF_FALSE` always jumps
+/// on.
+fn is_never_true(expression: &Expression) -> bool {
+    match expression {
+        Expression::Boolean(boolean) => !boolean.value,
+        Expression::Integer(integer) => integer.value <= 0,
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct Reachability {
+    warnings: Vec<Warning>,
+}
+
+impl Reachability {
+    /// Removes unreachable statements, returning `true` if the statements
+    /// always return.
+    fn statements(&mut self, statements: &mut Vec<Statement>) -> bool {
+        let mut i = 0;
+
+        while i < statements.len() {
+            if let Statement::While(r#while) = &statements[i]
+                && is_never_true(&r#while.condition)
+            {
+                if let Some(first) = r#while.block.statements.first() {
+                    self.warnings.push(Warning::UnreachableCode(first.span()));
+                }
+
+                statements.remove(i);
+                continue;
+            }
+
+            if self.statement(&mut statements[i]) {
+                if let Some(next) = statements.get(i + 1) {
+                    self.warnings.push(Warning::UnreachableCode(next.span()));
+                    statements.truncate(i + 1);
+                }
+
+                return true;
+            }
+
+            i += 1;
+        }
+
+        false
+    }
+
+    fn block(&mut self, block: &mut BlockExpression) -> bool {
+        self.statements(&mut block.statements)
+    }
+
+    /// Returns `true` if the statement always returns.
+    fn statement(&mut self, statement: &mut Statement) -> bool {
+        match statement {
+            Statement::Return(r#return) => {
+                self.expression(&mut r#return.return_value);
+                true
+            },
+            Statement::Expression(statement) => self.expression(&mut statement.expression),
+            Statement::While(r#while) => {
+                self.expression(&mut r#while.condition);
+
+                // the body might not run at all
+                self.block(&mut r#while.block);
+                false
+            },
+        }
+    }
+
+    /// Returns `true` if evaluating the expression always returns.
+    ///
+    /// Only blocks and `if` expressions are considered. A `return` nested in
+    /// an operand is rare enough that the code after it is kept.
+    fn expression(&mut self, expression: &mut Expression) -> bool {
+        match expression {
+            Expression::Block(block) => self.block(block),
+            Expression::If(r#if) => {
+                self.expression(&mut r#if.condition);
+
+                let consequence = self.block(&mut r#if.consequence);
+                let alternative = match &mut r#if.alternative {
+                    Some(alternative) => self.expression(alternative),
+                    None => false,
+                };
+
+                consequence && alternative
+            },
+            Expression::Function(function) => {
+                self.block(&mut function.body);
+                false
+            },
+            Expression::Array(array) => {
+                for element in &mut array.elements {
+                    self.expression(element);
+                }
+                false
+            },
+            Expression::Var(var) => {
+                self.expression(&mut var.value);
+                false
+            },
+            Expression::Call(call) => {
+                self.expression(&mut call.function);
+                for arg in &mut call.args {
+                    self.expression(arg);
+                }
+                false
+            },
+            Expression::Index(index) => {
+                self.expression(&mut index.left);
+                self.expression(&mut index.index);
+                false
+            },
+            Expression::Infix(infix) => {
+                self.expression(&mut infix.left);
+                self.expression(&mut infix.right);
+                false
+            },
+            Expression::Prefix(prefix) => {
+                self.expression(&mut prefix.right);
+                false
+            },
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use belc_ast::{Parser, Program};
+    use belc_lexer::Lexer;
+
+    use super::eliminate_dead_code;
+    use crate::error::Warning;
+
+    fn eliminate(input: &str) -> (Program, Vec<String>) {
+        let source = input.to_owned();
+        let lexer = Lexer::new(&source);
+        let mut parser = Parser::new(lexer);
+        let mut program = parser.parse_program().unwrap();
+
+        let warnings = eliminate_dead_code(&mut program)
+            .iter()
+            .map(Warning::to_string)
+            .collect();
+
+        (program, warnings)
+    }
+
+    #[test]
+    fn code_after_return() {
+        let (program, warnings) = eliminate("f := fn() {\n  return 1;\n  2;\n  3;\n};");
+
+        assert_eq!(program.to_string(), "f := fn() { return 1; };;");
+        assert_eq!(warnings, ["unreachable code at 3:3"]);
+    }
+
+    #[test]
+    fn code_after_returning_if() {
+        let (program, warnings) = eliminate("if (x) { return 1; } else { return 2; };\n4;");
+
+        assert_eq!(program.to_string(), "if (x) { return 1; } else { return 2; };");
+        assert_eq!(warnings, ["unreachable code at 2:1"]);
+    }
+
+    #[test]
+    fn keeps_code_after_partial_return() {
+        let (program, warnings) = eliminate("if (x) { return 1; };\n4;");
+
+        assert_eq!(program.to_string(), "if (x) { return 1; } else {};4;");
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn loop_never_entered() {
+        let (program, warnings) = eliminate("while (false) {\n  1;\n};\n2;");
+
+        assert_eq!(program.to_string(), "2;");
+        assert_eq!(warnings, ["unreachable code at 2:3"]);
+    }
+}
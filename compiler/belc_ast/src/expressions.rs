@@ -38,7 +38,7 @@ pub struct IntegerLiteral {
 
 impl std::fmt::Display for IntegerLiteral {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", &self.value)
+        write!(f, "{}", self.value)
     }
 }
 
@@ -56,7 +56,7 @@ pub struct FloatLiteral {
 
 impl std::fmt::Display for FloatLiteral {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", &self.value)
+        write!(f, "{}", self.value)
     }
 }
 
@@ -224,7 +224,7 @@ pub struct Identifier {
 
 impl std::fmt::Display for Identifier {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", &self.value)
+        write!(f, "{}", self.value)
     }
 }
 
@@ -2,6 +2,7 @@ use belc_lexer::InfixKind;
 use belc_lexer::Lexer;
 use belc_lexer::LiteralKind;
 use belc_lexer::PrefixKind;
+use belc_lexer::Span;
 use belc_lexer::Token;
 
 use super::{Expression, ParserError, Statement};
@@ -95,6 +96,9 @@ pub struct Parser<'a> {
     curr_token: Token,
     peek_token: Token,
 
+    curr_span: Span,
+    peek_span: Span,
+
     depth: i32,
     has_semicolon: bool,
 }
@@ -107,6 +111,9 @@ impl Parser<'_> {
             curr_token: Token::default(),
             peek_token: Token::default(),
 
+            curr_span: Span::default(),
+            peek_span: Span::default(),
+
             depth: 0,
             has_semicolon: false,
         }
@@ -114,7 +121,10 @@ impl Parser<'_> {
 
     fn next_token(&mut self) -> Result<(), ParserError> {
         self.curr_token = std::mem::take(&mut self.peek_token);
+        self.curr_span = self.peek_span;
+
         self.peek_token = self.lexer.next_token()?;
+        self.peek_span = self.lexer.span();
 
         Ok(())
     }
@@ -125,7 +135,10 @@ impl Parser<'_> {
     /// Each statement and expression is parsed and added to the program.
     pub fn parse_program(&mut self) -> Result<Program, ParserError> {
         self.curr_token = self.lexer.next_token()?;
+        self.curr_span = self.lexer.span();
+
         self.peek_token = self.lexer.next_token()?;
+        self.peek_span = self.lexer.span();
 
         let mut program = Program::default();
 
@@ -138,6 +151,8 @@ impl Parser<'_> {
     }
 
     fn parse_statement(&mut self) -> Result<Statement, ParserError> {
+        let start = self.curr_span;
+
         match self.curr_token {
             // parse_return
             Token::Return => {
@@ -146,7 +161,10 @@ impl Parser<'_> {
 
                 self.has_semicolon = expect_peek!(self, Token::Semicolon);
 
-                Ok(Statement::Return(ReturnStatement { return_value }))
+                Ok(Statement::Return(ReturnStatement {
+                    return_value,
+                    span: start.to(self.curr_span),
+                }))
             },
 
             // parse_while
@@ -163,6 +181,7 @@ impl Parser<'_> {
                 Ok(Statement::While(WhileStatement {
                     condition: Box::new(condition),
                     block,
+                    span: start.to(self.curr_span),
                 }))
             },
 
@@ -172,13 +191,14 @@ impl Parser<'_> {
 
                 self.has_semicolon = optional_peek!(self, Token::Semicolon);
 
-                Ok(Statement::Expression(ExpressionStatement { expression }))
+                Ok(Statement::Expression(ExpressionStatement {
+                    expression,
+                    span: start.to(self.curr_span),
+                }))
             },
 
             _ => {
-                let stmt = ExpressionStatement {
-                    expression: self.parse_expression(Precedence::Lowest)?,
-                };
+                let expression = self.parse_expression(Precedence::Lowest)?;
 
                 self.has_semicolon = if self.depth == 0 {
                     expect_peek!(self, Token::Semicolon)
@@ -186,7 +206,10 @@ impl Parser<'_> {
                     optional_peek!(self, Token::Semicolon)
                 };
 
-                Ok(Statement::Expression(stmt))
+                Ok(Statement::Expression(ExpressionStatement {
+                    expression,
+                    span: start.to(self.curr_span),
+                }))
             },
         }
     }
@@ -1,19 +1,23 @@
+use belc_lexer::Span;
+
 use super::{BlockExpression, Expression};
 
 #[derive(Debug, Clone)]
 pub struct ExpressionStatement {
     pub expression: Expression,
+    pub span: Span,
 }
 
 impl std::fmt::Display for ExpressionStatement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{};", &self.expression)
+        write!(f, "{};", self.expression)
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct ReturnStatement {
     pub return_value: Expression,
+    pub span: Span,
 }
 
 impl std::fmt::Display for ReturnStatement {
@@ -26,6 +30,7 @@ impl std::fmt::Display for ReturnStatement {
 pub struct WhileStatement {
     pub condition: Box<Expression>,
     pub block: BlockExpression,
+    pub span: Span,
 }
 
 impl std::fmt::Display for WhileStatement {
@@ -41,6 +46,17 @@ pub enum Statement {
     While(WhileStatement),
 }
 
+impl Statement {
+    /// Location of the statement in the source code
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Expression(v) => v.span,
+            Statement::Return(v) => v.span,
+            Statement::While(v) => v.span,
+        }
+    }
+}
+
 impl std::fmt::Display for Statement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let value = match self {
@@ -1,178 +1,159 @@
-use belvm_bytecode::opcode;
-
-fn read_u16(bytes: &[u8], i: &mut usize) -> (u16, usize) {
-    let hi = bytes[*i + 1];
-    let lo = bytes[*i + 2];
-    *i += 2;
-
-    ((hi as u16) << 8 | lo as u16, *i - 2)
+//! Human readable listing of compiled [`Bytecode`].
+//!
+//! Constant operands are annotated with the value they refer to, relative
+//! jumps are resolved to labels and function bodies get their own header.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use belvm_bytecode::opcode::Opcode;
+use belvm_bytecode::{Bytecode, Constant, DecodeError, Instruction, instructions};
+
+/// A single decoded line of the listing
+enum Line {
+    Instruction(Instruction),
+    Invalid { offset: usize, byte: u8 },
+    Truncated { offset: usize, name: &'static str },
 }
 
-fn read_u8(bytes: &[u8], i: &mut usize) -> (u16, usize) {
-    let value = bytes[*i + 1];
-    *i += 1;
-
-    (value as u16, *i - 1)
+impl Line {
+    fn offset(&self) -> usize {
+        match self {
+            Line::Instruction(instruction) => instruction.offset,
+            Line::Invalid { offset, .. } | Line::Truncated { offset, .. } => *offset,
+        }
+    }
 }
 
-pub fn disassemble(bytes: Vec<u8>) -> String {
-    let mut result = String::new();
-    let mut i = 0;
-
-    while i < bytes.len() {
-        match bytes[i] {
-            opcode::CONSTANT => {
-                let (operand, start_i) = read_u16(&bytes, &mut i);
-                result.push_str(&format!("{start_i:#06x}: CONSTANT {operand:#03}\n"));
-            },
-
-            opcode::POP => {
-                result.push_str(&format!("{i:#06x}: POP\n"));
-            },
-
-            opcode::ADD => {
-                result.push_str(&format!("{i:#06x}: ADD\n"));
-            },
-
-            opcode::SUB => {
-                result.push_str(&format!("{i:#06x}: SUB\n"));
-            },
-
-            opcode::MUL => {
-                result.push_str(&format!("{i:#06x}: MUL\n"));
-            },
-
-            opcode::DIV => {
-                result.push_str(&format!("{i:#06x}: DIV\n"));
-            },
-
-            opcode::MOD => {
-                result.push_str(&format!("{i:#06x}: MOD\n"));
-            },
-
-            opcode::TRUE => {
-                result.push_str(&format!("{i:#06x}: TRUE\n"));
-            },
-
-            opcode::FALSE => {
-                result.push_str(&format!("{i:#06x}: FALSE\n"));
-            },
-
-            opcode::NULL => {
-                result.push_str(&format!("{i:#06x}: NULL\n"));
-            },
-
-            opcode::EQUAL => {
-                result.push_str(&format!("{i:#06x}: EQUAL\n"));
-            },
-
-            opcode::NOT_EQUAL => {
-                result.push_str(&format!("{i:#06x}: NOT_EQUAL\n"));
-            },
-
-            opcode::LESS_THAN => {
-                result.push_str(&format!("{i:#06x}: LESS_THAN\n"));
-            },
-
-            opcode::LESS_THAN_EQUAL => {
-                result.push_str(&format!("{i:#06x}: LESS_THAN_EQUAL\n"));
-            },
-
-            opcode::AND => {
-                result.push_str(&format!("{i:#06x}: AND\n"));
-            },
-
-            opcode::OR => {
-                result.push_str(&format!("{i:#06x}: OR\n"));
-            },
-
-            opcode::BIT_AND => {
-                result.push_str(&format!("{i:#06x}: BIT_AND\n"));
-            },
-
-            opcode::BIT_OR => {
-                result.push_str(&format!("{i:#06x}: BIT_OR\n"));
-            },
-
-            opcode::BIT_XOR => {
-                result.push_str(&format!("{i:#06x}: BIT_XOR\n"));
-            },
-
-            opcode::BIT_SL => {
-                result.push_str(&format!("{i:#06x}: BIT_SL\n"));
-            },
-
-            opcode::BIT_SR => {
-                result.push_str(&format!("{i:#06x}: BIT_SR\n"));
-            },
-
-            opcode::BANG => {
-                result.push_str(&format!("{i:#06x}: BANG\n"));
-            },
-
-            opcode::MINUS => {
-                result.push_str(&format!("{i:#06x}: MINUS\n"));
-            },
-
-            opcode::JUMP => {
-                let (operand, start_i) = read_u16(&bytes, &mut i);
-                result.push_str(&format!("{start_i:#06x}: JUMP {operand:#03}\n"));
-            },
-            opcode::JUMP_IF_FALSE => {
-                let (operand, start_i) = read_u16(&bytes, &mut i);
-                result.push_str(&format!("{start_i:#06x}: JUMP_IF_FALSE {operand:#03}\n"));
-            },
-
-            opcode::SET_GLOBAL => {
-                let (operand, start_i) = read_u16(&bytes, &mut i);
-                result.push_str(&format!("{start_i:#06x}: SET_GLOBAL {operand:#03}\n"));
-            },
-
-            opcode::GET_GLOBAL => {
-                let (operand, start_i) = read_u16(&bytes, &mut i);
-                result.push_str(&format!("{start_i:#06x}: GET_GLOBAL {operand:#03}\n"));
-            },
-
-            opcode::SET_LOCAL => {
-                let (operand, start_i) = read_u8(&bytes, &mut i);
-                result.push_str(&format!("{start_i:#06x}: SET_LOCAL {operand:#03}\n"));
-            },
+fn decode(bytes: &[u8]) -> Vec<Line> {
+    instructions(bytes)
+        .map(|result| match result {
+            Ok(instruction) => Line::Instruction(instruction),
+            Err(DecodeError::UnknownOpcode { offset, byte }) => Line::Invalid { offset, byte },
+            Err(DecodeError::InvalidWide { offset, .. }) => Line::Invalid {
+                offset,
+                byte: bytes[offset],
+            },
+            Err(DecodeError::Truncated { offset, name }) => Line::Truncated { offset, name },
+            Err(DecodeError::UnexpectedEnd(_)) => unreachable!(),
+        })
+        .collect()
+}
 
-            opcode::GET_LOCAL => {
-                let (operand, start_i) = read_u8(&bytes, &mut i);
-                result.push_str(&format!("{start_i:#06x}: GET_LOCAL {operand:#03}\n"));
-            },
+fn describe_constant(index: usize, constant: &Constant) -> String {
+    match constant {
+        Constant::Null => "null".into(),
+        Constant::Integer(int) => int.to_string(),
+        Constant::Float(float) => format!("{float:?}"),
+        Constant::Boolean(boolean) => boolean.to_string(),
+        Constant::String(string) => format!("{string:?}"),
+        Constant::Function(function) => format!("<fn#{index} arity={}>", function.arity),
+    }
+}
 
-            opcode::GET_BUILTIN => {
-                let (operand, start_i) = read_u8(&bytes, &mut i);
-                result.push_str(&format!("{start_i:#06x}: GET_BUILTIN {operand:#03}\n"));
-            },
+/// Produces a listing of the instructions and function bodies in `bytecode`.
+///
+/// Bytes that are not valid opcodes are flagged inline instead of being
+/// skipped, so a corrupted stream is still readable.
+pub fn disassemble(bytecode: &Bytecode) -> String {
+    let lines = decode(&bytecode.instructions);
+
+    // jumping right past the last instruction is how execution ends, so the
+    // end of the stream is a valid target too
+    let starts: Vec<usize> = lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::Instruction(instruction) => Some(instruction.offset),
+            _ => None,
+        })
+        .chain(std::iter::once(bytecode.instructions.len()))
+        .collect();
+
+    let mut functions = BTreeMap::new();
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        if let Constant::Function(function) = constant {
+            functions.insert(function.pointer, (index, function));
+        }
+    }
 
-            opcode::CALL => {
-                result.push_str(&format!("{i:#06x}: CALL\n"));
-            },
+    let mut labels = BTreeMap::new();
+    for line in &lines {
+        if let Line::Instruction(instruction) = line
+            && let Some(target) = instruction.jump_target()
+            && starts.binary_search(&target).is_ok()
+        {
+            labels.insert(target, String::new());
+        }
+    }
 
-            opcode::RETURN => {
-                result.push_str(&format!("{i:#06x}: RETURN\n"));
-            },
+    for (i, label) in labels.values_mut().enumerate() {
+        *label = format!("L{i}");
+    }
 
-            opcode::RETURN_VALUE => {
-                result.push_str(&format!("{i:#06x}: RETURN_VALUE\n"));
-            },
+    let mut result = String::new();
 
-            opcode::MAKE_ARRAY => {
-                let (operand, start_i) = read_u16(&bytes, &mut i);
-                result.push_str(&format!("{start_i:#06x}: ARRAY {operand:#03}\n"));
-            },
+    for line in &lines {
+        let offset = line.offset();
 
-            opcode::INDEX => {
-                result.push_str(&format!("{i:#06x}: INDEX\n"));
-            },
+        if let Some((index, function)) = functions.get(&offset) {
+            let _ = writeln!(
+                result,
+                "\nfn#{index} (arity {}, locals {}):",
+                function.arity, function.locals_count
+            );
+        }
 
-            _ => {},
+        if let Some(label) = labels.get(&offset) {
+            let _ = writeln!(result, "{label}:");
         }
 
-        i += 1;
+        let _ = match line {
+            Line::Invalid { byte, .. } => writeln!(result, "{offset:#06x}: <invalid opcode {byte:#04x}>"),
+
+            Line::Truncated { name, .. } => writeln!(result, "{offset:#06x}: <truncated {name}>"),
+
+            Line::Instruction(instruction) => {
+                let name = match instruction.wide {
+                    true => format!("WIDE {}", instruction.op.name()),
+                    false => instruction.op.name().to_string(),
+                };
+
+                match instruction.op {
+                    Opcode::Constant => {
+                        let index = instruction.operands[0] as usize;
+                        let annotation = match bytecode.constants.get(index) {
+                            Some(constant) => describe_constant(index, constant),
+                            None => "<invalid constant>".into(),
+                        };
+
+                        writeln!(result, "{offset:#06x}: {name} {index:#03} ; {annotation}")
+                    },
+
+                    Opcode::Jump | Opcode::JumpIfFalse => {
+                        let relative = instruction.operands[0];
+                        let label = instruction.jump_target().and_then(|target| labels.get(&target));
+
+                        match label {
+                            Some(label) => writeln!(result, "{offset:#06x}: {name} {label} ({relative:+})"),
+                            None => writeln!(result, "{offset:#06x}: {name} <invalid target> ({relative:+})"),
+                        }
+                    },
+
+                    _ => {
+                        let _ = write!(result, "{offset:#06x}: {name}");
+                        for operand in instruction.operands.iter() {
+                            let _ = write!(result, " {operand:#03}");
+                        }
+
+                        writeln!(result)
+                    },
+                }
+            },
+        };
+    }
+
+    if let Some(label) = labels.get(&bytecode.instructions.len()) {
+        let _ = writeln!(result, "{label}:");
     }
 
     result
@@ -1,13 +1,18 @@
+use belc_ir::LowerError;
 use belc_lexer::Token;
+use belvm_bytecode::EncodeError;
 
 #[derive(thiserror::Error, Debug)]
 pub enum CodegenError {
     #[error("unknown infix operator: {0}")]
     UnknownInfixOp(Token),
 
-    #[error("duplicate symbol: {0}")]
-    DuplicateSymbol(String),
+    #[error(transparent)]
+    Lower(#[from] LowerError),
 
-    #[error("unknown symbol: {0}")]
-    UnknownSymbol(String),
+    #[error("unsupported by the register target: {0}")]
+    Unsupported(&'static str),
+
+    #[error("limit exceeded: {0}")]
+    LimitExceeded(#[from] EncodeError),
 }
@@ -0,0 +1,56 @@
+//! Final placement of instructions and their jumps.
+//!
+//! Jumps are kept as instruction indices until the very end, since a jump
+//! needing a [`WIDE`](belvm_bytecode::opcode::WIDE) prefix moves everything
+//! after it, which can in turn push other jumps over the limit.
+
+use belvm_bytecode::Instruction;
+
+use crate::error::CodegenError;
+
+/// An instruction together with the index of the instruction it jumps to.
+///
+/// A target equal to the number of instructions means the end of the stream.
+pub(crate) struct Node {
+    pub instruction: Instruction,
+    pub target: Option<usize>,
+}
+
+/// Encodes `nodes`, fixing up the jump offsets.
+///
+/// Returns the instructions along with the offset of every node, plus the
+/// offset of the end of the stream.
+pub(crate) fn layout(nodes: &mut [Node]) -> Result<(Vec<u8>, Vec<usize>), CodegenError> {
+    // jump sizes depend on the offsets and the offsets on the jump sizes,
+    // so lay the stream out until it settles
+    let mut offsets;
+    loop {
+        offsets = Vec::with_capacity(nodes.len() + 1);
+        let mut offset = 0;
+        for node in nodes.iter() {
+            offsets.push(offset);
+            offset += node.instruction.size();
+        }
+        offsets.push(offset);
+
+        let mut changed = false;
+        for (i, node) in nodes.iter_mut().enumerate() {
+            if let Some(target) = node.target {
+                let size = node.instruction.size();
+                node.instruction.operands[0] = offsets[target] as i64 - (offsets[i] + size) as i64;
+                changed |= node.instruction.size() != size;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    let mut instructions = Vec::with_capacity(offsets[nodes.len()]);
+    for node in nodes.iter() {
+        node.instruction.encode(&mut instructions)?;
+    }
+
+    Ok((instructions, offsets))
+}
@@ -1,32 +1,82 @@
 pub mod disassembler;
 mod error;
-mod scope;
+mod layout;
+pub mod peephole;
+mod register;
+
+use std::collections::HashMap;
+
+use belc_ast::Program;
+use belc_ir::scope::ScopeLevel;
+use belc_ir::{BinaryOp, Function, Lowerer, Op, Terminator, UnaryOp, Value, Variable};
+use belvm_bytecode::opcode::Opcode;
+use belvm_bytecode::{Bytecode, Constant, Instruction};
+pub use error::CodegenError;
+
+use crate::layout::{Node, layout};
+
+/// Identity of a literal constant in the constant pool.
+///
+/// Floats are compared by their bits, so `0.0` and `-0.0` stay distinct
+/// entries and `NaN` literals can still be shared.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Integer(i64),
+    Float(u64),
+    String(String),
+}
 
-use belc_ast::{BlockExpression, Expression, Program, Statement};
-use belc_lexer::{AssignmentKind, InfixKind, PrefixKind};
-use belvm_bytecode::opcode;
-use belvm_bytecode::{Bytecode, Constant};
-use scope::{ScopeLevel, ScopeManager};
+impl ConstantKey {
+    fn new(constant: &Constant) -> Option<Self> {
+        match constant {
+            Constant::Integer(int) => Some(ConstantKey::Integer(*int)),
+            Constant::Float(float) => Some(ConstantKey::Float(float.to_bits())),
+            Constant::String(string) => Some(ConstantKey::String(string.clone())),
+            _ => None,
+        }
+    }
+}
 
-use crate::error::CodegenError;
+/// Instruction set the [`Compiler`] generates code for
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// The stack-based instruction set in [`belvm_bytecode::opcode`]
+    #[default]
+    Stack,
+
+    /// The experimental register-based instruction set in
+    /// [`belvm_bytecode::register`], which doesn't support functions and
+    /// calls yet
+    Register,
+}
 
 #[derive(Default)]
 pub struct Compiler {
     prev_constants: usize,
 
+    /// Pool index of every literal constant added so far
+    interned: HashMap<ConstantKey, usize>,
+
+    /// Function literals whose bodies still have to be compiled, paired with
+    /// the index of their constant
+    functions: Vec<(usize, Function)>,
+
     pub constants: Vec<Constant>,
-    pub scope: ScopeManager,
+    pub lowerer: Lowerer,
+    pub target: Target,
 }
 
 impl Compiler {
     pub fn compile_program(&mut self, program: Program) -> Result<Bytecode, CodegenError> {
-        for statement in program.statements {
-            self.compile_statement(statement)?;
-        }
-
-        let mut instructions: Vec<_> = self.scope.main_scope.instructions.drain(..).collect();
-
-        instructions.push(opcode::RETURN_VALUE);
+        let function = self.lowerer.lower_program(program)?;
+        let instructions = match self.target {
+            Target::Stack => self.compile_with_functions(&function)?,
+            Target::Register => {
+                // every global has a register, temporaries go after them
+                let base = self.lowerer.scope.main_scope.symbol_count;
+                register::compile_function(self, &function, base)?
+            },
+        };
 
         let constants = self.constants[self.prev_constants..].to_vec();
         self.prev_constants = self.constants.len();
@@ -37,346 +87,188 @@ impl Compiler {
         })
     }
 
-    pub fn compile_statement(&mut self, statement: Statement) -> Result<(), CodegenError> {
-        match statement {
-            Statement::Expression(statement) => {
-                self.compile_expression(statement.expression)?;
-                self.add_bytecode(opcode::POP);
-            },
-
-            Statement::Return(r#return) => {
-                self.compile_expression(r#return.return_value)?;
-                self.add_bytecode(opcode::RETURN_VALUE);
-            },
-
-            Statement::While(r#while) => {
-                let start_of_while = self.scope.current().instructions.len();
-
-                self.compile_expression(*r#while.condition)?;
+    /// Emits `function`, followed by the bodies of every function literal in
+    /// it. Function constants point to the start of their body.
+    fn compile_with_functions(&mut self, function: &Function) -> Result<Vec<u8>, CodegenError> {
+        let mut instructions = self.compile_function(function)?;
 
-                let jif = self.add_instruction(opcode::jump_if_false(0).to_vec());
-                let jif_index = self.scope.current().instructions.len();
+        // bodies can contain function literals of their own
+        while !self.functions.is_empty() {
+            for (index, function) in std::mem::take(&mut self.functions) {
+                let pointer = instructions.len();
+                instructions.extend(self.compile_function(&function)?);
 
-                // self.scope.enter();
-                self.compile_block(r#while.block)?;
-                // let scope = self.scope.leave();
-
-                // self.scope
-                //     .current_mut()
-                //     .instructions
-                //     .extend(scope.instructions);
-
-                let jump = self.add_instruction(opcode::jump(0).to_vec());
-                let current = self.scope.current().instructions.len();
-
-                self.replace_u16_operand(jump, (start_of_while as isize - current as isize) as u16);
-                self.replace_u16_operand(jif, (current as isize - jif_index as isize) as u16);
-
-                self.add_bytecode(opcode::NOOP);
-            },
-        };
+                if let Constant::Function(function) = &mut self.constants[index] {
+                    function.pointer = pointer;
+                }
+            }
+        }
 
-        Ok(())
+        Ok(instructions)
     }
 
-    pub fn compile_expression(&mut self, expression: Expression) -> Result<(), CodegenError> {
-        match expression {
-            Expression::Boolean(boolean) => {
-                self.add_bytecode(if boolean.value { opcode::TRUE } else { opcode::FALSE });
-            },
-
-            Expression::Integer(integer) => {
-                let integer = Constant::Integer(integer.value);
-                let index = self.add_constant(integer) as u16;
-                self.add_instruction(opcode::constant(index).to_vec());
-            },
-
-            Expression::Float(_float) => {
-                // let float = Object::Float(float.value);
-                // let index = self.add_constant(float) as u16;
-                // self.add_instruction(opcode::constant(index).to_vec());
-            },
-
-            Expression::String(_string) => {
-                // let string = Object::String(string.value);
-                // let index = self.add_constant(string) as u16;
-                // self.add_instruction(opcode::constant(index).to_vec());
-            },
-
-            Expression::Null(_) => {
-                // let null = Object::Null;
-                // let index = self.add_constant(null) as u16;
-                // self.add_instruction(opcode::constant(index).to_vec());
-            },
-
-            Expression::Array(_array) => {
-                // let array_len = array.elements.len() as u16;
-                //
-                // for element in array.elements.into_iter().rev() {
-                //     self.compile_expression(element)?;
-                // }
-                //
-                // self.add_instruction(opcode::array(array_len).to_vec());
-            },
-
-            Expression::Var(var) => match var.kind {
-                AssignmentKind::ColonAssign => {
-                    let symbol = self.scope.define(var.name.value)?;
-                    let scope = symbol.scope;
-                    let index = symbol.index;
-
-                    self.compile_expression(*var.value)?;
-
-                    self.set_variable(&scope, index);
+    /// Emits the blocks of `function` in order.
+    ///
+    /// Jumps to the block that comes right after are left out, so control
+    /// simply falls through to it.
+    fn compile_function(&mut self, function: &Function) -> Result<Vec<u8>, CodegenError> {
+        let mut nodes = Vec::new();
+        let mut starts = Vec::with_capacity(function.blocks.len());
+
+        for id in function.block_ids() {
+            starts.push(nodes.len());
+
+            let block = function.block(id);
+            for op in &block.ops {
+                nodes.push(Node {
+                    instruction: self.compile_op(op),
+                    target: None,
+                });
+            }
+
+            let next = id.0 + 1;
+            let mut jump = |op, target: usize| {
+                nodes.push(Node {
+                    instruction: Instruction::new(op, &[0]),
+                    target: Some(target),
+                })
+            };
+
+            match block.terminator {
+                Terminator::Jump(target) => {
+                    if target.0 != next {
+                        jump(Opcode::Jump, target.0);
+                    }
                 },
-                _ => {
-                    let symbol = self.scope.resolve(var.name.value)?;
-                    let scope = symbol.scope;
-                    let index = symbol.index;
-
-                    match var.kind {
-                        AssignmentKind::Assign => {
-                            self.compile_expression(*var.value)?;
-                        },
-                        AssignmentKind::AddAssign => {
-                            self.get_variable(&scope, index);
-                            self.compile_expression(*var.value)?;
-                            self.add_bytecode(opcode::ADD);
-                        },
-                        AssignmentKind::SubAssign => {
-                            self.get_variable(&scope, index);
-                            self.compile_expression(*var.value)?;
-                            self.add_bytecode(opcode::SUB);
-                        },
-                        AssignmentKind::MulAssign => {
-                            self.get_variable(&scope, index);
-                            self.compile_expression(*var.value)?;
-                            self.add_bytecode(opcode::MUL);
-                        },
-                        AssignmentKind::DivAssign => {
-                            self.get_variable(&scope, index);
-                            self.compile_expression(*var.value)?;
-                            self.add_bytecode(opcode::DIV);
-                        },
-                        AssignmentKind::ModAssign => {
-                            self.get_variable(&scope, index);
-                            self.compile_expression(*var.value)?;
-                            self.add_bytecode(opcode::MOD);
-                        },
-                        AssignmentKind::BitAndAssign => {
-                            self.get_variable(&scope, index);
-                            self.compile_expression(*var.value)?;
-                            self.add_bytecode(opcode::BIT_AND);
-                        },
-                        AssignmentKind::BitOrAssign => {
-                            self.get_variable(&scope, index);
-                            self.compile_expression(*var.value)?;
-                            self.add_bytecode(opcode::BIT_OR);
-                        },
-                        AssignmentKind::BitXorAssign => {
-                            self.get_variable(&scope, index);
-                            self.compile_expression(*var.value)?;
-                            self.add_bytecode(opcode::BIT_XOR);
-                        },
-                        AssignmentKind::ShiftLeftAssign => {
-                            self.get_variable(&scope, index);
-                            self.compile_expression(*var.value)?;
-                            self.add_bytecode(opcode::BIT_SL);
-                        },
-                        AssignmentKind::ShiftRightAssign => {
-                            self.get_variable(&scope, index);
-                            self.compile_expression(*var.value)?;
-                            self.add_bytecode(opcode::BIT_SR);
-                        },
-                        _ => unreachable!(),
+                Terminator::Branch { then, otherwise } => {
+                    jump(Opcode::JumpIfFalse, otherwise.0);
+                    if then.0 != next {
+                        jump(Opcode::Jump, then.0);
                     }
-
-                    self.set_variable(&scope, index);
                 },
-            },
-
-            Expression::Call(call) => {
-                for arg in call.args.into_iter().rev() {
-                    self.compile_expression(arg)?;
-                }
-
-                self.compile_expression(*call.function)?;
-                self.add_bytecode(opcode::CALL);
-            },
-
-            Expression::Index(index) => {
-                self.compile_expression(*index.left)?;
-                self.compile_expression(*index.index)?;
-                self.add_bytecode(opcode::INDEX);
-            },
-
-            Expression::Function(_function) => {
-                todo!()
-            },
-
-            Expression::Identifier(ident) => {
-                let symbol = self.scope.resolve(ident.value)?;
-                let scope = symbol.scope;
-                let index = symbol.index;
+                Terminator::Return => nodes.push(Node {
+                    instruction: Instruction::new(Opcode::ReturnValue, &[]),
+                    target: None,
+                }),
+            }
+        }
 
-                self.get_variable(&scope, index);
-            },
+        // targets were block ids so far
+        for node in &mut nodes {
+            node.target = node.target.map(|block| starts[block]);
+        }
 
-            Expression::If(r#if) => {
-                self.compile_expression(*r#if.condition)?;
-
-                let jif = self.add_instruction(opcode::jump_if_false(0).to_vec());
-                let jif_index = self.scope.current().instructions.len();
-
-                // self.scope.enter();
-                self.compile_block(r#if.consequence)?;
-                // let scope = self.scope.leave();
-
-                // self.scope
-                //     .current_mut()
-                //     .instructions
-                //     .extend(scope.instructions);
-
-                let jump = self.add_instruction(opcode::jump(0).to_vec());
-                let jump_index = self.scope.current().instructions.len();
-
-                let post_consequence = self.scope.current().instructions.len();
-                self.replace_u16_operand(jif, (post_consequence - jif_index) as u16);
-
-                match r#if.alternative {
-                    None => {
-                        self.add_bytecode(opcode::NULL);
-                    },
-                    Some(alt) => match *alt {
-                        Expression::Block(block) => {
-                            // self.scope.enter();
-                            self.compile_block(block)?;
-                            // let scope = self.scope.leave();
-
-                            // self.scope
-                            //     .current_mut()
-                            //     .instructions
-                            //     .extend(scope.instructions);
-                        },
-                        _ => {
-                            self.compile_expression(*alt)?;
-                        },
-                    },
-                };
-
-                let post_alternative = self.scope.current().instructions.len();
-                self.replace_u16_operand(jump, (post_alternative - jump_index) as u16);
-            },
+        let (instructions, _) = layout(&mut nodes)?;
 
-            Expression::Infix(infix) => {
-                match infix.operator {
-                    InfixKind::Gt | InfixKind::Ge => {
-                        self.compile_expression(*infix.right)?;
-                        self.compile_expression(*infix.left)?;
-                    },
-                    _ => {
-                        self.compile_expression(*infix.left)?;
-                        self.compile_expression(*infix.right)?;
-                    },
-                }
+        Ok(instructions)
+    }
 
-                self.add_bytecode(match infix.operator {
-                    InfixKind::Add => opcode::ADD,
-                    InfixKind::Sub => opcode::SUB,
-                    InfixKind::Mul => opcode::MUL,
-                    InfixKind::Div => opcode::DIV,
-                    InfixKind::Mod => opcode::MOD,
-                    InfixKind::Eq => opcode::EQUAL,
-                    InfixKind::Ne => opcode::NOT_EQUAL,
-                    InfixKind::And => opcode::AND,
-                    InfixKind::Or => opcode::OR,
-                    InfixKind::BitAnd => opcode::BIT_AND,
-                    InfixKind::BitOr => opcode::BIT_OR,
-                    InfixKind::BitXor => opcode::BIT_XOR,
-                    InfixKind::ShiftLeft => opcode::BIT_SL,
-                    InfixKind::ShiftRight => opcode::BIT_SR,
-                    InfixKind::Lt | InfixKind::Gt => opcode::LESS_THAN,
-                    InfixKind::Le | InfixKind::Ge => opcode::LESS_THAN_EQUAL,
-                });
+    fn compile_op(&mut self, op: &Op) -> Instruction {
+        match op {
+            Op::Push(Value::Null) => Instruction::new(Opcode::Null, &[]),
+            Op::Push(Value::Boolean(true)) => Instruction::new(Opcode::True, &[]),
+            Op::Push(Value::Boolean(false)) => Instruction::new(Opcode::False, &[]),
+            Op::Push(Value::Integer(int)) => self.load_constant(Constant::Integer(*int)),
+            Op::Push(Value::Float(float)) => self.load_constant(Constant::Float(*float)),
+            Op::Push(Value::String(string)) => self.load_constant(Constant::String(string.clone())),
+            Op::Push(Value::Function(function)) => {
+                let instruction = self.load_constant(Constant::Function(belvm_bytecode::Function {
+                    pointer: 0,
+                    locals_count: function.locals_count,
+                    arity: function.arity,
+                }));
+
+                let index = self.constants.len() - 1;
+                self.functions.push((index, (**function).clone()));
+
+                instruction
             },
 
-            Expression::Prefix(prefix) => {
-                self.compile_expression(*prefix.right)?;
-                self.add_bytecode(match prefix.operator {
-                    PrefixKind::Sub => opcode::MINUS,
-                    PrefixKind::Not => opcode::BANG,
-                });
-            },
+            Op::Pop => Instruction::new(Opcode::Pop, &[]),
 
-            Expression::Block(block) => {
-                // self.scope.enter();
-                self.compile_block(block)?;
-                // let scope = self.scope.leave();
+            Op::Load(variable) => variable_instruction(
+                match variable.scope {
+                    ScopeLevel::Global => Opcode::GetGlobal,
+                    ScopeLevel::Local => Opcode::GetLocal,
+                    ScopeLevel::Builtin => Opcode::GetBuiltin,
+                },
+                variable,
+            ),
+
+            Op::Store(variable) => variable_instruction(
+                match variable.scope {
+                    ScopeLevel::Global => Opcode::SetGlobal,
+                    ScopeLevel::Local => Opcode::SetLocal,
+                    ScopeLevel::Builtin => unreachable!("builtins are never stored to"),
+                },
+                variable,
+            ),
+
+            Op::Binary(op) => Instruction::new(
+                match op {
+                    BinaryOp::Add => Opcode::Add,
+                    BinaryOp::Sub => Opcode::Sub,
+                    BinaryOp::Mul => Opcode::Mul,
+                    BinaryOp::Div => Opcode::Div,
+                    BinaryOp::Mod => Opcode::Mod,
+                    BinaryOp::Equal => Opcode::Equal,
+                    BinaryOp::NotEqual => Opcode::NotEqual,
+                    BinaryOp::LessThan => Opcode::LessThan,
+                    BinaryOp::LessThanEqual => Opcode::LessThanEqual,
+                    BinaryOp::And => Opcode::And,
+                    BinaryOp::Or => Opcode::Or,
+                    BinaryOp::BitAnd => Opcode::BitAnd,
+                    BinaryOp::BitOr => Opcode::BitOr,
+                    BinaryOp::BitXor => Opcode::BitXor,
+                    BinaryOp::ShiftLeft => Opcode::BitSl,
+                    BinaryOp::ShiftRight => Opcode::BitSr,
+                },
+                &[],
+            ),
 
-                // self.scope
-                //     .current_mut()
-                //     .instructions
-                //     .extend(scope.instructions);
-            },
-        };
+            Op::Unary(op) => Instruction::new(
+                match op {
+                    UnaryOp::Neg => Opcode::Minus,
+                    UnaryOp::Not => Opcode::Bang,
+                },
+                &[],
+            ),
 
-        Ok(())
-    }
+            Op::Call(argc) => Instruction::new(Opcode::Call, &[*argc as i64]),
 
-    fn compile_block(&mut self, block: BlockExpression) -> Result<(), CodegenError> {
-        for statement in block.statements {
-            self.compile_statement(statement)?;
-        }
+            Op::TailCall(argc) => Instruction::new(Opcode::TailCall, &[*argc as i64]),
 
-        if let Some(&opcode::POP) = self.scope.current().instructions.last() {
-            self.scope.current_mut().instructions.pop();
+            Op::Index => Instruction::new(Opcode::Index, &[]),
         }
-
-        Ok(())
     }
 
-    pub fn add_constant(&mut self, obj: Constant) -> usize {
-        self.constants.push(obj);
-        self.constants.len() - 1
-    }
-
-    pub fn add_bytecode(&mut self, byte: u8) -> usize {
-        let scope = self.scope.current_mut();
-
-        scope.instructions.push(byte);
-        scope.instructions.len() - 1
+    fn load_constant(&mut self, constant: Constant) -> Instruction {
+        let index = self.add_constant(constant);
+        Instruction::new(Opcode::Constant, &[index as i64])
     }
 
-    pub fn add_instruction(&mut self, bytes: Vec<u8>) -> usize {
-        let pos = self.scope.current_mut().instructions.len();
+    /// Adds `obj` to the constant pool and returns its index.
+    ///
+    /// Integer, float and string literals are interned, so adding one that is
+    /// already in the pool returns the existing index.
+    pub fn add_constant(&mut self, obj: Constant) -> usize {
+        let key = ConstantKey::new(&obj);
 
-        for byte in bytes {
-            self.add_bytecode(byte);
+        if let Some(index) = key.as_ref().and_then(|key| self.interned.get(key)) {
+            return *index;
         }
 
-        pos
-    }
-
-    pub fn replace_u16_operand(&mut self, index: usize, value: u16) {
-        let scope = self.scope.current_mut();
-
-        scope.instructions[index + 1] = (value >> 8) as u8;
-        scope.instructions[index + 2] = (value & 0xFF) as u8;
-    }
+        self.constants.push(obj);
+        let index = self.constants.len() - 1;
 
-    fn get_variable(&mut self, scope: &ScopeLevel, index: usize) -> usize {
-        match scope {
-            ScopeLevel::Global => self.add_instruction(opcode::get_global(index as u16).to_vec()),
-            ScopeLevel::Local => self.add_instruction(opcode::get_local(index as u8).to_vec()),
-            ScopeLevel::Builtin => self.add_instruction(opcode::get_builtin(index as u8).to_vec()),
+        if let Some(key) = key {
+            self.interned.insert(key, index);
         }
-    }
 
-    fn set_variable(&mut self, scope: &ScopeLevel, index: usize) -> usize {
-        match scope {
-            ScopeLevel::Global => self.add_instruction(opcode::set_global(index as u16).to_vec()),
-            ScopeLevel::Local => self.add_instruction(opcode::set_local(index as u8).to_vec()),
-            ScopeLevel::Builtin => 0,
-        }
+        index
     }
 }
+
+fn variable_instruction(op: Opcode, variable: &Variable) -> Instruction {
+    Instruction::new(op, &[variable.index as i64])
+}
@@ -0,0 +1,270 @@
+//! Peephole optimization of generated bytecode.
+//!
+//! The pass works on decoded [`Instruction`]s rather than raw bytes. Jump
+//! targets and function entry points are tracked as instruction indices while
+//! rewriting, and the stream is laid out again at the end, so relative jump
+//! offsets (and their [`WIDE`](belvm_bytecode::opcode::WIDE) prefixes) always
+//! match the new positions.
+
+use belvm_bytecode::opcode::Opcode;
+use belvm_bytecode::{Bytecode, Constant, Instruction, instructions};
+
+use crate::error::CodegenError;
+use crate::layout::{Node, layout};
+
+struct Program {
+    nodes: Vec<Node>,
+
+    /// Index of the first instruction of every function body, paired with the
+    /// index of its constant
+    functions: Vec<(usize, usize)>,
+}
+
+impl Program {
+    /// Decodes `bytecode`, or returns `None` if it contains bytes that can't
+    /// be decoded or jumps that don't land on an instruction.
+    fn decode(bytecode: &Bytecode) -> Option<Self> {
+        let decoded = instructions(&bytecode.instructions)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+
+        let mut offsets: Vec<usize> = decoded.iter().map(|instruction| instruction.offset).collect();
+        offsets.push(bytecode.instructions.len());
+
+        let index_of = |offset: usize| offsets.binary_search(&offset).ok();
+
+        let mut nodes = Vec::with_capacity(decoded.len());
+        for instruction in decoded {
+            let target = match instruction.jump_target() {
+                Some(target) => Some(index_of(target)?),
+                None if instruction.is_jump() => return None,
+                None => None,
+            };
+
+            nodes.push(Node { instruction, target });
+        }
+
+        let mut functions = Vec::new();
+        for (index, constant) in bytecode.constants.iter().enumerate() {
+            if let Constant::Function(function) = constant {
+                functions.push((index_of(function.pointer)?, index));
+            }
+        }
+
+        Some(Self { nodes, functions })
+    }
+
+    /// Flags every index some jump or function lands on.
+    fn targets(&self) -> Vec<bool> {
+        let mut targets = vec![false; self.nodes.len() + 1];
+
+        for target in self.nodes.iter().filter_map(|node| node.target) {
+            targets[target] = true;
+        }
+
+        for &(entry, _) in &self.functions {
+            targets[entry] = true;
+        }
+
+        targets
+    }
+
+    /// Drops the instructions at the flagged indices. Jumps to a dropped
+    /// instruction land on the next one that is kept instead.
+    fn remove(&mut self, removed: &[bool]) {
+        // new index of every old index, including the end of the stream
+        let mut remap = Vec::with_capacity(self.nodes.len() + 1);
+        let mut kept = 0;
+        for &removed in removed {
+            remap.push(kept);
+            if !removed {
+                kept += 1;
+            }
+        }
+        remap.push(kept);
+
+        let nodes = std::mem::take(&mut self.nodes);
+        self.nodes = nodes
+            .into_iter()
+            .zip(removed)
+            .filter(|(_, removed)| !**removed)
+            .map(|(mut node, _)| {
+                node.target = node.target.map(|target| remap[target]);
+                node
+            })
+            .collect();
+
+        for (entry, _) in &mut self.functions {
+            *entry = remap[*entry];
+        }
+    }
+
+    /// Points jumps that land on an unconditional `JUMP` to where that jump
+    /// goes.
+    fn thread_jumps(&mut self) -> bool {
+        let mut changed = false;
+
+        for i in 0..self.nodes.len() {
+            let Some(mut target) = self.nodes[i].target else {
+                continue;
+            };
+
+            // bounded, since a loop of jumps never settles
+            for _ in 0..self.nodes.len() {
+                match self.nodes.get(target) {
+                    Some(node) if node.instruction.op == Opcode::Jump && node.target != Some(target) => {
+                        target = node.target.unwrap();
+                    },
+                    _ => break,
+                }
+            }
+
+            if self.nodes[i].target != Some(target) {
+                self.nodes[i].target = Some(target);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// Drops `NOOP`s, jumps to the next instruction, values pushed only to be
+    /// popped, and loads of a variable that was just stored.
+    fn remove_redundant(&mut self) -> bool {
+        let targets = self.targets();
+        let mut removed = vec![false; self.nodes.len()];
+        let mut changed = false;
+
+        let mut i = 0;
+        while i < self.nodes.len() {
+            let node = &self.nodes[i];
+
+            match node.instruction.op {
+                Opcode::Noop => {
+                    removed[i] = true;
+                    changed = true;
+                },
+
+                Opcode::Jump if node.target == Some(i + 1) => {
+                    removed[i] = true;
+                    changed = true;
+                },
+
+                // still has to pop the condition, and can only skip checking
+                // it if nothing else jumps here with a value of another type
+                Opcode::JumpIfFalse
+                    if node.target == Some(i + 1)
+                        && i > 0
+                        && pushes_boolean(self.nodes[i - 1].instruction.op)
+                        && !targets[i] =>
+                {
+                    self.nodes[i] = Node {
+                        instruction: Instruction::new(Opcode::Pop, &[]),
+                        target: None,
+                    };
+                    changed = true;
+                },
+
+                Opcode::Constant
+                | Opcode::True
+                | Opcode::False
+                | Opcode::Null
+                | Opcode::GetGlobal
+                | Opcode::GetLocal
+                    if self.op_at(i + 1) == Some(Opcode::Pop) && !targets[i + 1] =>
+                {
+                    removed[i] = true;
+                    removed[i + 1] = true;
+                    changed = true;
+                    i += 1;
+                },
+
+                // SET_X n; POP; GET_X n leaves the same value on the stack as
+                // SET_X n alone
+                Opcode::SetGlobal | Opcode::SetLocal
+                    if self.op_at(i + 1) == Some(Opcode::Pop)
+                        && self.op_at(i + 2) == Some(load_of(node.instruction.op))
+                        && self.nodes[i + 2].instruction.operands == node.instruction.operands
+                        && !targets[i + 1]
+                        && !targets[i + 2] =>
+                {
+                    removed[i + 1] = true;
+                    removed[i + 2] = true;
+                    changed = true;
+                    i += 2;
+                },
+
+                _ => {},
+            }
+
+            i += 1;
+        }
+
+        if changed {
+            self.remove(&removed);
+        }
+
+        changed
+    }
+
+    fn op_at(&self, index: usize) -> Option<Opcode> {
+        self.nodes.get(index).map(|node| node.instruction.op)
+    }
+
+    /// Encodes the instructions again, fixing up jump offsets and function
+    /// pointers.
+    fn encode(mut self, bytecode: &mut Bytecode) -> Result<(), CodegenError> {
+        for node in &mut self.nodes {
+            node.instruction.wide = false;
+        }
+
+        let (instructions, offsets) = layout(&mut self.nodes)?;
+
+        for (entry, index) in self.functions {
+            if let Constant::Function(function) = &mut bytecode.constants[index] {
+                function.pointer = offsets[entry];
+            }
+        }
+
+        bytecode.instructions = instructions;
+
+        Ok(())
+    }
+}
+
+/// Whether `op` always leaves a boolean on the stack, which any condition
+/// accepts
+fn pushes_boolean(op: Opcode) -> bool {
+    matches!(
+        op,
+        Opcode::True
+            | Opcode::False
+            | Opcode::Bang
+            | Opcode::Equal
+            | Opcode::NotEqual
+            | Opcode::LessThan
+            | Opcode::LessThanEqual
+    )
+}
+
+/// The load matching a store opcode
+fn load_of(store: Opcode) -> Opcode {
+    match store {
+        Opcode::SetGlobal => Opcode::GetGlobal,
+        Opcode::SetLocal => Opcode::GetLocal,
+        _ => unreachable!(),
+    }
+}
+
+/// Rewrites the instructions of `bytecode` into a shorter equivalent.
+///
+/// Bytecode that can't be fully decoded is left untouched.
+pub fn optimize(bytecode: &mut Bytecode) -> Result<(), CodegenError> {
+    let Some(mut program) = Program::decode(bytecode) else {
+        return Ok(());
+    };
+
+    while program.thread_jumps() | program.remove_redundant() {}
+
+    program.encode(bytecode)
+}
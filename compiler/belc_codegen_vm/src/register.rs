@@ -0,0 +1,355 @@
+//! Code generation for the experimental register instruction set.
+//!
+//! The IR describes operations on an operand stack. Here every stack slot is
+//! given a fixed register instead: global variables keep the register of
+//! their symbol index and the value at stack depth `d` lives in register
+//! `base + d`, `base` being the number of global slots.
+//!
+//! Loading a variable doesn't copy it. The stack slot just refers to the
+//! variable's register until the variable is stored to or the block ends, so
+//! `i = i + 1` becomes a single `ADD` writing straight into `i`.
+//!
+//! Only straight-line code, branches and loops over globals are covered so
+//! far. Functions, calls, local variables and indexing have no register
+//! instructions yet, and programs using them fail with
+//! [`CodegenError::Unsupported`] instead of compiling.
+
+use belc_ir::scope::ScopeLevel;
+use belc_ir::{BinaryOp, BlockId, Function, Op, Terminator, UnaryOp, Value};
+use belvm_bytecode::opcode::OperandType;
+use belvm_bytecode::register::Opcode;
+use belvm_bytecode::{Constant, EncodeError};
+
+use crate::Compiler;
+use crate::error::CodegenError;
+
+/// A register instruction together with the block it jumps to, if any.
+///
+/// The jump offset is always the last operand.
+struct Node {
+    op: Opcode,
+    operands: Vec<i64>,
+    target: Option<BlockId>,
+}
+
+struct RegisterCodegen<'a> {
+    compiler: &'a mut Compiler,
+    base: usize,
+    nodes: Vec<Node>,
+
+    /// Register holding the value at each depth of the operand stack
+    stack: Vec<usize>,
+
+    /// Index of the last node if it computed the value on top of the stack
+    /// into a temporary, so that a store can retarget it
+    last_result: Option<usize>,
+}
+
+/// Compiles `function` into register instructions. `base` is the first
+/// register free for temporaries.
+pub(crate) fn compile_function(
+    compiler: &mut Compiler,
+    function: &Function,
+    base: usize,
+) -> Result<Vec<u8>, CodegenError> {
+    let depths = entry_depths(function)?;
+
+    // blocks nothing jumps to are left out entirely
+    let emitted: Vec<BlockId> = function.block_ids().filter(|id| depths[id.0].is_some()).collect();
+
+    let mut codegen = RegisterCodegen {
+        compiler,
+        base,
+        nodes: Vec::new(),
+        stack: Vec::new(),
+        last_result: None,
+    };
+
+    let mut starts = vec![0; function.blocks.len()];
+    for (i, &id) in emitted.iter().enumerate() {
+        starts[id.0] = codegen.nodes.len();
+
+        let depth = depths[id.0].unwrap_or_default();
+        codegen.stack = (0..depth).map(|d| base + d).collect();
+        codegen.last_result = None;
+
+        let block = function.block(id);
+        for op in &block.ops {
+            codegen.compile_op(op)?;
+        }
+
+        codegen.compile_terminator(block.terminator, emitted.get(i + 1).copied());
+    }
+
+    encode(&codegen.nodes, &starts)
+}
+
+impl RegisterCodegen<'_> {
+    fn compile_op(&mut self, op: &Op) -> Result<(), CodegenError> {
+        match op {
+            Op::Push(value) => {
+                let dst = self.temporary(self.stack.len());
+
+                match value {
+                    Value::Null => self.emit_result(Opcode::Null, vec![dst]),
+                    Value::Boolean(true) => self.emit_result(Opcode::True, vec![dst]),
+                    Value::Boolean(false) => self.emit_result(Opcode::False, vec![dst]),
+                    Value::Integer(int) => self.load_constant(dst, Constant::Integer(*int)),
+                    Value::Float(float) => self.load_constant(dst, Constant::Float(*float)),
+                    Value::String(string) => self.load_constant(dst, Constant::String(string.clone())),
+                    Value::Function(_) => return Err(CodegenError::Unsupported("functions")),
+                }
+            },
+
+            Op::Pop => {
+                self.stack.pop();
+                self.last_result = None;
+            },
+
+            Op::Load(variable) => {
+                if variable.scope != ScopeLevel::Global {
+                    return Err(CodegenError::Unsupported("non-global variables"));
+                }
+
+                self.stack.push(variable.index);
+                self.last_result = None;
+            },
+
+            Op::Store(variable) => {
+                if variable.scope != ScopeLevel::Global {
+                    return Err(CodegenError::Unsupported("non-global variables"));
+                }
+
+                self.store(variable.index);
+            },
+
+            Op::Binary(op) => {
+                let right = self.stack.pop().unwrap();
+                let left = self.stack.pop().unwrap();
+                let dst = self.temporary(self.stack.len());
+
+                self.emit_result(binary_opcode(*op), vec![dst, left as i64, right as i64]);
+            },
+
+            Op::Unary(op) => {
+                let src = self.stack.pop().unwrap();
+                let dst = self.temporary(self.stack.len());
+
+                let op = match op {
+                    UnaryOp::Neg => Opcode::Minus,
+                    UnaryOp::Not => Opcode::Bang,
+                };
+
+                self.emit_result(op, vec![dst, src as i64]);
+            },
+
+            Op::Call(_) | Op::TailCall(_) => return Err(CodegenError::Unsupported("calls")),
+
+            Op::Index => return Err(CodegenError::Unsupported("indexing")),
+        }
+
+        Ok(())
+    }
+
+    fn compile_terminator(&mut self, terminator: Terminator, next: Option<BlockId>) {
+        match terminator {
+            Terminator::Jump(target) => {
+                self.materialize();
+
+                if Some(target) != next {
+                    self.emit_jump(Opcode::Jump, Vec::new(), target);
+                }
+            },
+
+            Terminator::Branch { then, otherwise } => {
+                let cond = self.stack.pop().unwrap();
+                self.materialize();
+
+                self.emit_jump(Opcode::JumpIfFalse, vec![cond as i64], otherwise);
+                if Some(then) != next {
+                    self.emit_jump(Opcode::Jump, Vec::new(), then);
+                }
+            },
+
+            Terminator::Return => match self.stack.last() {
+                Some(&src) => self.emit(Opcode::ReturnValue, vec![src as i64]),
+                None => self.emit(Opcode::Return, Vec::new()),
+            },
+        }
+    }
+
+    /// Stores the value on top of the stack into the register of a global,
+    /// leaving the global on top of the stack.
+    fn store(&mut self, register: usize) {
+        let top = self.stack.len() - 1;
+
+        // values still referring to the old contents get their own copy
+        for depth in 0..top {
+            if self.stack[depth] == register {
+                let temporary = self.temporary(depth);
+                self.emit(Opcode::Move, vec![temporary, register as i64]);
+                self.stack[depth] = temporary as usize;
+            }
+        }
+
+        let src = self.stack[top];
+        if src != register {
+            match self.last_result {
+                Some(node) if src == self.base + top => self.nodes[node].operands[0] = register as i64,
+                _ => self.emit(Opcode::Move, vec![register as i64, src as i64]),
+            }
+        }
+
+        self.stack[top] = register;
+        self.last_result = None;
+    }
+
+    /// Moves every value on the stack into the register for its depth, which
+    /// is where the next block expects them.
+    fn materialize(&mut self) {
+        for depth in 0..self.stack.len() {
+            let temporary = self.temporary(depth);
+
+            if self.stack[depth] != temporary as usize {
+                self.emit(Opcode::Move, vec![temporary, self.stack[depth] as i64]);
+                self.stack[depth] = temporary as usize;
+            }
+        }
+    }
+
+    fn load_constant(&mut self, dst: i64, constant: Constant) {
+        let index = self.compiler.add_constant(constant);
+        self.emit_result(Opcode::Constant, vec![dst, index as i64]);
+    }
+
+    fn temporary(&self, depth: usize) -> i64 {
+        (self.base + depth) as i64
+    }
+
+    /// Emits an instruction computing a new value on top of the stack into
+    /// the register given by its first operand.
+    fn emit_result(&mut self, op: Opcode, operands: Vec<i64>) {
+        self.stack.push(operands[0] as usize);
+        self.emit(op, operands);
+        self.last_result = Some(self.nodes.len() - 1);
+    }
+
+    fn emit(&mut self, op: Opcode, operands: Vec<i64>) {
+        self.nodes.push(Node {
+            op,
+            operands,
+            target: None,
+        });
+        self.last_result = None;
+    }
+
+    fn emit_jump(&mut self, op: Opcode, mut operands: Vec<i64>, target: BlockId) {
+        operands.push(0);
+        self.nodes.push(Node {
+            op,
+            operands,
+            target: Some(target),
+        });
+    }
+}
+
+fn binary_opcode(op: BinaryOp) -> Opcode {
+    match op {
+        BinaryOp::Add => Opcode::Add,
+        BinaryOp::Sub => Opcode::Sub,
+        BinaryOp::Mul => Opcode::Mul,
+        BinaryOp::Div => Opcode::Div,
+        BinaryOp::Mod => Opcode::Mod,
+        BinaryOp::Equal => Opcode::Equal,
+        BinaryOp::NotEqual => Opcode::NotEqual,
+        BinaryOp::LessThan => Opcode::LessThan,
+        BinaryOp::LessThanEqual => Opcode::LessThanEqual,
+        BinaryOp::And => Opcode::And,
+        BinaryOp::Or => Opcode::Or,
+        BinaryOp::BitAnd => Opcode::BitAnd,
+        BinaryOp::BitOr => Opcode::BitOr,
+        BinaryOp::BitXor => Opcode::BitXor,
+        BinaryOp::ShiftLeft => Opcode::BitSl,
+        BinaryOp::ShiftRight => Opcode::BitSr,
+    }
+}
+
+/// Returns the stack depth at the start of every block, or `None` for blocks
+/// that can't be reached.
+fn entry_depths(function: &Function) -> Result<Vec<Option<usize>>, CodegenError> {
+    let mut depths = vec![None; function.blocks.len()];
+    depths[Function::ENTRY.0] = Some(0);
+
+    let mut worklist = vec![Function::ENTRY];
+    while let Some(id) = worklist.pop() {
+        let block = function.block(id);
+        let mut depth = depths[id.0].unwrap();
+
+        for op in &block.ops {
+            depth = match op {
+                Op::Push(_) | Op::Load(_) => depth + 1,
+                Op::Store(_) | Op::Unary(_) => depth,
+                Op::Pop | Op::Binary(_) | Op::Index => depth - 1,
+                Op::Call(_) | Op::TailCall(_) => return Err(CodegenError::Unsupported("calls")),
+            };
+        }
+
+        let successors = match block.terminator {
+            Terminator::Jump(target) => vec![(target, depth)],
+            Terminator::Branch { then, otherwise } => vec![(then, depth - 1), (otherwise, depth - 1)],
+            Terminator::Return => Vec::new(),
+        };
+
+        for (successor, depth) in successors {
+            if depths[successor.0].is_none() {
+                depths[successor.0] = Some(depth);
+                worklist.push(successor);
+            }
+        }
+    }
+
+    Ok(depths)
+}
+
+/// Encodes `nodes`, turning jump targets into relative offsets. `starts` is
+/// the index of the first node of every block.
+fn encode(nodes: &[Node], starts: &[usize]) -> Result<Vec<u8>, CodegenError> {
+    let mut offsets = Vec::with_capacity(nodes.len() + 1);
+    let mut offset = 0;
+    for node in nodes {
+        offsets.push(offset);
+        offset += node.op.info().size();
+    }
+    offsets.push(offset);
+
+    let mut instructions = Vec::with_capacity(offset);
+    for (i, node) in nodes.iter().enumerate() {
+        let mut operands = node.operands.clone();
+
+        if let Some(target) = node.target {
+            *operands.last_mut().unwrap() = offsets[starts[target.0]] as i64 - offsets[i + 1] as i64;
+        }
+
+        instructions.push(node.op.byte());
+
+        for (&value, &ty) in operands.iter().zip(node.op.operands()) {
+            if !ty.fits(value) {
+                return Err(EncodeError::OperandOutOfRange {
+                    name: node.op.name(),
+                    ty,
+                    value,
+                }
+                .into());
+            }
+
+            // range checked above, so the casts are lossless
+            match ty {
+                OperandType::U8 => instructions.push(value as u8),
+                OperandType::U16 => instructions.extend((value as u16).to_be_bytes()),
+                OperandType::I16 => instructions.extend((value as i16).to_be_bytes()),
+            }
+        }
+    }
+
+    Ok(instructions)
+}
@@ -0,0 +1,100 @@
+use belc_codegen_vm::disassembler::disassemble;
+use belvm_bytecode::opcode;
+use belvm_bytecode::{Bytecode, Constant, Function};
+
+#[test]
+fn annotates_constants() {
+    let mut instructions = Vec::new();
+    instructions.extend(opcode::constant(0));
+    instructions.extend(opcode::constant(1));
+    instructions.extend(opcode::constant(2));
+
+    let code = Bytecode {
+        instructions,
+        constants: vec![Constant::Integer(12), Constant::String("hi".into())],
+    };
+
+    assert_eq!(
+        disassemble(&code),
+        "0x0000: CONSTANT 000 ; 12\n\
+         0x0003: CONSTANT 001 ; \"hi\"\n\
+         0x0006: CONSTANT 002 ; <invalid constant>\n"
+    );
+}
+
+#[test]
+fn resolves_jump_targets() {
+    let mut instructions = Vec::new();
+    instructions.push(opcode::TRUE);
+    instructions.extend(opcode::jump_if_false(4));
+    instructions.extend(opcode::jump(-7));
+    instructions.push(opcode::NOOP);
+    instructions.extend(opcode::jump(100));
+
+    let code = Bytecode {
+        instructions,
+        constants: Vec::new(),
+    };
+
+    assert_eq!(
+        disassemble(&code),
+        "L0:\n\
+         0x0000: TRUE\n\
+         0x0001: JUMP_IF_FALSE L1 (+4)\n\
+         0x0004: JUMP L0 (-7)\n\
+         0x0007: NOOP\n\
+         L1:\n\
+         0x0008: JUMP <invalid target> (+100)\n"
+    );
+}
+
+#[test]
+fn flags_invalid_bytes() {
+    let mut instructions = vec![opcode::TRUE, 0xFF, opcode::POP];
+    instructions.extend(opcode::make_array(2));
+    instructions.push(opcode::CONSTANT);
+
+    let code = Bytecode {
+        instructions,
+        constants: Vec::new(),
+    };
+
+    assert_eq!(
+        disassemble(&code),
+        "0x0000: TRUE\n\
+         0x0001: <invalid opcode 0xff>\n\
+         0x0002: POP\n\
+         0x0003: MAKE_ARRAY 002\n\
+         0x0005: <truncated CONSTANT>\n"
+    );
+}
+
+#[test]
+fn function_bodies() {
+    let mut instructions = Vec::new();
+    instructions.extend(opcode::constant(0));
+    instructions.extend(opcode::call(1));
+    instructions.push(opcode::RETURN_VALUE);
+    instructions.extend(opcode::get_local(0));
+    instructions.push(opcode::RETURN_VALUE);
+
+    let code = Bytecode {
+        instructions,
+        constants: vec![Constant::Function(Function {
+            pointer: 6,
+            locals_count: 1,
+            arity: 1,
+        })],
+    };
+
+    assert_eq!(
+        disassemble(&code),
+        "0x0000: CONSTANT 000 ; <fn#0 arity=1>\n\
+         0x0003: CALL 001\n\
+         0x0005: RETURN_VALUE\n\
+         \n\
+         fn#0 (arity 1, locals 1):\n\
+         0x0006: GET_LOCAL 000\n\
+         0x0008: RETURN_VALUE\n"
+    );
+}
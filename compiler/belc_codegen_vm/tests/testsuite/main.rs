@@ -1,10 +1,14 @@
+mod disassembler;
+mod peephole;
+mod register;
+
 use std::error::Error;
 
 use belc_ast::Parser;
 use belc_codegen_vm::Compiler;
 use belc_lexer::Lexer;
 use belvm_bytecode::opcode;
-use belvm_bytecode::{Bytecode, Constant};
+use belvm_bytecode::{Bytecode, Constant, Function};
 
 fn test_compile(input: &str) -> Result<Bytecode, Box<dyn Error>> {
     let source = input.to_owned();
@@ -115,26 +119,21 @@ fn if_expressions() {
     #[rustfmt::skip]
     assert_eq!(code.instructions, vec![
         opcode::CONSTANT, 0, 0,
-        opcode::CONSTANT, 0, 1,
+        opcode::CONSTANT, 0, 0,
         opcode::EQUAL,
         opcode::JUMP_IF_FALSE, 0, 6,
-        opcode::CONSTANT, 0, 2,
+        opcode::CONSTANT, 0, 1,
         opcode::JUMP, 0, 1,
         opcode::NULL,
         opcode::POP,
-        opcode::CONSTANT, 0, 3,
+        opcode::CONSTANT, 0, 2,
         opcode::POP,
         opcode::RETURN_VALUE,
     ]);
 
     assert_eq!(
         code.constants,
-        vec![
-            Constant::Integer(1),
-            Constant::Integer(1),
-            Constant::Integer(10),
-            Constant::Integer(9),
-        ]
+        vec![Constant::Integer(1), Constant::Integer(10), Constant::Integer(9),]
     );
 }
 
@@ -218,14 +217,14 @@ fn var_assignment_ops() {
         opcode::SET_GLOBAL, 0, 1,
         opcode::POP,
         opcode::GET_GLOBAL, 0, 1,
-        opcode::CONSTANT, 0, 1,
+        opcode::CONSTANT, 0, 0,
         opcode::ADD,
         opcode::SET_GLOBAL, 0, 1,
         opcode::POP,
         opcode::RETURN_VALUE,
     ]);
 
-    assert_eq!(code.constants, vec![Constant::Integer(1), Constant::Integer(1),]);
+    assert_eq!(code.constants, vec![Constant::Integer(1),]);
 }
 
 #[test]
@@ -243,62 +242,152 @@ fn block_expression() {
     assert_eq!(code.constants, vec![Constant::Integer(12),]);
 }
 
-// #[test]
-// fn function_expressions() {
-//     let code = test_compile("ten := fn() { 10 };").unwrap();
-//
-//     assert_eq!(code.instructions, vec![
-//         opcode::CONSTANT, 0, 1,
-//         opcode::SET_GLOBAL, 0, 1,
-//         opcode::POP,
-//         opcode::RETURN_VALUE,
-//
-//         // ten function instructions
-//         opcode::CONSTANT, 0, 0,
-//         opcode::RETURN_VALUE,
-//     ]);
-//
-//     assert_eq!(code.constants, vec![
-//         Constant::Integer(10),
-//         Constant::Function(Function {
-//             pointer: 8,
-//             locals_count: 0,
-//             arity: 0
-//         })
-//     ]);
-// }
-//
-// #[test]
-// fn function_with_args_expressions() {
-//     let code = test_compile("add := fn(a, b) { a + b }; three := add(1,
-// 2);").unwrap();
-//
-//     assert_eq!(code.instructions, vec![
-//         opcode::CONSTANT, 0, 0,
-//         opcode::SET_GLOBAL, 0, 1,
-//         opcode::POP,
-//         opcode::CONSTANT, 0, 1,
-//         opcode::CONSTANT, 0, 2,
-//         opcode::GET_GLOBAL, 0, 1,
-//         opcode::CALL,
-//         opcode::SET_GLOBAL, 0, 2,
-//         opcode::POP,
-//         opcode::RETURN_VALUE,
-//
-//         // add function instructions
-//         opcode::GET_LOCAL, 0,
-//         opcode::GET_LOCAL, 1,
-//         opcode::ADD,
-//         opcode::RETURN_VALUE,
-//     ]);
-//
-//     assert_eq!(code.constants, vec![
-//         Constant::Function(Function {
-//             pointer: 22,
-//             locals_count: 2,
-//             arity: 2,
-//         }),
-//         Constant::Integer(2),
-//         Constant::Integer(1),
-//     ]);
-// }
+#[test]
+fn float_and_string_literals() {
+    let code = test_compile("1.5; \"hi\";").unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        opcode::CONSTANT, 0, 0,
+        opcode::POP,
+        opcode::CONSTANT, 0, 1,
+        opcode::POP,
+        opcode::RETURN_VALUE,
+    ]);
+
+    assert_eq!(
+        code.constants,
+        vec![Constant::Float(1.5), Constant::String("hi".into()),]
+    );
+}
+
+#[test]
+fn repeated_literals_are_interned() {
+    let input = format!("i := 0; {}", "i = i + 1; \"hello\"; 2.5; 0;".repeat(5000));
+    let code = test_compile(&input).unwrap();
+
+    assert_eq!(
+        code.constants,
+        vec![
+            Constant::Integer(0),
+            Constant::Integer(1),
+            Constant::String("hello".into()),
+            Constant::Float(2.5),
+        ]
+    );
+}
+
+#[test]
+fn wide_constant_index() {
+    let input = (0..65537).map(|i| format!("{i};")).collect::<String>();
+    let code = test_compile(&input).unwrap();
+
+    let tail = &code.instructions[code.instructions.len() - 8..];
+
+    #[rustfmt::skip]
+    assert_eq!(tail, &[
+        opcode::WIDE, opcode::CONSTANT, 0, 1, 0, 0,
+        opcode::POP,
+        opcode::RETURN_VALUE,
+    ]);
+}
+
+#[test]
+fn wide_jumps() {
+    // 4 bytes per statement, well past what a 16-bit offset can reach
+    let body = "1;".repeat(10000);
+    let input = format!("if (true) {{ {body} }};");
+    let code = test_compile(&input).unwrap();
+
+    // CONSTANT + POP for every statement but the last, whose value is kept
+    let body_size = 10000 * 4 - 1;
+
+    assert_eq!(
+        &code.instructions[..3],
+        &[opcode::TRUE, opcode::WIDE, opcode::JUMP_IF_FALSE]
+    );
+
+    let offset = i32::from_be_bytes(code.instructions[3..7].try_into().unwrap());
+    assert_eq!(offset as usize, body_size + 3);
+}
+
+#[test]
+fn function_expressions() {
+    let code = test_compile("ten := fn() { 10 };").unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        opcode::CONSTANT, 0, 0,
+        opcode::SET_GLOBAL, 0, 1,
+        opcode::POP,
+        opcode::RETURN_VALUE,
+
+        // ten function instructions
+        opcode::CONSTANT, 0, 1,
+        opcode::RETURN_VALUE,
+    ]);
+
+    assert_eq!(
+        code.constants,
+        vec![
+            Constant::Function(Function {
+                pointer: 8,
+                locals_count: 0,
+                arity: 0
+            }),
+            Constant::Integer(10),
+        ]
+    );
+}
+
+#[test]
+fn function_with_args_expressions() {
+    let code = test_compile("add := fn(a, b) { a + b }; three := add(1, 2);").unwrap();
+
+    // arguments are pushed in order, so they end up in the first locals
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        opcode::CONSTANT, 0, 0,
+        opcode::SET_GLOBAL, 0, 1,
+        opcode::POP,
+        opcode::CONSTANT, 0, 1,
+        opcode::CONSTANT, 0, 2,
+        opcode::GET_GLOBAL, 0, 1,
+        opcode::CALL, 2,
+        opcode::SET_GLOBAL, 0, 2,
+        opcode::POP,
+        opcode::RETURN_VALUE,
+
+        // add function instructions
+        opcode::GET_LOCAL, 0,
+        opcode::GET_LOCAL, 1,
+        opcode::ADD,
+        opcode::RETURN_VALUE,
+    ]);
+
+    assert_eq!(
+        code.constants,
+        vec![
+            Constant::Function(Function {
+                pointer: 23,
+                locals_count: 2,
+                arity: 2,
+            }),
+            Constant::Integer(1),
+            Constant::Integer(2),
+        ]
+    );
+}
+
+#[test]
+fn tail_calls() {
+    let code = test_compile("f := fn(n) { f(n) };").unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(&code.instructions[8..], &[
+        opcode::GET_LOCAL, 0,
+        opcode::GET_GLOBAL, 0, 1,
+        opcode::TAIL_CALL, 1,
+        opcode::RETURN_VALUE,
+    ]);
+}
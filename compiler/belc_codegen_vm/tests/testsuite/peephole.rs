@@ -0,0 +1,188 @@
+use belc_codegen_vm::peephole::optimize;
+use belvm_bytecode::opcode::{self, Opcode};
+use belvm_bytecode::{Bytecode, Constant, Function, Instruction};
+
+use crate::test_compile;
+
+fn optimized(input: &str) -> Bytecode {
+    let mut code = test_compile(input).unwrap();
+    optimize(&mut code).unwrap();
+    code
+}
+
+#[test]
+fn removes_dead_pushes() {
+    let code = optimized("1; true; x := 2; x;");
+
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        opcode::CONSTANT, 0, 1,
+        opcode::SET_GLOBAL, 0, 1,
+        opcode::POP,
+        opcode::RETURN_VALUE,
+    ]);
+}
+
+#[test]
+fn keeps_stored_value_on_stack() {
+    let code = optimized("x := 2; x + 1;");
+
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        opcode::CONSTANT, 0, 0,
+        opcode::SET_GLOBAL, 0, 1,
+        opcode::CONSTANT, 0, 1,
+        opcode::ADD,
+        opcode::POP,
+        opcode::RETURN_VALUE,
+    ]);
+}
+
+#[test]
+fn repatches_loops() {
+    let code = optimized("i := 0; while (i < 3) { 5; i = i + 1; };");
+
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        opcode::CONSTANT, 0, 0,
+        opcode::SET_GLOBAL, 0, 1,
+        opcode::POP,
+        // loop condition
+        opcode::GET_GLOBAL, 0, 1,
+        opcode::CONSTANT, 0, 1,
+        opcode::LESS_THAN,
+        opcode::JUMP_IF_FALSE, 0, 14,
+        // `i = i + 1;`, with the `5;` gone
+        opcode::GET_GLOBAL, 0, 1,
+        opcode::CONSTANT, 0, 3,
+        opcode::ADD,
+        opcode::SET_GLOBAL, 0, 1,
+        opcode::POP,
+        opcode::JUMP, 0xFF, 0xE8,
+        opcode::RETURN_VALUE,
+    ]);
+}
+
+#[test]
+fn threads_jumps() {
+    let mut instructions = Vec::new();
+    instructions.push(opcode::TRUE);
+    instructions.extend(opcode::jump_if_false(3));
+    instructions.extend(opcode::jump(1));
+    instructions.push(opcode::NOOP);
+    instructions.extend(opcode::jump(1));
+    instructions.push(opcode::TRUE);
+    instructions.push(opcode::FALSE);
+
+    let mut code = Bytecode {
+        instructions,
+        constants: Vec::new(),
+    };
+
+    optimize(&mut code).unwrap();
+
+    // both jumps end up at the FALSE the second JUMP was going to
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        opcode::TRUE,
+        opcode::JUMP_IF_FALSE, 0, 7,
+        opcode::JUMP, 0, 4,
+        opcode::JUMP, 0, 1,
+        opcode::TRUE,
+        opcode::FALSE,
+    ]);
+}
+
+#[test]
+fn pops_only_boolean_conditions() {
+    let mut instructions = Vec::new();
+    instructions.push(opcode::TRUE);
+    instructions.extend(opcode::jump_if_false(0));
+    instructions.extend(opcode::get_global(0));
+    instructions.extend(opcode::jump_if_false(0));
+    instructions.push(opcode::FALSE);
+
+    let mut code = Bytecode {
+        instructions,
+        constants: Vec::new(),
+    };
+
+    optimize(&mut code).unwrap();
+
+    // TRUE is left to be popped, and so dropped, but the global could be of
+    // any type, so the jump still has to check it
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        opcode::GET_GLOBAL, 0, 0,
+        opcode::JUMP_IF_FALSE, 0, 0,
+        opcode::FALSE,
+    ]);
+}
+
+#[test]
+fn shrinks_wide_jumps() {
+    let mut instructions = Vec::new();
+    instructions.push(opcode::TRUE);
+    Instruction {
+        wide: true,
+        ..Instruction::new(Opcode::JumpIfFalse, &[1])
+    }
+    .encode(&mut instructions)
+    .unwrap();
+    instructions.push(opcode::TRUE);
+    instructions.push(opcode::FALSE);
+
+    let mut code = Bytecode {
+        instructions,
+        constants: Vec::new(),
+    };
+
+    optimize(&mut code).unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        opcode::TRUE,
+        opcode::JUMP_IF_FALSE, 0, 1,
+        opcode::TRUE,
+        opcode::FALSE,
+    ]);
+}
+
+#[test]
+fn moves_function_pointers() {
+    let mut instructions = Vec::new();
+    instructions.extend(opcode::constant(0));
+    instructions.push(opcode::NOOP);
+    instructions.push(opcode::RETURN_VALUE);
+    instructions.push(opcode::NOOP);
+    instructions.extend(opcode::get_local(0));
+    instructions.push(opcode::RETURN_VALUE);
+
+    let mut code = Bytecode {
+        instructions,
+        constants: vec![Constant::Function(Function {
+            pointer: 5,
+            locals_count: 1,
+            arity: 1,
+        })],
+    };
+
+    optimize(&mut code).unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        opcode::CONSTANT, 0, 0,
+        opcode::RETURN_VALUE,
+        opcode::GET_LOCAL, 0,
+        opcode::RETURN_VALUE,
+    ]);
+
+    assert_eq!(
+        code.constants,
+        vec![Constant::Function(Function {
+            pointer: 4,
+            locals_count: 1,
+            arity: 1,
+        })]
+    );
+}
@@ -0,0 +1,67 @@
+use belc_ast::Parser;
+use belc_codegen_vm::{Compiler, Target};
+use belc_lexer::Lexer;
+use belvm_bytecode::Bytecode;
+use belvm_bytecode::register;
+
+fn compile_register(input: &str) -> Bytecode {
+    let source = input.to_owned();
+    let lexer = Lexer::new(&source);
+    let mut parser = Parser::new(lexer);
+    let program = parser.parse_program().unwrap();
+
+    let mut compiler = Compiler::default();
+    compiler.target = Target::Register;
+
+    compiler.compile_program(program).unwrap()
+}
+
+#[test]
+fn stores_into_variable_registers() {
+    let code = compile_register("i := 0; i = i + 1;");
+
+    // `print` is symbol 0, so `i` lives in register 1 and temporaries start
+    // at register 2
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        register::CONSTANT, 1, 0, 0,
+        register::CONSTANT, 3, 0, 1,
+        register::ADD, 1, 1, 3,
+        register::RETURN,
+    ]);
+}
+
+#[test]
+fn copies_before_overwriting() {
+    // the old value of `a` is still needed after it is reassigned
+    let code = compile_register("a := 1; b := a + (a = 2);");
+
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        register::CONSTANT, 1, 0, 0,
+        register::CONSTANT, 4, 0, 1,
+        register::MOVE, 3, 1,
+        register::MOVE, 1, 4,
+        register::ADD, 2, 3, 1,
+        register::RETURN,
+    ]);
+}
+
+#[test]
+fn loops() {
+    let code = compile_register("i := 0; while (i < 3) { i += 1; };");
+
+    #[rustfmt::skip]
+    assert_eq!(code.instructions, vec![
+        register::CONSTANT, 1, 0, 0,
+        // condition
+        register::CONSTANT, 3, 0, 1,
+        register::LESS_THAN, 2, 1, 3,
+        register::JUMP_IF_FALSE, 2, 0, 11,
+        // body
+        register::CONSTANT, 3, 0, 2,
+        register::ADD, 1, 1, 3,
+        register::JUMP, 0xFF, 0xE9,
+        register::RETURN,
+    ]);
+}
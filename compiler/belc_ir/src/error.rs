@@ -0,0 +1,14 @@
+#[derive(thiserror::Error, Debug)]
+pub enum LowerError {
+    #[error("duplicate symbol: {0}")]
+    DuplicateSymbol(String),
+
+    #[error("unknown symbol: {0}")]
+    UnknownSymbol(String),
+
+    #[error("cannot capture {0} from an enclosing function, closures are not supported")]
+    CapturedVariable(String),
+
+    #[error("{callee} takes {arity} arguments but is called with {argc}")]
+    ArityMismatch { callee: String, arity: usize, argc: usize },
+}
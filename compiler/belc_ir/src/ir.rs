@@ -0,0 +1,303 @@
+//! The control-flow graph representation.
+//!
+//! A [`Function`] is a list of [`BasicBlock`]s. Each block is a straight run of
+//! [`Op`]s working on the operand stack, ended by a single [`Terminator`] that
+//! says where control goes next. Values left on the stack by a block are seen
+//! by its successors, the same way the VM runs them.
+
+use std::fmt;
+
+use crate::scope::ScopeLevel;
+
+/// Index of a [`BasicBlock`] in its [`Function`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockId(pub usize);
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+/// A literal value pushed onto the stack
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+
+    /// A function literal, with the body lowered into its own control-flow
+    /// graph
+    Function(Box<Function>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Integer(int) => write!(f, "{int}"),
+            Value::Float(float) => write!(f, "{float:?}"),
+            Value::Boolean(boolean) => write!(f, "{boolean}"),
+            Value::String(string) => write!(f, "{string:?}"),
+            Value::Function(function) => write!(f, "fn(arity {}, locals {})", function.arity, function.locals_count),
+        }
+    }
+}
+
+/// A resolved variable slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Variable {
+    pub scope: ScopeLevel,
+    pub index: usize,
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scope = match self.scope {
+            ScopeLevel::Builtin => "builtin",
+            ScopeLevel::Global => "global",
+            ScopeLevel::Local => "local",
+        };
+
+        write!(f, "{scope} {}", self.index)
+    }
+}
+
+/// Operations on the top two values of the stack, TOS-1 being the left
+/// operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanEqual,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::Div => "div",
+            BinaryOp::Mod => "mod",
+            BinaryOp::Equal => "eq",
+            BinaryOp::NotEqual => "ne",
+            BinaryOp::LessThan => "lt",
+            BinaryOp::LessThanEqual => "le",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+            BinaryOp::BitAnd => "bit_and",
+            BinaryOp::BitOr => "bit_or",
+            BinaryOp::BitXor => "bit_xor",
+            BinaryOp::ShiftLeft => "shl",
+            BinaryOp::ShiftRight => "shr",
+        })
+    }
+}
+
+/// Operations on the top value of the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnaryOp::Not => "not",
+            UnaryOp::Neg => "neg",
+        })
+    }
+}
+
+/// A single operation inside a [`BasicBlock`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    /// Pushes a literal value
+    Push(Value),
+
+    /// Discards TOS
+    Pop,
+
+    /// Pushes the value of a variable
+    Load(Variable),
+
+    /// Stores TOS into a variable, leaving it on the stack
+    Store(Variable),
+
+    Binary(BinaryOp),
+
+    Unary(UnaryOp),
+
+    /// Calls the function on TOS with the given number of arguments below
+    /// it, the first argument being the deepest
+    Call(usize),
+
+    /// A [`Op::Call`] whose result is returned right away, so the callee can
+    /// take over the frame of the caller
+    TailCall(usize),
+
+    /// Indexes TOS-1 with TOS
+    Index,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Push(value) => write!(f, "push {value}"),
+            Op::Pop => write!(f, "pop"),
+            Op::Load(variable) => write!(f, "load {variable}"),
+            Op::Store(variable) => write!(f, "store {variable}"),
+            Op::Binary(op) => write!(f, "{op}"),
+            Op::Unary(op) => write!(f, "{op}"),
+            Op::Call(argc) => write!(f, "call {argc}"),
+            Op::TailCall(argc) => write!(f, "tail_call {argc}"),
+            Op::Index => write!(f, "index"),
+        }
+    }
+}
+
+/// How control leaves a [`BasicBlock`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminator {
+    /// Continues at another block
+    Jump(BlockId),
+
+    /// Pops TOS and continues at `then` if it is truthy, or at `otherwise`
+    Branch { then: BlockId, otherwise: BlockId },
+
+    /// Returns TOS from the function
+    Return,
+}
+
+impl Terminator {
+    /// Blocks control can continue at
+    pub fn successors(&self) -> Vec<BlockId> {
+        match *self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch { then, otherwise } => vec![then, otherwise],
+            Terminator::Return => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jump {target}"),
+            Terminator::Branch { then, otherwise } => write!(f, "branch {then}, {otherwise}"),
+            Terminator::Return => write!(f, "return"),
+        }
+    }
+}
+
+/// A straight run of operations with a single entry and a single exit
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub ops: Vec<Op>,
+    pub terminator: Terminator,
+}
+
+/// A control-flow graph of [`BasicBlock`]s
+///
+/// Execution starts at [`Function::ENTRY`]. Blocks are kept in source order,
+/// which is also a good order to lay them out in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Function {
+    pub blocks: Vec<BasicBlock>,
+
+    /// Number of parameters the function takes
+    pub arity: usize,
+
+    /// Number of local slots the function needs, including its parameters
+    pub locals_count: usize,
+}
+
+impl Function {
+    /// The block execution starts at
+    pub const ENTRY: BlockId = BlockId(0);
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id.0]
+    }
+
+    /// Returns the ids of every block, in order.
+    pub fn block_ids(&self) -> impl Iterator<Item = BlockId> + use<> {
+        (0..self.blocks.len()).map(BlockId)
+    }
+
+    /// Returns the predecessors of every block, indexed by block id.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+
+        for id in self.block_ids() {
+            for successor in self.block(id).terminator.successors() {
+                predecessors[successor.0].push(id);
+            }
+        }
+
+        predecessors
+    }
+}
+
+impl Function {
+    /// Writes the blocks indented by `indent` spaces. Function literals are
+    /// written in place, indented further.
+    fn write_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        let pad = " ".repeat(indent);
+        let predecessors = self.predecessors();
+
+        for id in self.block_ids() {
+            let block = self.block(id);
+
+            write!(f, "{pad}{id}:")?;
+            if !predecessors[id.0].is_empty() {
+                let preds = predecessors[id.0]
+                    .iter()
+                    .map(|pred| pred.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                write!(f, " ; preds: {preds}")?;
+            }
+            writeln!(f)?;
+
+            for op in &block.ops {
+                match op {
+                    Op::Push(Value::Function(function)) => {
+                        writeln!(f, "{pad}    {op} {{")?;
+                        function.write_indented(f, indent + 8)?;
+                        writeln!(f, "{pad}    }}")?;
+                    },
+                    op => writeln!(f, "{pad}    {op}")?,
+                }
+            }
+
+            writeln!(f, "{pad}    {}", block.terminator)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
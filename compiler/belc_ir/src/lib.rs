@@ -0,0 +1,15 @@
+//! Mid-level intermediate representation of Belalang programs.
+//!
+//! The AST is lowered into a control-flow graph of basic blocks (see
+//! [`Function`]) with every name resolved to a [`Variable`] slot. Passes that
+//! care about control flow work on this graph, and backends only have to turn
+//! each block into their own instructions instead of reasoning about jumps.
+
+mod error;
+mod ir;
+mod lower;
+pub mod scope;
+
+pub use error::LowerError;
+pub use ir::{BasicBlock, BinaryOp, BlockId, Function, Op, Terminator, UnaryOp, Value, Variable};
+pub use lower::Lowerer;
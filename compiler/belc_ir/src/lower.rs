@@ -0,0 +1,512 @@
+use std::collections::{HashMap, HashSet};
+
+use belc_ast::{BlockExpression, Expression, FunctionLiteral, Program, Statement};
+use belc_lexer::{AssignmentKind, InfixKind, PrefixKind};
+
+use crate::error::LowerError;
+use crate::ir::{BasicBlock, BinaryOp, BlockId, Function, Op, Terminator, UnaryOp, Value, Variable};
+use crate::scope::{ScopeLevel, ScopeManager};
+
+/// Lowers the AST into a [`Function`].
+///
+/// Symbols are kept between calls to [`Lowerer::lower_program`], so a
+/// program can be lowered piece by piece as in a REPL.
+#[derive(Default)]
+pub struct Lowerer {
+    pub scope: ScopeManager,
+
+    blocks: Vec<BasicBlock>,
+    current: BlockId,
+
+    /// Blocks in the order they were first switched to
+    order: Vec<BlockId>,
+
+    /// Arities of the function literals variables were defined with
+    functions: HashMap<Variable, usize>,
+
+    /// Variables assigned to after their definition, which may hold any
+    /// function
+    reassigned: HashSet<Variable>,
+
+    /// Calls through variables in [`Self::functions`], checked once every
+    /// assignment that could change the callee was seen
+    calls: Vec<KnownCall>,
+}
+
+/// A call through a variable defined with a function literal
+struct KnownCall {
+    name: String,
+    variable: Variable,
+    argc: usize,
+}
+
+impl Lowerer {
+    pub fn lower_program(&mut self, program: Program) -> Result<Function, LowerError> {
+        self.blocks.clear();
+        self.order.clear();
+
+        let entry = self.new_block();
+        self.switch_to(entry);
+
+        let result = program
+            .statements
+            .into_iter()
+            .try_for_each(|statement| self.lower_statement(statement));
+
+        let blocks = std::mem::take(&mut self.blocks);
+        let order = std::mem::take(&mut self.order);
+        let calls = std::mem::take(&mut self.calls);
+        result?;
+
+        // only globals are left, locals were checked with their function
+        self.check_calls(calls)?;
+
+        Ok(renumber(blocks, &order))
+    }
+
+    pub fn lower_statement(&mut self, statement: Statement) -> Result<(), LowerError> {
+        match statement {
+            Statement::Expression(statement) => {
+                self.lower_expression(statement.expression)?;
+                self.push(Op::Pop);
+            },
+
+            Statement::Return(r#return) => {
+                self.lower_expression(r#return.return_value)?;
+                self.terminate(Terminator::Return);
+
+                // anything after the return still gets a block, even though
+                // nothing jumps to it
+                let next = self.new_block();
+                self.switch_to(next);
+            },
+
+            Statement::While(r#while) => {
+                let condition = self.new_block();
+                let body = self.new_block();
+                let exit = self.new_block();
+
+                self.terminate(Terminator::Jump(condition));
+
+                self.switch_to(condition);
+                self.lower_expression(*r#while.condition)?;
+                self.terminate(Terminator::Branch {
+                    then: body,
+                    otherwise: exit,
+                });
+
+                // the body is not a value, so every statement in it is popped
+                self.switch_to(body);
+                for statement in r#while.block.statements {
+                    self.lower_statement(statement)?;
+                }
+                self.terminate(Terminator::Jump(condition));
+
+                self.switch_to(exit);
+            },
+        };
+
+        Ok(())
+    }
+
+    pub fn lower_expression(&mut self, expression: Expression) -> Result<(), LowerError> {
+        match expression {
+            Expression::Boolean(boolean) => self.push(Op::Push(Value::Boolean(boolean.value))),
+
+            Expression::Integer(integer) => self.push(Op::Push(Value::Integer(integer.value))),
+
+            Expression::Float(float) => self.push(Op::Push(Value::Float(float.value))),
+
+            Expression::String(string) => self.push(Op::Push(Value::String(string.value))),
+
+            Expression::Null(_) => self.push(Op::Push(Value::Null)),
+
+            Expression::Array(_array) => {
+                // arrays are not supported by the VM yet
+            },
+
+            Expression::Var(var) => match var.kind {
+                AssignmentKind::ColonAssign => {
+                    let variable = self.define(var.name.value)?;
+
+                    if let Expression::Function(function) = &*var.value {
+                        self.functions.insert(variable, function.params.len());
+                    }
+
+                    self.lower_expression(*var.value)?;
+                    self.store(variable);
+                },
+                _ => {
+                    let variable = self.resolve(var.name.value)?;
+                    self.reassigned.insert(variable);
+
+                    let op = match var.kind {
+                        AssignmentKind::Assign => None,
+                        AssignmentKind::AddAssign => Some(BinaryOp::Add),
+                        AssignmentKind::SubAssign => Some(BinaryOp::Sub),
+                        AssignmentKind::MulAssign => Some(BinaryOp::Mul),
+                        AssignmentKind::DivAssign => Some(BinaryOp::Div),
+                        AssignmentKind::ModAssign => Some(BinaryOp::Mod),
+                        AssignmentKind::BitAndAssign => Some(BinaryOp::BitAnd),
+                        AssignmentKind::BitOrAssign => Some(BinaryOp::BitOr),
+                        AssignmentKind::BitXorAssign => Some(BinaryOp::BitXor),
+                        AssignmentKind::ShiftLeftAssign => Some(BinaryOp::ShiftLeft),
+                        AssignmentKind::ShiftRightAssign => Some(BinaryOp::ShiftRight),
+                        AssignmentKind::ColonAssign => unreachable!(),
+                    };
+
+                    match op {
+                        Some(op) => {
+                            self.push(Op::Load(variable));
+                            self.lower_expression(*var.value)?;
+                            self.push(Op::Binary(op));
+                        },
+                        None => self.lower_expression(*var.value)?,
+                    }
+
+                    self.store(variable);
+                },
+            },
+
+            Expression::Call(call) => {
+                let argc = call.args.len();
+
+                match &*call.function {
+                    Expression::Function(function) if function.params.len() != argc => {
+                        return Err(LowerError::ArityMismatch {
+                            callee: "function literal".into(),
+                            arity: function.params.len(),
+                            argc,
+                        });
+                    },
+                    Expression::Identifier(ident) => {
+                        let variable = self.resolve(ident.value.clone())?;
+
+                        if self.functions.contains_key(&variable) {
+                            self.calls.push(KnownCall {
+                                name: ident.value.clone(),
+                                variable,
+                                argc,
+                            });
+                        }
+                    },
+                    _ => {},
+                }
+
+                for arg in call.args {
+                    self.lower_expression(arg)?;
+                }
+
+                self.lower_expression(*call.function)?;
+                self.push(Op::Call(argc));
+            },
+
+            Expression::Index(index) => {
+                self.lower_expression(*index.left)?;
+                self.lower_expression(*index.index)?;
+                self.push(Op::Index);
+            },
+
+            Expression::Function(function) => {
+                let function = self.lower_function(function)?;
+                self.push(Op::Push(Value::Function(Box::new(function))));
+            },
+
+            Expression::Identifier(ident) => {
+                let variable = self.resolve(ident.value)?;
+                self.push(Op::Load(variable));
+            },
+
+            Expression::If(r#if) => {
+                self.lower_expression(*r#if.condition)?;
+
+                let then = self.new_block();
+                let otherwise = self.new_block();
+                let merge = self.new_block();
+
+                self.terminate(Terminator::Branch { then, otherwise });
+
+                self.switch_to(then);
+                self.lower_block(r#if.consequence)?;
+                self.terminate(Terminator::Jump(merge));
+
+                self.switch_to(otherwise);
+                match r#if.alternative.map(|alternative| *alternative) {
+                    None => self.push(Op::Push(Value::Null)),
+                    Some(Expression::Block(block)) => self.lower_block(block)?,
+                    Some(alternative) => self.lower_expression(alternative)?,
+                }
+                self.terminate(Terminator::Jump(merge));
+
+                self.switch_to(merge);
+            },
+
+            Expression::Infix(infix) => {
+                // there are no greater-than ops, so the operands are swapped
+                // and the less-than ops are used instead
+                match infix.operator {
+                    InfixKind::Gt | InfixKind::Ge => {
+                        self.lower_expression(*infix.right)?;
+                        self.lower_expression(*infix.left)?;
+                    },
+                    _ => {
+                        self.lower_expression(*infix.left)?;
+                        self.lower_expression(*infix.right)?;
+                    },
+                }
+
+                self.push(Op::Binary(match infix.operator {
+                    InfixKind::Add => BinaryOp::Add,
+                    InfixKind::Sub => BinaryOp::Sub,
+                    InfixKind::Mul => BinaryOp::Mul,
+                    InfixKind::Div => BinaryOp::Div,
+                    InfixKind::Mod => BinaryOp::Mod,
+                    InfixKind::Eq => BinaryOp::Equal,
+                    InfixKind::Ne => BinaryOp::NotEqual,
+                    InfixKind::And => BinaryOp::And,
+                    InfixKind::Or => BinaryOp::Or,
+                    InfixKind::BitAnd => BinaryOp::BitAnd,
+                    InfixKind::BitOr => BinaryOp::BitOr,
+                    InfixKind::BitXor => BinaryOp::BitXor,
+                    InfixKind::ShiftLeft => BinaryOp::ShiftLeft,
+                    InfixKind::ShiftRight => BinaryOp::ShiftRight,
+                    InfixKind::Lt | InfixKind::Gt => BinaryOp::LessThan,
+                    InfixKind::Le | InfixKind::Ge => BinaryOp::LessThanEqual,
+                }));
+            },
+
+            Expression::Prefix(prefix) => {
+                self.lower_expression(*prefix.right)?;
+                self.push(Op::Unary(match prefix.operator {
+                    PrefixKind::Sub => UnaryOp::Neg,
+                    PrefixKind::Not => UnaryOp::Not,
+                }));
+            },
+
+            Expression::Block(block) => {
+                self.lower_block(block)?;
+            },
+        };
+
+        Ok(())
+    }
+
+    /// Lowers a block used as a value, which is the value of its last
+    /// statement.
+    fn lower_block(&mut self, block: BlockExpression) -> Result<(), LowerError> {
+        for statement in block.statements {
+            self.lower_statement(statement)?;
+        }
+
+        let ops = &mut self.blocks[self.current.0].ops;
+        if ops.last() == Some(&Op::Pop) {
+            ops.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Lowers a function literal into a [`Function`] of its own, with its
+    /// parameters as the first locals.
+    fn lower_function(&mut self, function: FunctionLiteral) -> Result<Function, LowerError> {
+        // the body gets its own blocks, the enclosing ones are put back after
+        let blocks = std::mem::take(&mut self.blocks);
+        let order = std::mem::take(&mut self.order);
+        let current = self.current;
+
+        // so are the locals of the enclosing function, which the body can't
+        // see
+        let is_local = |variable: &Variable| variable.scope == ScopeLevel::Local;
+        let functions: Vec<_> = self.functions.extract_if(|variable, _| is_local(variable)).collect();
+        let reassigned: Vec<_> = self.reassigned.extract_if(is_local).collect();
+        let calls: Vec<_> = self.calls.extract_if(.., |call| is_local(&call.variable)).collect();
+
+        self.scope.enter();
+        let arity = function.params.len();
+        let result = self.lower_function_body(function);
+        let scope = self.scope.leave();
+
+        let body = std::mem::replace(&mut self.blocks, blocks);
+        let body_order = std::mem::replace(&mut self.order, order);
+        self.current = current;
+
+        let body_calls: Vec<_> = self.calls.extract_if(.., |call| is_local(&call.variable)).collect();
+        let result = result.and_then(|_| self.check_calls(body_calls));
+
+        self.functions.retain(|variable, _| !is_local(variable));
+        self.functions.extend(functions);
+        self.reassigned.retain(|variable| !is_local(variable));
+        self.reassigned.extend(reassigned);
+        self.calls.extend(calls);
+        result?;
+
+        let mut function = renumber(body, &body_order);
+        function.arity = arity;
+        function.locals_count = scope.symbol_count;
+        mark_tail_calls(&mut function);
+
+        Ok(function)
+    }
+
+    fn lower_function_body(&mut self, function: FunctionLiteral) -> Result<(), LowerError> {
+        let entry = self.new_block();
+        self.switch_to(entry);
+
+        for param in function.params {
+            self.define(param.value)?;
+        }
+
+        // a body that doesn't end with an expression returns null
+        let has_value = matches!(function.body.statements.last(), Some(Statement::Expression(_)));
+
+        self.lower_block(function.body)?;
+        if !has_value {
+            self.push(Op::Push(Value::Null));
+        }
+        self.terminate(Terminator::Return);
+
+        Ok(())
+    }
+
+    /// Fails if any of `calls` passes a different number of arguments than
+    /// its callee takes, unless the variable it calls through was assigned
+    /// another function since.
+    fn check_calls(&self, calls: Vec<KnownCall>) -> Result<(), LowerError> {
+        for call in calls {
+            if self.reassigned.contains(&call.variable) {
+                continue;
+            }
+
+            let arity = self.functions[&call.variable];
+            if arity != call.argc {
+                return Err(LowerError::ArityMismatch {
+                    callee: call.name,
+                    arity,
+                    argc: call.argc,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn define(&mut self, name: String) -> Result<Variable, LowerError> {
+        let symbol = self.scope.define(name)?;
+
+        Ok(Variable {
+            scope: symbol.scope,
+            index: symbol.index,
+        })
+    }
+
+    fn resolve(&mut self, name: String) -> Result<Variable, LowerError> {
+        // locals of the function being lowered are in the current scope, any
+        // other local belongs to an enclosing function
+        let is_current = self.scope.current().resolve(&name).is_some();
+
+        let symbol = self.scope.resolve(name.clone())?;
+        if symbol.scope == ScopeLevel::Local && !is_current {
+            return Err(LowerError::CapturedVariable(name));
+        }
+
+        Ok(Variable {
+            scope: symbol.scope,
+            index: symbol.index,
+        })
+    }
+
+    /// Stores TOS into `variable`. Builtins can't be reassigned, so the store
+    /// is left out for them.
+    fn store(&mut self, variable: Variable) {
+        if variable.scope != ScopeLevel::Builtin {
+            self.push(Op::Store(variable));
+        }
+    }
+
+    fn push(&mut self, op: Op) {
+        self.blocks[self.current.0].ops.push(op);
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock {
+            ops: Vec::new(),
+            terminator: Terminator::Return,
+        });
+
+        BlockId(self.blocks.len() - 1)
+    }
+
+    fn switch_to(&mut self, block: BlockId) {
+        self.current = block;
+        self.order.push(block);
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.blocks[self.current.0].terminator = terminator;
+    }
+}
+
+/// Puts the blocks in `order`, which follows the source, and updates every
+/// reference to them.
+fn renumber(blocks: Vec<BasicBlock>, order: &[BlockId]) -> Function {
+    let mut new_ids = vec![BlockId(0); blocks.len()];
+    for (new, old) in order.iter().enumerate() {
+        new_ids[old.0] = BlockId(new);
+    }
+
+    let mut blocks: Vec<Option<BasicBlock>> = blocks.into_iter().map(Some).collect();
+
+    let blocks = order
+        .iter()
+        .map(|old| {
+            let mut block = blocks[old.0].take().unwrap();
+
+            block.terminator = match block.terminator {
+                Terminator::Jump(target) => Terminator::Jump(new_ids[target.0]),
+                Terminator::Branch { then, otherwise } => Terminator::Branch {
+                    then: new_ids[then.0],
+                    otherwise: new_ids[otherwise.0],
+                },
+                Terminator::Return => Terminator::Return,
+            };
+
+            block
+        })
+        .collect();
+
+    Function {
+        blocks,
+        ..Function::default()
+    }
+}
+
+/// Turns calls whose result is returned right away into tail calls. That is a
+/// call ending a block that returns, or that jumps through empty blocks to a
+/// return, like the last expression of an `if` at the end of a function.
+fn mark_tail_calls(function: &mut Function) {
+    for id in function.block_ids() {
+        if !returns(function, id) {
+            continue;
+        }
+
+        if let Some(op) = function.blocks[id.0].ops.last_mut()
+            && let Op::Call(argc) = *op
+        {
+            *op = Op::TailCall(argc);
+        }
+    }
+}
+
+/// Returns whether control returns right after the ops of block `id`.
+fn returns(function: &Function, mut id: BlockId) -> bool {
+    // bounded, since a loop of empty blocks never settles
+    for _ in 0..function.blocks.len() {
+        match function.block(id).terminator {
+            Terminator::Return => return true,
+            Terminator::Jump(target) if function.block(target).ops.is_empty() => id = target,
+            _ => return false,
+        }
+    }
+
+    false
+}
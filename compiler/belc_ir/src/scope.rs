@@ -1,12 +1,11 @@
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
 
-use belvm_bytecode::opcode;
 use belvm_std::BUILTIN_FUNCTIONS;
 
-use crate::error::CodegenError;
+use crate::error::LowerError;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ScopeLevel {
     Builtin,
     Global,
@@ -21,13 +20,12 @@ pub struct Symbol {
 
 pub struct CompilationScope {
     pub scope: ScopeLevel,
-    pub instructions: Vec<u8>,
     pub symbol_store: HashMap<String, Symbol>,
     pub symbol_count: usize,
 }
 
 impl CompilationScope {
-    pub fn define(&mut self, name: String) -> Result<&Symbol, CodegenError> {
+    pub fn define(&mut self, name: String) -> Result<&Symbol, LowerError> {
         let symbol = Symbol {
             scope: self.scope,
             index: self.symbol_count,
@@ -37,7 +35,7 @@ impl CompilationScope {
 
         match self.symbol_store.entry(name.clone()) {
             Entry::Vacant(entry) => Ok(entry.insert(symbol)),
-            Entry::Occupied(_) => Err(CodegenError::DuplicateSymbol(name)),
+            Entry::Occupied(_) => Err(LowerError::DuplicateSymbol(name)),
         }
     }
 
@@ -56,7 +54,6 @@ impl Default for ScopeManager {
         let mut sm = Self {
             main_scope: CompilationScope {
                 scope: ScopeLevel::Global,
-                instructions: Vec::new(),
                 symbol_store: HashMap::new(),
                 symbol_count: 0,
             },
@@ -83,7 +80,6 @@ impl ScopeManager {
     pub fn enter(&mut self) {
         self.scope_store.push(CompilationScope {
             scope: ScopeLevel::Local,
-            instructions: Vec::new(),
             symbol_store: HashMap::new(),
             symbol_count: 0,
         });
@@ -91,13 +87,7 @@ impl ScopeManager {
 
     pub fn leave(&mut self) -> CompilationScope {
         // we want to panic when trying to leave main scope
-        let mut scope = self.scope_store.pop().unwrap();
-
-        if let Some(&opcode::POP) = scope.instructions.last() {
-            scope.instructions.pop();
-        }
-
-        scope
+        self.scope_store.pop().unwrap()
     }
 
     pub fn current_mut(&mut self) -> &mut CompilationScope {
@@ -108,17 +98,17 @@ impl ScopeManager {
         self.scope_store.last().unwrap_or(&self.main_scope)
     }
 
-    pub fn define(&mut self, name: String) -> Result<&Symbol, CodegenError> {
+    pub fn define(&mut self, name: String) -> Result<&Symbol, LowerError> {
         self.current_mut().define(name)
     }
 
-    pub fn resolve(&self, name: String) -> Result<&Symbol, CodegenError> {
+    pub fn resolve(&self, name: String) -> Result<&Symbol, LowerError> {
         for symbol in self.scope_store.iter().rev() {
             if let Some(symbol) = symbol.resolve(&name) {
                 return Ok(symbol);
             }
         }
 
-        self.main_scope.resolve(&name).ok_or(CodegenError::UnknownSymbol(name))
+        self.main_scope.resolve(&name).ok_or(LowerError::UnknownSymbol(name))
     }
 }
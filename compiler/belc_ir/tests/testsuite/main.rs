@@ -0,0 +1,298 @@
+use std::error::Error;
+
+use belc_ast::Parser;
+use belc_ir::{Function, LowerError, Lowerer};
+use belc_lexer::Lexer;
+
+fn test_lower(input: &str) -> Result<Function, Box<dyn Error>> {
+    let source = input.to_owned();
+    let lexer = Lexer::new(&source);
+    let mut parser = Parser::new(lexer);
+    let program = parser.parse_program()?;
+
+    let mut lowerer = Lowerer::default();
+    let function = lowerer.lower_program(program)?;
+
+    Ok(function)
+}
+
+#[test]
+fn straight_line() {
+    let function = test_lower("a := 1; a += 2;").unwrap();
+
+    assert_eq!(function.blocks.len(), 1);
+    assert_eq!(
+        function.to_string(),
+        "\
+bb0:
+    push 1
+    store global 1
+    pop
+    load global 1
+    push 2
+    add
+    store global 1
+    pop
+    return
+"
+    );
+}
+
+#[test]
+fn if_else() {
+    let function = test_lower("if (true) { 1 } else { 2 }; 3;").unwrap();
+
+    assert_eq!(
+        function.to_string(),
+        "\
+bb0:
+    push true
+    branch bb1, bb2
+bb1: ; preds: bb0
+    push 1
+    jump bb3
+bb2: ; preds: bb0
+    push 2
+    jump bb3
+bb3: ; preds: bb1, bb2
+    pop
+    push 3
+    pop
+    return
+"
+    );
+}
+
+#[test]
+fn if_without_else() {
+    let function = test_lower("if (false) { 1 };").unwrap();
+
+    assert_eq!(
+        function.to_string(),
+        "\
+bb0:
+    push false
+    branch bb1, bb2
+bb1: ; preds: bb0
+    push 1
+    jump bb3
+bb2: ; preds: bb0
+    push null
+    jump bb3
+bb3: ; preds: bb1, bb2
+    pop
+    return
+"
+    );
+}
+
+#[test]
+fn while_loop() {
+    let function = test_lower("i := 0; while (i < 3) { i = i + 1; };").unwrap();
+
+    assert_eq!(
+        function.to_string(),
+        "\
+bb0:
+    push 0
+    store global 1
+    pop
+    jump bb1
+bb1: ; preds: bb0, bb2
+    load global 1
+    push 3
+    lt
+    branch bb2, bb3
+bb2: ; preds: bb1
+    load global 1
+    push 1
+    add
+    store global 1
+    pop
+    jump bb1
+bb3: ; preds: bb1
+    return
+"
+    );
+}
+
+#[test]
+fn code_after_return() {
+    let function = test_lower("return 1; 2;").unwrap();
+
+    assert_eq!(
+        function.to_string(),
+        "\
+bb0:
+    push 1
+    return
+bb1:
+    push 2
+    pop
+    return
+"
+    );
+}
+
+#[test]
+fn unknown_symbol() {
+    let error = test_lower("a + 1;").unwrap_err();
+
+    assert!(matches!(
+        error.downcast_ref::<LowerError>(),
+        Some(LowerError::UnknownSymbol(name)) if name == "a"
+    ));
+}
+
+#[test]
+fn functions() {
+    let function = test_lower("f := fn(a, b) { c := a; c - b }; f(1, 2);").unwrap();
+
+    assert_eq!(
+        function.to_string(),
+        "\
+bb0:
+    push fn(arity 2, locals 3) {
+        bb0:
+            load local 0
+            store local 2
+            pop
+            load local 2
+            load local 1
+            sub
+            return
+    }
+    store global 1
+    pop
+    push 1
+    push 2
+    load global 1
+    call 2
+    pop
+    return
+"
+    );
+}
+
+#[test]
+fn tail_calls() {
+    let function = test_lower(
+        "
+        f := fn(n) {
+          if (n == 0) {
+            return f(1);
+          };
+
+          g := fn() { 1 };
+          if (n == 1) { f(n) + 1 } else { g() }
+        };
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(
+        function.to_string(),
+        "\
+bb0:
+    push fn(arity 1, locals 2) {
+        bb0:
+            load local 0
+            push 0
+            eq
+            branch bb1, bb3
+        bb1: ; preds: bb0
+            push 1
+            load global 1
+            tail_call 1
+            return
+        bb2:
+            jump bb4
+        bb3: ; preds: bb0
+            push null
+            jump bb4
+        bb4: ; preds: bb2, bb3
+            pop
+            push fn(arity 0, locals 0) {
+                bb0:
+                    push 1
+                    return
+            }
+            store local 1
+            pop
+            load local 0
+            push 1
+            eq
+            branch bb5, bb6
+        bb5: ; preds: bb4
+            load local 0
+            load global 1
+            call 1
+            push 1
+            add
+            jump bb7
+        bb6: ; preds: bb4
+            load local 1
+            tail_call 0
+            jump bb7
+        bb7: ; preds: bb5, bb6
+            return
+    }
+    store global 1
+    pop
+    return
+"
+    );
+}
+
+#[test]
+fn captured_variable() {
+    let error = test_lower("f := fn(a) { fn() { a } };").unwrap_err();
+
+    assert!(matches!(
+        error.downcast_ref::<LowerError>(),
+        Some(LowerError::CapturedVariable(name)) if name == "a"
+    ));
+}
+
+#[test]
+fn arity_mismatch() {
+    let sources = [
+        ("f := fn(a, b) { a }; f(1);", "f", 2, 1),
+        ("f := fn() { 1 }; g := fn() { f(1, 2) };", "f", 0, 2),
+        ("g := fn() { f := fn(a) { a }; f() };", "f", 1, 0),
+        ("fn(a) { a }(1, 2);", "function literal", 1, 2),
+    ];
+
+    for (source, name, expected_arity, expected_argc) in sources {
+        let error = test_lower(source).unwrap_err();
+
+        assert!(
+            matches!(
+                error.downcast_ref::<LowerError>(),
+                Some(LowerError::ArityMismatch { callee, arity, argc })
+                    if callee == name && *arity == expected_arity && *argc == expected_argc
+            ),
+            "{source}: {error}"
+        );
+    }
+}
+
+#[test]
+fn reassigned_callees_are_not_checked() {
+    // the call may run after `f` was given another function
+    test_lower(
+        "
+        f := fn(a) { a };
+        i := 0;
+        while (i < 2) {
+          if (i == 1) { f(1, 2) };
+          f = fn(a, b) { b };
+          i += 1;
+        };
+        ",
+    )
+    .unwrap();
+
+    // locals of other functions don't count, even at the same index
+    test_lower("g := fn() { f := fn(a) { a }; f(1) }; h := fn() { f := fn() { 1 }; f() };").unwrap();
+}
@@ -18,6 +18,42 @@ pub enum LexerError {
     UnclosedString,
 }
 
+/// A position in the source code, both counted from 1
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: u32,
+    pub col: u32,
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/// A range of the source code, from `start` up to but not including `end`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    /// Returns the span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.start)
+    }
+}
+
 pub fn char_to_u8(c: char) -> Option<u8> {
     match c {
         '0'..='9' => Some(c as u8 - b'0'),
@@ -42,6 +78,9 @@ pub struct Lexer<'a> {
     ///
     /// Points to the next character to process.
     current_col: u32,
+
+    /// Span of the last token returned by [`Lexer::next_token`]
+    span: Span,
 }
 
 impl<'a> Lexer<'a> {
@@ -55,9 +94,22 @@ impl<'a> Lexer<'a> {
             source,
             current_row: 1,
             current_col: 1,
+            span: Span::default(),
         }
     }
 
+    fn position(&self) -> Position {
+        Position {
+            line: self.current_row,
+            col: self.current_col,
+        }
+    }
+
+    /// Returns the span of the last token returned by [`Lexer::next_token`].
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
     fn advance(&mut self) -> Option<char> {
         let result = self.current;
         self.current = self.chars.next();
@@ -94,6 +146,18 @@ impl<'a> Lexer<'a> {
             };
         }
 
+        let start = self.position();
+        let token = self.read_token();
+
+        self.span = Span {
+            start,
+            end: self.position(),
+        };
+
+        token
+    }
+
+    fn read_token(&mut self) -> Result<Token, LexerError> {
         if self.current.is_none() {
             return Ok(Token::EOF);
         }
@@ -546,4 +610,20 @@ mod tests {
         assert_eq!(lexer.current_row, 3);
         assert_eq!(lexer.current_col, 1);
     }
+
+    #[test]
+    fn token_spans() {
+        let source = String::from("x :=\n  12;");
+        let mut lexer = Lexer::new(&source);
+
+        let mut spans = Vec::new();
+        while lexer.next_token().unwrap() != Token::EOF {
+            spans.push(lexer.span().to_string());
+        }
+
+        assert_eq!(spans, ["1:1", "1:3", "2:3", "2:5"]);
+
+        let span = lexer.span();
+        assert_eq!(span.start, span.end);
+    }
 }
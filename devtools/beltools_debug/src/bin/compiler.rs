@@ -14,7 +14,7 @@ fn compile(line: String) -> Result<(), Box<dyn Error>> {
     let mut compiler = Compiler::default();
     let code = compiler.compile_program(program)?;
 
-    let disassembled = disassemble(code.instructions);
+    let disassembled = disassemble(&code);
 
     println!("===== BYTECODE =====");
     print!("{disassembled}");
@@ -25,7 +25,7 @@ fn compile(line: String) -> Result<(), Box<dyn Error>> {
     }
 
     println!("\n===== SYMBOLS =====");
-    println!("{:#?}", compiler.scope.current().symbol_store);
+    println!("{:#?}", compiler.lowerer.scope.current().symbol_store);
 
     println!();
 
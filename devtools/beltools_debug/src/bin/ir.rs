@@ -0,0 +1,43 @@
+use std::error::Error;
+use std::io::{self, Write};
+
+use belc_ast::Parser;
+use belc_ir::Lowerer;
+use belc_lexer::Lexer;
+
+fn lower(line: String) -> Result<(), Box<dyn Error>> {
+    let lexer = Lexer::new(&line);
+    let mut parser = Parser::new(lexer);
+    let program = parser.parse_program()?;
+
+    let mut lowerer = Lowerer::default();
+    let function = lowerer.lower_program(program)?;
+
+    println!("===== IR =====");
+    print!("{function}");
+
+    println!();
+
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut input = String::new();
+
+    loop {
+        print!(">> ");
+        io::stdout().flush().unwrap();
+
+        input.clear();
+        io::stdin().read_line(&mut input).unwrap();
+
+        if input.trim().is_empty() {
+            println!();
+            continue;
+        }
+
+        if let Err(error) = lower(input.clone()) {
+            println!("ERROR: {error}");
+        }
+    }
+}
@@ -1,5 +1,6 @@
 mod vm;
 
+use belvm_bytecode::Instruction;
 pub use vm::*;
 
 pub trait IntoInstructionBytes {
@@ -18,6 +19,12 @@ impl<const N: usize> IntoInstructionBytes for [u8; N] {
     }
 }
 
+impl IntoInstructionBytes for Instruction {
+    fn into_bytes(self) -> Vec<u8> {
+        self.to_bytes().expect("invalid instruction")
+    }
+}
+
 #[macro_export]
 macro_rules! instructions {
     ( $( $item:expr ),* $(,)? ) => {{
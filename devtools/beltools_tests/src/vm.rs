@@ -1,4 +1,5 @@
 use belvm::VM;
+use belvm::errors::RuntimeError;
 use belvm::stack::StackValue;
 use belvm_bytecode::{Bytecode, Constant};
 
@@ -29,6 +30,17 @@ impl VMBuilder {
         result.expect("VM failed to run");
         VMRunner { vm }
     }
+
+    #[track_caller]
+    pub fn run_err(self) -> RuntimeError {
+        let mut vm = VM::default();
+        let result = vm.run(Bytecode {
+            instructions: self.instructions,
+            constants: self.constants,
+        });
+
+        result.expect_err("VM ran successfully")
+    }
 }
 
 pub struct VMRunner {
@@ -62,6 +74,16 @@ impl VMRunner {
         self
     }
 
+    #[track_caller]
+    pub fn expect_stack_top_is_string(mut self, expected: &str) -> Self {
+        let obj = self.vm.stack_pop().expect("Failed popping from the stack!");
+        let StackValue::String(value) = obj else {
+            panic!("TOS is not a String!");
+        };
+        assert_eq!(value.value, expected, "String value mismatch on stack top!");
+        self
+    }
+
     pub fn into_vm(self) -> VM {
         self.vm
     }
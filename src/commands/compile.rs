@@ -1,16 +1,30 @@
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::PathBuf;
+use std::process;
+
+use belc::Target;
 
 #[derive(clap::Args)]
 pub struct Args {
     path: PathBuf,
+
+    /// Run the peephole optimizer over the generated bytecode
+    #[arg(short = 'O')]
+    optimize: bool,
 }
 
 impl Args {
     pub fn exec(mut self) {
         let source = fs::read_to_string(self.path.clone()).unwrap();
-        let bytecode = belc::compile(&source);
+        let mut bytecode = super::compile_source(&source, Target::Stack);
+
+        if self.optimize
+            && let Err(err) = belc::optimize(&mut bytecode)
+        {
+            eprintln!("error: {err}");
+            process::exit(1);
+        }
 
         self.path.set_extension("belc");
         let mut file = File::create(self.path).unwrap();
@@ -1,17 +1,37 @@
 use std::fs;
 use std::path::PathBuf;
 
+use belc::Target;
+use belvm_bytecode::Bytecode;
+
 #[derive(clap::Args)]
 pub struct Args {
+    /// Source file or compiled `.belc` file
     path: PathBuf,
 }
 
 impl Args {
     pub fn exec(self) {
-        let source = fs::read_to_string(self.path).unwrap();
-        let bytecode = belc::compile(&source);
+        let buffer = fs::read(self.path).unwrap();
+
+        let bytecode = if Bytecode::is_encoded(&buffer) {
+            let header = Bytecode::read_header(&buffer).unwrap();
+            let bytecode = Bytecode::from_bytes(&buffer).unwrap();
+
+            println!("version:      {}", header.version);
+            println!("checksum:     {:#010x}", header.checksum);
+            println!("payload:      {} bytes", header.payload_size);
+            println!("instructions: {} bytes", bytecode.instructions.len());
+            println!("constants:    {} entries", bytecode.constants.len());
+            println!();
+
+            bytecode
+        } else {
+            let source = String::from_utf8(buffer).unwrap();
+            super::compile_source(&source, Target::Stack)
+        };
 
-        let dis = belc::disassemble(bytecode.instructions);
+        let dis = belc::disassemble(&bytecode);
 
         println!("{dis}");
     }
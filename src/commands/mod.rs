@@ -1,2 +1,26 @@
 pub mod compile;
 pub mod dis;
+pub mod run;
+
+use std::process;
+
+use belc::Target;
+use belvm_bytecode::Bytecode;
+
+/// Compiles `source` for `target`, printing the warnings found along the way.
+/// Exits if it doesn't compile.
+fn compile_source(source: &String, target: Target) -> Bytecode {
+    let (bytecode, warnings) = match belc::compile_with_target(source, target) {
+        Ok(compiled) => compiled,
+        Err(err) => {
+            eprintln!("error: {err}");
+            process::exit(1);
+        },
+    };
+
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+
+    bytecode
+}
@@ -0,0 +1,172 @@
+use std::fs;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::process;
+
+use belc::Target;
+use belvm::VM;
+use belvm::errors::RuntimeError;
+use belvm::register::RegisterVM;
+use belvm_bytecode::Bytecode;
+use belvm_gc::gc::GcStats;
+use belvm_gc::snapshot::HeapSnapshot;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Source file or compiled `.belc` file
+    path: PathBuf,
+
+    /// Instruction set to compile the source file for, `stack` or
+    /// `register`. The register one is an experiment: programs with
+    /// functions, calls, local variables or indexing don't compile for it
+    /// yet, and compiled files can't be run with it
+    #[arg(long, value_name = "TARGET", default_value = "stack", value_parser = parse_target)]
+    target: Target,
+
+    /// Print heap statistics to stderr once the program ends
+    #[arg(long)]
+    gc_stats: bool,
+
+    /// Write a JSON snapshot of the objects on the heap, and of what keeps
+    /// them alive, once the program ends or fails
+    #[arg(long, value_name = "PATH")]
+    heap_snapshot: Option<PathBuf>,
+
+    /// Most bytes of memory the heap of the program can hold
+    #[arg(long, value_name = "BYTES")]
+    heap_limit: Option<usize>,
+
+    /// Least bytes the heap can take before it is collected
+    #[arg(long, value_name = "BYTES")]
+    gc_threshold: Option<usize>,
+
+    /// Factor the live bytes are multiplied by to get the bytes the heap can
+    /// take before it is collected again
+    #[arg(long, value_name = "FACTOR", value_parser = parse_growth_factor)]
+    gc_growth: Option<f64>,
+
+    /// Bytes young objects are allocated in before they are collected on
+    /// their own, or 0 to allocate every object in the old generation
+    #[arg(long, value_name = "BYTES")]
+    gc_nursery: Option<usize>,
+}
+
+fn parse_target(arg: &str) -> Result<Target, String> {
+    match arg {
+        "stack" => Ok(Target::Stack),
+        "register" => Ok(Target::Register),
+        _ => Err("target must be `stack` or `register`".into()),
+    }
+}
+
+fn parse_growth_factor(arg: &str) -> Result<f64, String> {
+    let factor: f64 = arg.parse().map_err(|err| format!("{err}"))?;
+
+    if factor >= 1.0 {
+        Ok(factor)
+    } else {
+        Err("growth factor must be at least 1".into())
+    }
+}
+
+impl Args {
+    pub fn exec(self) {
+        let buffer = fs::read(&self.path).unwrap();
+
+        let bytecode = if Bytecode::is_encoded(&buffer) {
+            if self.target == Target::Register {
+                eprintln!("error: compiled files hold stack bytecode, which the register target can't run");
+                process::exit(1);
+            }
+
+            Bytecode::from_bytes(&buffer).unwrap()
+        } else {
+            let source = String::from_utf8(buffer).unwrap();
+            super::compile_source(&source, self.target)
+        };
+
+        let (result, stats, snapshot) = match self.target {
+            Target::Stack => self.run_stack(bytecode),
+            Target::Register => self.run_register(bytecode),
+        };
+
+        if self.gc_stats {
+            print_gc_stats(stats);
+        }
+
+        if let (Some(path), Some(snapshot)) = (&self.heap_snapshot, snapshot) {
+            let file = BufWriter::new(fs::File::create(path).unwrap());
+            snapshot.write_json(file).unwrap();
+        }
+
+        if let Err(err) = result {
+            eprintln!("error: {err}");
+            process::exit(1);
+        }
+    }
+
+    fn run_stack(&self, bytecode: Bytecode) -> (Result<(), RuntimeError>, GcStats, Option<HeapSnapshot>) {
+        let mut vm = VM::default();
+        vm.set_heap_limit(self.heap_limit);
+        vm.set_snapshot_on_error(self.heap_snapshot.is_some());
+
+        if let Some(bytes) = self.gc_threshold {
+            vm.set_gc_threshold(bytes);
+        }
+
+        if let Some(factor) = self.gc_growth {
+            vm.set_gc_growth_factor(factor);
+        }
+
+        if let Some(bytes) = self.gc_nursery {
+            vm.set_gc_nursery_size(bytes);
+        }
+
+        let result = vm.run(bytecode);
+
+        // a failed run unwinds its stack, so its snapshot was taken before
+        let snapshot = self
+            .heap_snapshot
+            .as_ref()
+            .map(|_| vm.take_error_snapshot().unwrap_or_else(|| vm.heap_snapshot()));
+
+        (result, vm.gc_stats(), snapshot)
+    }
+
+    fn run_register(&self, bytecode: Bytecode) -> (Result<(), RuntimeError>, GcStats, Option<HeapSnapshot>) {
+        let mut vm = RegisterVM::default();
+        vm.set_heap_limit(self.heap_limit);
+
+        if let Some(bytes) = self.gc_threshold {
+            vm.set_gc_threshold(bytes);
+        }
+
+        if let Some(factor) = self.gc_growth {
+            vm.set_gc_growth_factor(factor);
+        }
+
+        if let Some(bytes) = self.gc_nursery {
+            vm.set_gc_nursery_size(bytes);
+        }
+
+        let result = vm.run(bytecode);
+        let snapshot = self.heap_snapshot.as_ref().map(|_| vm.heap_snapshot());
+
+        (result, vm.gc_stats(), snapshot)
+    }
+}
+
+fn print_gc_stats(stats: GcStats) {
+    eprintln!(
+        "gc: {} collections, {} minor collections, {:?} total pause, {:?} max pause",
+        stats.collections, stats.minor_collections, stats.total_pause, stats.max_pause
+    );
+    eprintln!(
+        "gc: {} live objects, {} bytes, {} bytes reserved",
+        stats.objects, stats.bytes, stats.reserved
+    );
+
+    for ty in stats.types {
+        eprintln!("gc:   {:<12} {:>8} objects {:>10} bytes", ty.name, ty.objects, ty.bytes);
+    }
+}
@@ -12,6 +12,7 @@ struct Cli {
 enum Commands {
     Dis(commands::dis::Args),
     Compile(commands::compile::Args),
+    Run(commands::run::Args),
 }
 
 fn main() {
@@ -20,5 +21,6 @@ fn main() {
     match cli.command {
         Commands::Dis(args) => args.exec(),
         Commands::Compile(args) => args.exec(),
+        Commands::Run(args) => args.exec(),
     }
 }
@@ -0,0 +1,84 @@
+//! Compares the stack VM, with and without superinstructions, against the
+//! register VM on loop-heavy programs.
+//!
+//! Run with `cargo bench -p belvm --bench dispatch`.
+
+use belc::Target;
+use belvm::VM;
+use belvm::register::RegisterVM;
+use belvm_bytecode::Bytecode;
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+
+/// `examples/04_while_loops.bel`, counting much further and without printing
+const WHILE_LOOPS: &str = "
+i := 0;
+while (i < 100000) {
+  i = i + 1;
+};
+return i;
+";
+
+const SUM_OF_MULTIPLES: &str = "
+sum := 0;
+i := 0;
+while (i < 100000) {
+  if (i % 3 == 0 || i % 5 == 0) {
+    sum += i;
+  };
+  i += 1;
+};
+return sum;
+";
+
+const FIBONACCI: &str = "
+a := 0;
+b := 1;
+i := 0;
+while (i < 100000) {
+  t := (a + b) % 1000000007;
+  a = b;
+  b = t;
+  i += 1;
+};
+return a;
+";
+
+fn compile(source: &str, target: Target) -> Bytecode {
+    let (bytecode, _) = belc::compile_with_target(&source.to_owned(), target).unwrap();
+    bytecode
+}
+
+fn dispatch(c: &mut Criterion) {
+    let programs = [
+        ("while_loops", WHILE_LOOPS),
+        ("sum_of_multiples", SUM_OF_MULTIPLES),
+        ("fibonacci", FIBONACCI),
+    ];
+
+    for (name, source) in programs {
+        let mut group = c.benchmark_group(name);
+
+        let stack = compile(source, Target::Stack);
+        group.bench_with_input(BenchmarkId::new("stack", name), &stack, |b, code| {
+            b.iter(|| VM::default().run(code.clone()).unwrap())
+        });
+
+        group.bench_with_input(BenchmarkId::new("stack_unfused", name), &stack, |b, code| {
+            b.iter(|| {
+                let mut vm = VM::default();
+                vm.set_superinstructions(false);
+                vm.run(code.clone()).unwrap()
+            })
+        });
+
+        let register = compile(source, Target::Register);
+        group.bench_with_input(BenchmarkId::new("register", name), &register, |b, code| {
+            b.iter(|| RegisterVM::default().run(code.clone()).unwrap())
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
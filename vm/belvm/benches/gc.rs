@@ -0,0 +1,101 @@
+//! Compares the simple collector against one with a nursery, on programs
+//! that mostly allocate short-lived objects.
+//!
+//! Run with `cargo bench -p belvm --bench gc`.
+
+use belc::Target;
+use belvm::VM;
+use belvm::objects::StringObject;
+use belvm::register::RegisterVM;
+use belvm_bytecode::Bytecode;
+use belvm_gc::gc::{GcHeap, GcPtr, Trace};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+
+/// Boxes a wide integer on every iteration, which is garbage by the next
+const BOXED_INTEGERS: &str = "
+sum := 140737488355328;
+i := 0;
+while (i < 100000) {
+  x := sum + i;
+  i += 1;
+};
+return i;
+";
+
+/// Sizes of the nursery to compare, the first one being no nursery at all
+const NURSERY_SIZES: [usize; 3] = [0, 256 * 1024, 1024 * 1024];
+
+fn compile(source: &str, target: Target) -> Bytecode {
+    let (bytecode, _) = belc::compile_with_target(&source.to_owned(), target).unwrap();
+    bytecode
+}
+
+fn name(nursery: usize) -> String {
+    match nursery {
+        0 => "simple".into(),
+        bytes => format!("nursery_{}k", bytes / 1024),
+    }
+}
+
+fn boxed_integers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("boxed_integers");
+    let stack = compile(BOXED_INTEGERS, Target::Stack);
+    let register = compile(BOXED_INTEGERS, Target::Register);
+
+    for nursery in NURSERY_SIZES {
+        group.bench_with_input(BenchmarkId::new("stack", name(nursery)), &stack, |b, code| {
+            b.iter(|| {
+                let mut vm = VM::default();
+                vm.set_gc_nursery_size(nursery);
+                vm.run(code.clone()).unwrap()
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("register", name(nursery)), &register, |b, code| {
+            b.iter(|| {
+                let mut vm = RegisterVM::default();
+                vm.set_gc_nursery_size(nursery);
+                vm.run(code.clone()).unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Allocates strings straight on a heap, keeping one in a hundred alive, the
+/// way a string-heavy script would once the VM has string operations.
+fn strings(c: &mut Criterion) {
+    let mut group = c.benchmark_group("strings");
+
+    for nursery in NURSERY_SIZES {
+        group.bench_function(BenchmarkId::new("heap", name(nursery)), |b| {
+            b.iter(|| {
+                let mut heap = GcHeap::default();
+                heap.set_nursery_size(nursery);
+                let mut kept: Vec<GcPtr<StringObject>> = Vec::new();
+
+                for i in 0..100_000 {
+                    let string = heap.alloc(StringObject::new(format!("string {i}"))).unwrap();
+
+                    if i % 100 == 0 {
+                        kept.push(string);
+                    }
+
+                    if heap.should_collect() {
+                        heap.collect(|tracer| kept.trace(tracer));
+                    } else if heap.should_collect_minor() {
+                        heap.collect_minor(|tracer| kept.trace(tracer));
+                    }
+                }
+
+                kept.len()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, boxed_integers, strings);
+criterion_main!(benches);
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use belvm_bytecode::{Constant, Function};
+use belvm_gc::gc::{GcHeap, GcPtr, Trace, Tracer};
+
+use crate::errors::RuntimeError;
+use crate::objects::StringObject;
+use crate::value::Value;
+
+/// The constants a VM is working with, supplied through the
+/// [`Bytecode`](belvm_bytecode::Bytecode) struct.
+#[derive(Default)]
+pub(crate) struct ConstantPool {
+    constants: Vec<Constant>,
+
+    /// String objects allocated for string constants, keyed by constant
+    /// index. Since the compiler interns string literals, each distinct
+    /// literal is allocated once.
+    strings: HashMap<usize, GcPtr<StringObject>>,
+}
+
+impl ConstantPool {
+    pub fn extend(&mut self, constants: Vec<Constant>) {
+        self.constants.extend(constants);
+    }
+
+    /// Returns the value of the constant at `index`, allocating its object on
+    /// `heap` if it needs one.
+    pub fn load(&mut self, index: usize, heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+        Ok(match &self.constants[index] {
+            Constant::Integer(int) => Value::integer(*int, heap)?,
+            Constant::Float(float) => Value::float(*float),
+            Constant::Boolean(boolean) => Value::boolean(*boolean),
+            Constant::String(_) => Value::string(self.intern_string(index, heap)?),
+            Constant::Null => Value::NULL,
+            Constant::Function(_) => Value::function(index),
+        })
+    }
+
+    /// Returns the function constant at `index`, if there is one.
+    pub fn function(&self, index: usize) -> Option<&Function> {
+        match self.constants.get(index) {
+            Some(Constant::Function(function)) => Some(function),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of constants in the pool.
+    pub fn len(&self) -> usize {
+        self.constants.len()
+    }
+
+    /// Returns the string object of the string constant at `index`,
+    /// allocating it on first use.
+    fn intern_string(&mut self, index: usize, heap: &mut GcHeap) -> Result<GcPtr<StringObject>, RuntimeError> {
+        if let Some(&string) = self.strings.get(&index) {
+            return Ok(string);
+        }
+
+        let Constant::String(value) = &self.constants[index] else {
+            return Err(RuntimeError::TypeError);
+        };
+
+        let value = value.clone();
+        let string = heap
+            .alloc(StringObject::new(value))
+            .map_err(|_| RuntimeError::AllocationFailed)?;
+        self.strings.insert(index, string);
+
+        Ok(string)
+    }
+
+    pub fn clear(&mut self) {
+        self.constants.clear();
+        self.strings.clear();
+    }
+}
+
+/// The string objects of the constants stay alive as long as the pool does.
+impl Trace for ConstantPool {
+    fn trace(&self, tracer: &mut Tracer) {
+        for &string in self.strings.values() {
+            tracer.mark(string);
+        }
+    }
+}
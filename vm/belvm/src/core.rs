@@ -1,26 +1,65 @@
-use belvm_bytecode::opcode;
-use belvm_bytecode::{Bytecode, Constant};
+use belvm_bytecode::{Bytecode, Constant, Function};
+use belvm_gc::gc::{DEFAULT_NURSERY_SIZE, GcHeap, GcStats, Trace, Tracer};
+use belvm_gc::snapshot::HeapSnapshot;
 
+use crate::constants::ConstantPool;
+use crate::dispatch::{self, Op, Program};
 use crate::errors::RuntimeError;
+use crate::ops;
 use crate::stack::{Stack, StackValue};
+use crate::value::Value;
 
 /// The core Virtual Machine structure.
-#[derive(Default)]
 pub struct VM {
-    /// The current instruction the VM is pointing to. This is why it's
-    /// named `ip` (short for instruction pointer).
+    /// Offset of the first instruction the next call to [`VM::run`] runs.
+    /// Everything before it has already been run.
     ip: usize,
 
     /// The list of bytecode instructions the VM is executing. The value of
     /// this field is supplied through the [`Bytecode`] struct.
     instructions: Vec<u8>,
 
-    /// The list of constants the VM is working with. The value of this field
-    /// is supplied through the [`Bytecode`] struct.
-    constants: Vec<Constant>,
+    /// The list of constants the VM is working with.
+    constants: ConstantPool,
+
+    /// Values of the global variables, indexed by their symbol index.
+    globals: Vec<Value>,
 
     /// The stack memory of the VM.
     stack: Stack,
+
+    /// Runs every instruction on its own instead of fusing common sequences
+    /// into superinstructions.
+    unfused: bool,
+
+    /// The heap the objects of the VM live on. Dropping the VM frees them.
+    heap: GcHeap,
+
+    /// Takes a heap snapshot when running fails, before the stack is unwound.
+    snapshot_on_error: bool,
+
+    /// Snapshot of the heap from the last time running failed, if
+    /// `snapshot_on_error` is set.
+    error_snapshot: Option<HeapSnapshot>,
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        let mut heap = GcHeap::default();
+        heap.set_nursery_size(DEFAULT_NURSERY_SIZE);
+
+        Self {
+            ip: 0,
+            instructions: Vec::new(),
+            constants: ConstantPool::default(),
+            globals: Vec::new(),
+            stack: Stack::default(),
+            unfused: false,
+            heap,
+            snapshot_on_error: false,
+            error_snapshot: None,
+        }
+    }
 }
 
 impl VM {
@@ -46,354 +85,434 @@ impl VM {
     /// })
     /// ```
     pub fn run(&mut self, code: Bytecode) -> Result<(), RuntimeError> {
-        self.constants.extend(code.constants);
-        self.instructions.extend(code.instructions);
-
-        while self.ip < self.instructions.len() {
-            let op = self.instructions[self.ip];
-
-            match op {
-                opcode::NOOP => {},
-
-                opcode::POP => {
-                    self.stack.pop()?;
-                },
-
-                opcode::ADD => {
-                    use StackValue::*;
-
-                    let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
-
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Integer(a + b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
-
-                    self.stack.push(result)?;
-                },
-
-                opcode::SUB => {
-                    use StackValue::*;
-
-                    let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
+        // function pointers are relative to the code they came with
+        let base = self.instructions.len();
+        let mut constants = code.constants;
+        for constant in &mut constants {
+            if let Constant::Function(function) = constant {
+                function.pointer += base;
+            }
+        }
 
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Integer(a - b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
+        self.constants.extend(constants);
+        self.instructions.extend(code.instructions);
 
-                    self.stack.push(result)?;
-                },
+        let functions = (0..self.constants.len()).filter_map(|index| self.constants.function(index));
+        let pointers: Vec<usize> = functions.map(|function| function.pointer).collect();
 
-                opcode::MUL => {
-                    use StackValue::*;
+        let program = dispatch::decode(&self.instructions, self.constants.len(), &pointers, !self.unfused)?;
+        let pc = program.index_of(self.ip).ok_or(RuntimeError::InvalidJump(self.ip))?;
 
-                    let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
+        let result = self.execute(&program, pc);
+        self.ip = self.instructions.len();
 
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Integer(a * b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
+        // a frame left by an error would return into code that is gone
+        if result.is_err() {
+            if self.snapshot_on_error {
+                self.error_snapshot = Some(self.heap_snapshot());
+            }
 
-                    self.stack.push(result)?;
-                },
+            self.stack.unwind();
+        }
 
-                opcode::DIV => {
-                    use StackValue::*;
+        result
+    }
 
-                    let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
+    /// Runs `program` from the op at `pc` until it halts.
+    fn execute(&mut self, program: &Program, mut pc: usize) -> Result<(), RuntimeError> {
+        loop {
+            // every value is on the stack or in a global between two ops, so
+            // garbage can be collected anywhere
+            #[cfg(feature = "gc-stress")]
+            self.maybe_collect()?;
+
+            // SAFETY: `decode` verified that every jump and function entry
+            // lands on an op, return addresses are checked as frames are
+            // popped, and the stream ends with `Op::Halt`, which stops the
+            // loop, so `pc` is always in bounds
+            let op = unsafe { *program.ops.get_unchecked(pc) };
+            pc += 1;
 
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Integer(a / b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
+            match op {
+                Op::Noop => {},
 
-                    self.stack.push(result)?;
+                Op::Pop => {
+                    self.stack.pop()?;
                 },
 
-                opcode::MOD => {
-                    use StackValue::*;
-
+                Op::Binary(f) => {
                     let right = self.stack.pop()?;
                     let left = self.stack.pop()?;
 
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Integer(a % b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
-
-                    self.stack.push(result)?;
+                    self.stack.push(f(&left, &right, &mut self.heap)?)?;
                 },
 
-                opcode::CONSTANT => {
-                    let index = self.read_u16();
-                    let constant = self.constants[index as usize].clone();
-
-                    let object = match constant {
-                        Constant::Integer(int) => StackValue::Integer(int),
-                        Constant::Boolean(boolean) => StackValue::Boolean(boolean),
-                        Constant::String(_) => todo!(),
-                        Constant::Null => todo!(),
-                    };
-
+                Op::Constant(index) => {
+                    let object = self.constants.load(index, &mut self.heap)?;
                     self.stack.push(object)?;
                 },
 
-                opcode::TRUE => {
-                    self.stack.push(StackValue::Boolean(true))?;
+                Op::True => {
+                    self.stack.push(Value::boolean(true))?;
                 },
 
-                opcode::FALSE => {
-                    self.stack.push(StackValue::Boolean(false))?;
+                Op::False => {
+                    self.stack.push(Value::boolean(false))?;
                 },
 
-                opcode::NULL => {
-                    self.stack.push(StackValue::Null)?;
+                Op::Null => {
+                    self.stack.push(Value::NULL)?;
                 },
 
-                opcode::EQUAL => {
-                    use StackValue::*;
-
+                Op::Not => {
                     let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
-
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Boolean(a == b)),
-                        (Boolean(a), Boolean(b)) => Ok(Boolean(a == b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
-
-                    self.stack.push(result)?;
+                    self.stack.push(ops::not(&right, &mut self.heap)?)?;
                 },
 
-                opcode::NOT_EQUAL => {
-                    use StackValue::*;
-
+                Op::Neg => {
                     let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
-
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Boolean(a != b)),
-                        (Boolean(a), Boolean(b)) => Ok(Boolean(a != b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
-
-                    self.stack.push(result)?;
+                    self.stack.push(ops::neg(&right, &mut self.heap)?)?;
                 },
 
-                opcode::LESS_THAN => {
-                    use StackValue::*;
-
-                    let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
-
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Boolean(a < b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
-
-                    self.stack.push(result)?;
+                // loops jump back and recursion goes through calls, so those
+                // are where garbage piles up
+                Op::Jump(target) => {
+                    pc = target;
+                    self.maybe_collect()?;
                 },
 
-                opcode::LESS_THAN_EQUAL => {
-                    use StackValue::*;
-
+                Op::JumpIfFalse(target) => {
                     let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
 
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Boolean(a <= b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
+                    if !ops::is_truthy(&right)? {
+                        pc = target;
+                    }
+                },
 
-                    self.stack.push(result)?;
+                Op::SetGlobal(index) => {
+                    let value = *self.stack.top().ok_or(RuntimeError::StackUnderflow)?;
+                    self.set_global(index, value);
                 },
 
-                opcode::AND => {
-                    use StackValue::*;
+                Op::GetGlobal(index) => {
+                    let value = self.get_global(index);
+                    self.stack.push(value)?;
+                },
 
-                    let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
+                Op::SetLocal(index) => {
+                    let value = *self.stack.top().ok_or(RuntimeError::StackUnderflow)?;
+                    self.stack.set_local(index, value)?;
+                },
 
-                    let result = match (left, right) {
-                        (Boolean(a), Boolean(b)) => Ok(Boolean(a && b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
+                Op::GetLocal(index) => {
+                    let value = *self.stack.local(index)?;
+                    self.stack.push(value)?;
+                },
 
-                    self.stack.push(result)?;
+                Op::Call(argc) => {
+                    let (function, entry) = self.callee(program, argc)?;
+                    self.stack.push_frame(function.arity, function.locals_count, pc)?;
+                    pc = entry;
+                    self.maybe_collect()?;
                 },
 
-                opcode::OR => {
-                    use StackValue::*;
+                // a tail call outside of a function has no frame to reuse
+                Op::TailCall(argc) if self.stack.depth() == 0 => {
+                    let (function, entry) = self.callee(program, argc)?;
+                    self.stack.push_frame(function.arity, function.locals_count, pc)?;
+                    pc = entry;
+                    self.maybe_collect()?;
+                },
 
-                    let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
+                Op::TailCall(argc) => {
+                    let (function, entry) = self.callee(program, argc)?;
+                    self.stack.reuse_frame(function.arity, function.locals_count)?;
+                    pc = entry;
+                    self.maybe_collect()?;
+                },
 
-                    let result = match (left, right) {
-                        (Boolean(a), Boolean(b)) => Ok(Boolean(a || b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
+                // returning from the top level ends the program, leaving the
+                // value on the stack
+                Op::Return | Op::ReturnValue if self.stack.depth() == 0 => break,
 
-                    self.stack.push(result)?;
+                Op::Return => {
+                    pc = self.pop_frame(program)?;
+                    self.stack.push(Value::NULL)?;
                 },
 
-                opcode::BIT_AND => {
-                    use StackValue::*;
+                Op::ReturnValue => {
+                    let value = self.stack.pop()?;
+                    pc = self.pop_frame(program)?;
+                    self.stack.push(value)?;
+                },
 
-                    let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
+                Op::Halt => break,
 
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Integer(a & b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
+                Op::Unsupported(byte) => return Err(RuntimeError::UnknownInstruction(byte)),
 
-                    self.stack.push(result)?;
+                Op::StoreGlobal(index) => {
+                    let value = self.stack.pop()?;
+                    self.set_global(index, value);
                 },
 
-                opcode::BIT_OR => {
-                    use StackValue::*;
-
-                    let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
+                Op::StoreLocal(index) => {
+                    let value = self.stack.pop()?;
+                    self.stack.set_local(index, value)?;
+                },
 
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Integer(a | b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
+                Op::GlobalConstant { op, global, constant } => {
+                    let left = self.get_global(global);
+                    let right = self.constants.load(constant, &mut self.heap)?;
 
-                    self.stack.push(result)?;
+                    self.stack.push(op(&left, &right, &mut self.heap)?)?;
                 },
 
-                opcode::BIT_XOR => {
-                    use StackValue::*;
+                Op::LocalConstant { op, local, constant } => {
+                    let right = self.constants.load(constant, &mut self.heap)?;
+                    let left = self.stack.local(local)?;
 
-                    let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
+                    let value = op(left, &right, &mut self.heap)?;
+                    self.stack.push(value)?;
+                },
 
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Integer(a ^ b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
+                Op::GlobalGlobal { op, left, right } => {
+                    let left = self.get_global(left);
+                    let right = self.get_global(right);
 
-                    self.stack.push(result)?;
+                    self.stack.push(op(&left, &right, &mut self.heap)?)?;
                 },
 
-                opcode::BIT_SL => {
-                    use StackValue::*;
-
+                Op::BinaryJumpIfFalse { op, target } => {
                     let right = self.stack.pop()?;
                     let left = self.stack.pop()?;
 
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Integer(a << b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
-
-                    self.stack.push(result)?;
+                    if !ops::is_truthy(&op(&left, &right, &mut self.heap)?)? {
+                        pc = target;
+                    }
                 },
 
-                opcode::BIT_SR => {
-                    use StackValue::*;
+                Op::GlobalConstantJumpIfFalse {
+                    op,
+                    global,
+                    constant,
+                    target,
+                } => {
+                    let left = self.get_global(global);
+                    let right = self.constants.load(constant, &mut self.heap)?;
+
+                    if !ops::is_truthy(&op(&left, &right, &mut self.heap)?)? {
+                        pc = target;
+                    }
+                },
+            };
+        }
 
-                    let right = self.stack.pop()?;
-                    let left = self.stack.pop()?;
+        Ok(())
+    }
 
-                    let result = match (left, right) {
-                        (Integer(a), Integer(b)) => Ok(Integer(a >> b)),
-                        (_, _) => Err(RuntimeError::TypeError),
-                    }?;
+    /// Pops the function to call with `argc` arguments, returning it along
+    /// with the index of the op its body starts at.
+    fn callee(&mut self, program: &Program, argc: usize) -> Result<(Function, usize), RuntimeError> {
+        let value = self.stack.pop()?;
+        let index = value.as_function().ok_or(RuntimeError::NotAFunction)?;
+        let function = self
+            .constants
+            .function(index)
+            .ok_or(RuntimeError::NotAFunction)?
+            .clone();
+
+        // the frame is laid out from the arity, so any other number of
+        // arguments would take values of the caller as its own
+        if function.arity != argc {
+            return Err(RuntimeError::ArityMismatch {
+                arity: function.arity,
+                argc,
+            });
+        }
 
-                    self.stack.push(result)?;
-                },
+        // `decode` made sure every function starts on an op
+        let entry = program
+            .index_of(function.pointer)
+            .ok_or(RuntimeError::InvalidFunction(function.pointer))?;
 
-                opcode::BANG => {
-                    use StackValue::*;
+        Ok((function, entry))
+    }
 
-                    let right = self.stack.pop()?;
+    /// Pops the current frame, returning the index of the op to return to.
+    fn pop_frame(&mut self, program: &Program) -> Result<usize, RuntimeError> {
+        let pc = self.stack.pop_frame()?;
 
-                    let result = match right {
-                        Boolean(a) => Ok(Boolean(!a)),
-                        _ => Err(RuntimeError::TypeError),
-                    }?;
+        // return addresses are plain values on the stack, which malformed
+        // bytecode can clobber, so they are never trusted
+        if pc >= program.ops.len() {
+            return Err(RuntimeError::InvalidReturn(pc));
+        }
 
-                    self.stack.push(result)?;
-                },
+        Ok(pc)
+    }
 
-                opcode::MINUS => {
-                    use StackValue::*;
+    /// Collects garbage if enough was allocated since the last collection, or
+    /// only the nursery if it is full, failing if the heap is still over its
+    /// limit afterwards.
+    fn maybe_collect(&mut self) -> Result<(), RuntimeError> {
+        let Self {
+            heap,
+            stack,
+            globals,
+            constants,
+            ..
+        } = self;
+
+        let trace_roots = trace_roots(stack, globals, constants);
+
+        if heap.should_collect() {
+            heap.collect(trace_roots);
+        } else if heap.should_collect_minor() {
+            heap.collect_minor(trace_roots);
+        } else {
+            return Ok(());
+        }
 
-                    let right = self.stack.pop()?;
+        match self.heap.limit() {
+            Some(limit) if self.heap.is_over_limit() => Err(RuntimeError::OutOfMemory(limit)),
+            _ => Ok(()),
+        }
+    }
 
-                    let result = match right {
-                        Integer(a) => Ok(Integer(-a)),
-                        _ => Err(RuntimeError::TypeError),
-                    }?;
+    /// Frees the objects the VM can no longer reach from its stack, globals
+    /// and constants.
+    ///
+    /// This also runs on its own while the VM runs, once enough was allocated
+    /// since the last collection.
+    pub fn collect_garbage(&mut self) {
+        let Self {
+            heap,
+            stack,
+            globals,
+            constants,
+            ..
+        } = self;
+
+        heap.collect(trace_roots(stack, globals, constants));
+    }
 
-                    self.stack.push(result)?;
-                },
+    /// Returns the heap the objects of the VM live on.
+    pub fn heap(&self) -> &GcHeap {
+        &self.heap
+    }
 
-                opcode::JUMP => {
-                    let relative = self.read_u16() as i16;
-                    self.increment_ip(relative as usize);
-                },
+    /// Returns what is on the heap of the VM and what collecting it cost.
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
 
-                opcode::JUMP_IF_FALSE => {
-                    use StackValue::*;
+    /// Takes a snapshot of the objects on the heap of the VM, and of what
+    /// points to them. See [`GcHeap::snapshot`].
+    pub fn heap_snapshot(&self) -> HeapSnapshot {
+        self.heap
+            .snapshot(trace_roots(&self.stack, &self.globals, &self.constants))
+    }
 
-                    let relative = self.read_u16() as i16;
-                    let right = self.stack.pop()?;
+    /// Makes the VM take a [heap snapshot](VM::heap_snapshot) whenever
+    /// running fails. It is taken before the stack is unwound, so it still
+    /// shows what the failed program was keeping alive.
+    pub fn set_snapshot_on_error(&mut self, enabled: bool) {
+        self.snapshot_on_error = enabled;
+    }
 
-                    let result = match right {
-                        Integer(a) => Ok(a > 0),
-                        Boolean(a) => Ok(a),
-                        _ => Err(RuntimeError::TypeError),
-                    }?;
+    /// Returns the heap snapshot taken the last time running failed, if
+    /// [`VM::set_snapshot_on_error`] was set then, and leaves none behind.
+    pub fn take_error_snapshot(&mut self) -> Option<HeapSnapshot> {
+        self.error_snapshot.take()
+    }
 
-                    if !result {
-                        self.increment_ip(relative as usize);
-                    }
-                },
+    /// Sets the least bytes the heap can take before the VM collects it. See
+    /// [`GcHeap::set_threshold`].
+    pub fn set_gc_threshold(&mut self, bytes: usize) {
+        self.heap.set_threshold(bytes);
+    }
 
-                _ => return Err(RuntimeError::UnknownInstruction(op)),
-            };
+    /// Sets the most bytes of memory the heap of the VM can hold, or removes
+    /// the limit. There is none by default. See
+    /// [`GcHeap::set_limit`](belvm_gc::gc::GcHeap::set_limit) for what counts.
+    ///
+    /// The VM checks the limit where it collects garbage: whenever it loops
+    /// or calls a function. If the heap is over the limit even after a
+    /// collection, running fails with [`RuntimeError::OutOfMemory`], and the
+    /// VM can be reused once it frees some of its objects.
+    pub fn set_heap_limit(&mut self, limit: Option<usize>) {
+        self.heap.set_limit(limit);
+    }
 
-            self.increment_ip(1);
-        }
+    /// Sets how much the heap can grow past what is live before the VM
+    /// collects it again. See [`GcHeap::set_growth_factor`].
+    pub fn set_gc_growth_factor(&mut self, factor: f64) {
+        self.heap.set_growth_factor(factor);
+    }
 
-        Ok(())
+    /// Sets the bytes the nursery of the heap can take, which defaults to
+    /// [`DEFAULT_NURSERY_SIZE`]. Zero turns the nursery off. See
+    /// [`GcHeap::set_nursery_size`].
+    pub fn set_gc_nursery_size(&mut self, bytes: usize) {
+        self.heap.set_nursery_size(bytes);
     }
 
-    fn increment_ip(&mut self, value: usize) {
-        self.ip = self.ip.checked_add_signed(value as isize).unwrap();
+    /// Turns fusing common instruction sequences into superinstructions on or
+    /// off. It is on by default.
+    pub fn set_superinstructions(&mut self, enabled: bool) {
+        self.unfused = !enabled;
     }
 
-    fn read_u16(&mut self) -> u16 {
-        let hi = self.instructions[self.ip + 1];
-        let lo = self.instructions[self.ip + 2];
-        self.ip += 2;
+    /// Sets the most call frames the VM can have at once. See
+    /// [`DEFAULT_RECURSION_LIMIT`](crate::stack::DEFAULT_RECURSION_LIMIT).
+    pub fn set_recursion_limit(&mut self, limit: usize) {
+        self.stack.set_recursion_limit(limit);
+    }
 
-        ((hi as u16) << 8) | (lo as u16)
+    fn get_global(&self, index: usize) -> Value {
+        self.globals.get(index).copied().unwrap_or_default()
     }
 
-    #[allow(dead_code)]
-    fn read_u8(&mut self) -> u8 {
-        let v = self.instructions[self.ip + 1];
-        self.ip += 1;
+    fn set_global(&mut self, index: usize, value: Value) {
+        if index >= self.globals.len() {
+            self.globals.resize(index + 1, Value::NULL);
+        }
 
-        v
+        self.globals[index] = value;
     }
 
     pub fn stack_size(&self) -> usize {
         self.stack.size()
     }
 
+    /// Pops the top of the stack.
+    ///
+    /// Once popped, an object the value points to is no longer a root, so it
+    /// only lives until the VM next collects garbage, or is dropped.
     pub fn stack_pop(&mut self) -> Result<StackValue, RuntimeError> {
-        self.stack.pop()
+        self.stack.pop().map(|value| value.unpack())
+    }
+
+    /// Returns the value of the global variable at `index`, if it was set.
+    pub fn global(&self, index: usize) -> Option<StackValue> {
+        self.globals.get(index).map(Value::unpack)
+    }
+}
+
+/// Returns a function marking the values the VM holds, naming them after
+/// where they are held.
+fn trace_roots<'a>(
+    stack: &'a Stack,
+    globals: &'a [Value],
+    constants: &'a ConstantPool,
+) -> impl Fn(&mut Tracer) + Copy + 'a {
+    move |tracer| {
+        tracer.root("stack");
+        stack.trace(tracer);
+        tracer.root("globals");
+        globals.trace(tracer);
+        tracer.root("constants");
+        constants.trace(tracer);
     }
 }
 
@@ -401,7 +520,29 @@ impl Drop for VM {
     fn drop(&mut self) {
         self.instructions.clear();
         self.constants.clear();
+        self.globals.clear();
 
         std::mem::drop(std::mem::take(&mut self.stack));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use belvm_bytecode::opcode;
+
+    use super::*;
+
+    #[test]
+    fn rejects_forged_return_addresses() {
+        let mut vm = VM::default();
+        vm.stack.push_frame(0, 0, 1000).unwrap();
+
+        let result = vm.run(Bytecode {
+            instructions: vec![opcode::RETURN],
+            constants: Vec::new(),
+        });
+
+        assert_eq!(result, Err(RuntimeError::InvalidReturn(1000)));
+        assert_eq!(vm.stack.depth(), 0);
+    }
+}
@@ -0,0 +1,447 @@
+//! Pre-decoded instruction stream run by [`VM`](crate::VM).
+//!
+//! Decoding bytes on every step of the interpreter loop is slow: each
+//! instruction is looked up in the opcode table, its operands are assembled
+//! byte by byte and jumps are range checked as they are taken. Instead the
+//! whole stream is decoded once into [`Op`]s before running it. Jump targets
+//! are resolved to op indices and verified up front, and the stream always
+//! ends with [`Op::Halt`], so the loop can index it without bounds checks.
+//!
+//! While decoding, common sequences are fused into superinstructions, such as
+//! `GET_GLOBAL; CONSTANT; ADD` into [`Op::GlobalConstant`]. A sequence is only
+//! fused if no jump or function entry lands in the middle of it.
+
+use belvm_bytecode::opcode::Opcode;
+use belvm_bytecode::{Instruction, instructions};
+use belvm_gc::gc::GcHeap;
+
+use crate::errors::RuntimeError;
+use crate::ops;
+use crate::value::Value;
+
+/// Implementation of a binary operation, from [`ops`]
+pub(crate) type BinaryFn = fn(&Value, &Value, &mut GcHeap) -> Result<Value, RuntimeError>;
+
+/// A decoded instruction. Jump targets are indices into the decoded stream.
+#[derive(Clone, Copy)]
+pub(crate) enum Op {
+    Noop,
+    Pop,
+    Binary(BinaryFn),
+    Constant(usize),
+    True,
+    False,
+    Null,
+    Not,
+    Neg,
+    Jump(usize),
+    JumpIfFalse(usize),
+    SetGlobal(usize),
+    GetGlobal(usize),
+    SetLocal(usize),
+    GetLocal(usize),
+    Call(usize),
+    TailCall(usize),
+    Return,
+    ReturnValue,
+
+    /// An opcode the VM can't run yet. Running it is an error, but it may
+    /// never be reached.
+    Unsupported(u8),
+
+    /// End of the stream
+    Halt,
+
+    /// `SET_GLOBAL; POP`
+    StoreGlobal(usize),
+
+    /// `SET_LOCAL; POP`
+    StoreLocal(usize),
+
+    /// `GET_GLOBAL; CONSTANT; <binary>`
+    GlobalConstant {
+        op: BinaryFn,
+        global: usize,
+        constant: usize,
+    },
+
+    /// `GET_LOCAL; CONSTANT; <binary>`
+    LocalConstant {
+        op: BinaryFn,
+        local: usize,
+        constant: usize,
+    },
+
+    /// `GET_GLOBAL; GET_GLOBAL; <binary>`
+    GlobalGlobal {
+        op: BinaryFn,
+        left: usize,
+        right: usize,
+    },
+
+    /// `<binary>; JUMP_IF_FALSE`
+    BinaryJumpIfFalse {
+        op: BinaryFn,
+        target: usize,
+    },
+
+    /// `GET_GLOBAL; CONSTANT; <binary>; JUMP_IF_FALSE`, the usual loop
+    /// condition
+    GlobalConstantJumpIfFalse {
+        op: BinaryFn,
+        global: usize,
+        constant: usize,
+        target: usize,
+    },
+}
+
+/// A decoded instruction stream
+pub(crate) struct Program {
+    /// The decoded ops, ending with [`Op::Halt`]
+    pub ops: Vec<Op>,
+
+    /// Byte offset of every op, including the [`Op::Halt`] at the end
+    offsets: Vec<usize>,
+}
+
+impl Program {
+    /// Returns the index of the op starting at byte `offset`.
+    pub fn index_of(&self, offset: usize) -> Option<usize> {
+        self.offsets.binary_search(&offset).ok()
+    }
+}
+
+fn binary_fn(op: Opcode) -> Option<BinaryFn> {
+    Some(match op {
+        Opcode::Add => ops::add,
+        Opcode::Sub => ops::sub,
+        Opcode::Mul => ops::mul,
+        Opcode::Div => ops::div,
+        Opcode::Mod => ops::rem,
+        Opcode::Equal => ops::equal,
+        Opcode::NotEqual => ops::not_equal,
+        Opcode::LessThan => ops::less_than,
+        Opcode::LessThanEqual => ops::less_than_equal,
+        Opcode::And => ops::and,
+        Opcode::Or => ops::or,
+        Opcode::BitAnd => ops::bit_and,
+        Opcode::BitOr => ops::bit_or,
+        Opcode::BitXor => ops::bit_xor,
+        Opcode::BitSl => ops::shift_left,
+        Opcode::BitSr => ops::shift_right,
+        _ => return None,
+    })
+}
+
+/// Decodes and verifies `bytes`, fusing superinstructions if `fuse` is set.
+/// `constants` is the size of the constant pool, and `entries` are the offsets
+/// functions start at.
+pub(crate) fn decode(bytes: &[u8], constants: usize, entries: &[usize], fuse: bool) -> Result<Program, RuntimeError> {
+    let decoded = instructions(bytes).collect::<Result<Vec<Instruction>, _>>()?;
+
+    let mut offsets: Vec<usize> = decoded.iter().map(|instruction| instruction.offset).collect();
+    offsets.push(bytes.len());
+
+    // index of the instruction every jump lands on
+    let mut targets = vec![None; decoded.len()];
+    let mut is_target = vec![false; decoded.len() + 1];
+    for (i, instruction) in decoded.iter().enumerate() {
+        if !instruction.is_jump() {
+            continue;
+        }
+
+        let target = instruction
+            .jump_target()
+            .and_then(|target| offsets.binary_search(&target).ok())
+            .ok_or(RuntimeError::InvalidJump(instruction.offset))?;
+
+        targets[i] = Some(target);
+        is_target[target] = true;
+    }
+
+    for &entry in entries {
+        let entry = offsets
+            .binary_search(&entry)
+            .map_err(|_| RuntimeError::InvalidFunction(entry))?;
+        is_target[entry] = true;
+    }
+
+    // superinstructions load constants through these too, so they are
+    // checked along with them
+    let constant = decoded
+        .iter()
+        .find(|instruction| instruction.op == Opcode::Constant && instruction.operands[0] as usize >= constants);
+    if let Some(instruction) = constant {
+        return Err(RuntimeError::InvalidConstant(instruction.offset));
+    }
+
+    let op_at = |i: usize| decoded.get(i).map(|instruction| instruction.op);
+    let operand = |i: usize| decoded[i].operands[0] as usize;
+
+    // the `len` instructions starting at `i` can be fused if nothing jumps
+    // into them
+    let fusable = |i: usize, len: usize| fuse && i + len <= decoded.len() && !is_target[i + 1..i + len].contains(&true);
+
+    let mut ops = Vec::with_capacity(decoded.len() + 1);
+    let mut new_offsets = Vec::with_capacity(decoded.len() + 1);
+
+    // new index of every instruction, including the end of the stream
+    let mut remap = vec![0; decoded.len() + 1];
+
+    let mut i = 0;
+    while i < decoded.len() {
+        let instruction = &decoded[i];
+
+        let (op, len) = match instruction.op {
+            Opcode::GetGlobal
+                if fusable(i, 4)
+                    && op_at(i + 1) == Some(Opcode::Constant)
+                    && op_at(i + 3) == Some(Opcode::JumpIfFalse)
+                    && binary_fn(decoded[i + 2].op).is_some() =>
+            {
+                let op = Op::GlobalConstantJumpIfFalse {
+                    op: binary_fn(decoded[i + 2].op).unwrap(),
+                    global: operand(i),
+                    constant: operand(i + 1),
+                    target: targets[i + 3].unwrap(),
+                };
+
+                (op, 4)
+            },
+
+            Opcode::GetGlobal
+                if fusable(i, 3)
+                    && op_at(i + 1) == Some(Opcode::Constant)
+                    && binary_fn(decoded[i + 2].op).is_some() =>
+            {
+                let op = Op::GlobalConstant {
+                    op: binary_fn(decoded[i + 2].op).unwrap(),
+                    global: operand(i),
+                    constant: operand(i + 1),
+                };
+
+                (op, 3)
+            },
+
+            Opcode::GetGlobal
+                if fusable(i, 3)
+                    && op_at(i + 1) == Some(Opcode::GetGlobal)
+                    && binary_fn(decoded[i + 2].op).is_some() =>
+            {
+                let op = Op::GlobalGlobal {
+                    op: binary_fn(decoded[i + 2].op).unwrap(),
+                    left: operand(i),
+                    right: operand(i + 1),
+                };
+
+                (op, 3)
+            },
+
+            Opcode::GetLocal
+                if fusable(i, 3)
+                    && op_at(i + 1) == Some(Opcode::Constant)
+                    && binary_fn(decoded[i + 2].op).is_some() =>
+            {
+                let op = Op::LocalConstant {
+                    op: binary_fn(decoded[i + 2].op).unwrap(),
+                    local: operand(i),
+                    constant: operand(i + 1),
+                };
+
+                (op, 3)
+            },
+
+            Opcode::SetGlobal if fusable(i, 2) && op_at(i + 1) == Some(Opcode::Pop) => (Op::StoreGlobal(operand(i)), 2),
+
+            Opcode::SetLocal if fusable(i, 2) && op_at(i + 1) == Some(Opcode::Pop) => (Op::StoreLocal(operand(i)), 2),
+
+            op if fusable(i, 2) && op_at(i + 1) == Some(Opcode::JumpIfFalse) && binary_fn(op).is_some() => {
+                let op = Op::BinaryJumpIfFalse {
+                    op: binary_fn(op).unwrap(),
+                    target: targets[i + 1].unwrap(),
+                };
+
+                (op, 2)
+            },
+
+            op => {
+                let op = match op {
+                    Opcode::Noop => Op::Noop,
+                    Opcode::Pop => Op::Pop,
+                    Opcode::Constant => Op::Constant(operand(i)),
+                    Opcode::True => Op::True,
+                    Opcode::False => Op::False,
+                    Opcode::Null => Op::Null,
+                    Opcode::Bang => Op::Not,
+                    Opcode::Minus => Op::Neg,
+                    Opcode::Jump => Op::Jump(targets[i].unwrap()),
+                    Opcode::JumpIfFalse => Op::JumpIfFalse(targets[i].unwrap()),
+                    Opcode::SetGlobal => Op::SetGlobal(operand(i)),
+                    Opcode::GetGlobal => Op::GetGlobal(operand(i)),
+                    Opcode::SetLocal => Op::SetLocal(operand(i)),
+                    Opcode::GetLocal => Op::GetLocal(operand(i)),
+                    Opcode::Call => Op::Call(operand(i)),
+                    Opcode::TailCall => Op::TailCall(operand(i)),
+                    Opcode::Return => Op::Return,
+                    Opcode::ReturnValue => Op::ReturnValue,
+                    op => match binary_fn(op) {
+                        Some(op) => Op::Binary(op),
+                        None => Op::Unsupported(op.byte()),
+                    },
+                };
+
+                (op, 1)
+            },
+        };
+
+        remap[i..i + len].fill(ops.len());
+
+        ops.push(op);
+        new_offsets.push(instruction.offset);
+        i += len;
+    }
+
+    remap[decoded.len()] = ops.len();
+    ops.push(Op::Halt);
+    new_offsets.push(bytes.len());
+
+    for op in &mut ops {
+        match op {
+            Op::Jump(target)
+            | Op::JumpIfFalse(target)
+            | Op::BinaryJumpIfFalse { target, .. }
+            | Op::GlobalConstantJumpIfFalse { target, .. } => *target = remap[*target],
+            _ => {},
+        }
+    }
+
+    Ok(Program {
+        ops,
+        offsets: new_offsets,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use belvm_bytecode::opcode;
+
+    use super::*;
+
+    fn decode_ops(bytes: &[u8]) -> Vec<Op> {
+        decode(bytes, 1, &[], true).unwrap().ops
+    }
+
+    #[test]
+    fn fuses_loop_condition() {
+        let mut bytes = Vec::new();
+        bytes.extend(opcode::get_global(1));
+        bytes.extend(opcode::constant(0));
+        bytes.push(opcode::LESS_THAN);
+        bytes.extend(opcode::jump_if_false(3));
+        bytes.extend(opcode::jump(-13));
+
+        let ops = decode_ops(&bytes);
+
+        assert!(matches!(
+            ops[..],
+            [
+                Op::GlobalConstantJumpIfFalse {
+                    global: 1,
+                    constant: 0,
+                    target: 2,
+                    ..
+                },
+                Op::Jump(0),
+                Op::Halt
+            ]
+        ));
+    }
+
+    #[test]
+    fn keeps_jump_targets() {
+        // the jump lands on the CONSTANT, so it can't be fused away
+        let mut bytes = Vec::new();
+        bytes.extend(opcode::get_global(1));
+        bytes.extend(opcode::constant(0));
+        bytes.push(opcode::ADD);
+        bytes.extend(opcode::jump(-7));
+
+        let ops = decode_ops(&bytes);
+
+        assert!(matches!(
+            ops[..],
+            [Op::GetGlobal(1), Op::Constant(0), Op::Binary(_), Op::Jump(1), Op::Halt]
+        ));
+    }
+
+    #[test]
+    fn fuses_store() {
+        let mut bytes = Vec::new();
+        bytes.extend(opcode::set_global(1));
+        bytes.push(opcode::POP);
+
+        assert!(matches!(decode_ops(&bytes)[..], [Op::StoreGlobal(1), Op::Halt]));
+        assert!(matches!(
+            decode(&bytes, 1, &[], false).unwrap().ops[..],
+            [Op::SetGlobal(1), Op::Pop, Op::Halt]
+        ));
+    }
+
+    #[test]
+    fn rejects_jumps_between_instructions() {
+        let mut bytes = Vec::new();
+        bytes.extend(opcode::jump(1));
+        bytes.extend(opcode::constant(0));
+
+        assert!(matches!(
+            decode(&bytes, 1, &[], true),
+            Err(RuntimeError::InvalidJump(0))
+        ));
+    }
+
+    #[test]
+    fn rejects_constants_out_of_the_pool() {
+        let mut bytes = Vec::new();
+        bytes.extend(opcode::constant(5));
+
+        assert!(matches!(
+            decode(&bytes, 0, &[], true),
+            Err(RuntimeError::InvalidConstant(0))
+        ));
+
+        // fused into a superinstruction
+        let mut bytes = Vec::new();
+        bytes.extend(opcode::get_global(0));
+        bytes.extend(opcode::constant(1));
+        bytes.push(opcode::ADD);
+
+        assert!(matches!(
+            decode(&bytes, 1, &[], true),
+            Err(RuntimeError::InvalidConstant(3))
+        ));
+    }
+
+    #[test]
+    fn keeps_function_entries() {
+        let mut bytes = Vec::new();
+        bytes.extend(opcode::get_local(0));
+        bytes.extend(opcode::constant(0));
+        bytes.push(opcode::ADD);
+
+        assert!(matches!(
+            decode_ops(&bytes)[..],
+            [Op::LocalConstant { local: 0, .. }, Op::Halt]
+        ));
+
+        // a function starting at the CONSTANT
+        assert!(matches!(
+            decode(&bytes, 1, &[2], true).unwrap().ops[..],
+            [Op::GetLocal(0), Op::Constant(0), Op::Binary(_), Op::Halt]
+        ));
+
+        assert!(matches!(
+            decode(&bytes, 1, &[1], true),
+            Err(RuntimeError::InvalidFunction(1))
+        ));
+    }
+}
@@ -1,16 +1,33 @@
 //! Errors used by The Belalang Virtual Machine.
 
+use belvm_bytecode::DecodeError;
+
 #[derive(thiserror::Error, Debug, PartialEq)]
 pub enum RuntimeError {
     #[error("stack underflow")]
     StackUnderflow,
 
-    #[error("stack overflow")]
-    StackOverflow,
+    #[error("stack overflow at recursion depth {0}")]
+    StackOverflow(usize),
 
     #[error("unknown instruction: {0}")]
     UnknownInstruction(u8),
 
+    #[error("malformed instruction: {0}")]
+    MalformedInstruction(DecodeError),
+
+    #[error("jump at {0:#06x} does not land on an instruction")]
+    InvalidJump(usize),
+
+    #[error("function at {0:#06x} does not start on an instruction")]
+    InvalidFunction(usize),
+
+    #[error("constant at {0:#06x} is not in the constant pool")]
+    InvalidConstant(usize),
+
+    #[error("return address {0} does not point to an instruction")]
+    InvalidReturn(usize),
+
     #[error("unknown builtin function")]
     UnknownBuiltinFunction,
 
@@ -19,12 +36,30 @@ pub enum RuntimeError {
     #[error("attempt to call non-function")]
     NotAFunction,
 
+    #[error("function takes {arity} arguments but is called with {argc}")]
+    ArityMismatch { arity: usize, argc: usize },
+
     #[error("Integer overflow")]
     IntegerOverflow,
 
+    #[error("division by zero")]
+    DivisionByZero,
+
     #[error("type error")]
     TypeError,
 
     #[error("allocation failed")]
     AllocationFailed,
+
+    #[error("out of memory: heap limit of {0} bytes exceeded")]
+    OutOfMemory(usize),
+}
+
+impl From<DecodeError> for RuntimeError {
+    fn from(error: DecodeError) -> Self {
+        match error {
+            DecodeError::UnknownOpcode { byte, .. } => RuntimeError::UnknownInstruction(byte),
+            error => RuntimeError::MalformedInstruction(error),
+        }
+    }
 }
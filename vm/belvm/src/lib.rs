@@ -1,5 +1,11 @@
+mod constants;
 mod core;
+mod dispatch;
 pub mod errors;
+pub mod objects;
+mod ops;
+pub mod register;
 pub mod stack;
+pub mod value;
 
 pub use core::VM;
@@ -0,0 +1,40 @@
+//! Heap objects of The Belalang VM.
+
+use belvm_gc::gc::GcObjectHeader;
+use belvm_macros::belalang_object;
+
+/// A string allocated on the GC heap
+///
+/// String constants are interned by the compiler, and the VM allocates a
+/// single object per constant, so every load of the same literal shares it.
+#[belalang_object(name = "String")]
+pub struct StringObject {
+    pub value: String,
+}
+
+impl StringObject {
+    pub fn new(value: String) -> Self {
+        Self {
+            header: GcObjectHeader::new::<Self>(),
+            value,
+        }
+    }
+}
+
+/// An integer allocated on the GC heap
+///
+/// Integers that are too wide to be stored in a [`Value`](crate::value::Value)
+/// directly are boxed into one of these.
+#[belalang_object(name = "Integer")]
+pub struct IntegerObject {
+    pub value: i64,
+}
+
+impl IntegerObject {
+    pub fn new(value: i64) -> Self {
+        Self {
+            header: GcObjectHeader::new::<Self>(),
+            value,
+        }
+    }
+}
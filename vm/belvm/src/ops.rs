@@ -0,0 +1,161 @@
+//! Semantics of the arithmetic, comparison and logical operations.
+//!
+//! Both the stack VM and the register VM go through these, so the two
+//! instruction sets can't drift apart in what they compute. Results too wide
+//! to be stored inline are boxed on the heap of the VM running them.
+
+use belvm_gc::gc::GcHeap;
+
+use crate::errors::RuntimeError;
+use crate::value::Value;
+
+pub fn add(left: &Value, right: &Value, heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_integer(), right.as_integer()) {
+        (Some(a), Some(b)) => Value::integer(a.checked_add(b).ok_or(RuntimeError::IntegerOverflow)?, heap),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn sub(left: &Value, right: &Value, heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_integer(), right.as_integer()) {
+        (Some(a), Some(b)) => Value::integer(a.checked_sub(b).ok_or(RuntimeError::IntegerOverflow)?, heap),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn mul(left: &Value, right: &Value, heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_integer(), right.as_integer()) {
+        (Some(a), Some(b)) => Value::integer(a.checked_mul(b).ok_or(RuntimeError::IntegerOverflow)?, heap),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn div(left: &Value, right: &Value, heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_integer(), right.as_integer()) {
+        (Some(_), Some(0)) => Err(RuntimeError::DivisionByZero),
+        (Some(a), Some(b)) => Value::integer(a.checked_div(b).ok_or(RuntimeError::IntegerOverflow)?, heap),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn rem(left: &Value, right: &Value, heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_integer(), right.as_integer()) {
+        (Some(_), Some(0)) => Err(RuntimeError::DivisionByZero),
+        (Some(a), Some(b)) => Value::integer(a.checked_rem(b).ok_or(RuntimeError::IntegerOverflow)?, heap),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn equal(left: &Value, right: &Value, _heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    if let (Some(a), Some(b)) = (left.as_integer(), right.as_integer()) {
+        return Ok(Value::boolean(a == b));
+    }
+
+    match (left.as_boolean(), right.as_boolean()) {
+        (Some(a), Some(b)) => Ok(Value::boolean(a == b)),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn not_equal(left: &Value, right: &Value, _heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    if let (Some(a), Some(b)) = (left.as_integer(), right.as_integer()) {
+        return Ok(Value::boolean(a != b));
+    }
+
+    match (left.as_boolean(), right.as_boolean()) {
+        (Some(a), Some(b)) => Ok(Value::boolean(a != b)),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn less_than(left: &Value, right: &Value, _heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_integer(), right.as_integer()) {
+        (Some(a), Some(b)) => Ok(Value::boolean(a < b)),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn less_than_equal(left: &Value, right: &Value, _heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_integer(), right.as_integer()) {
+        (Some(a), Some(b)) => Ok(Value::boolean(a <= b)),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn and(left: &Value, right: &Value, _heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_boolean(), right.as_boolean()) {
+        (Some(a), Some(b)) => Ok(Value::boolean(a && b)),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn or(left: &Value, right: &Value, _heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_boolean(), right.as_boolean()) {
+        (Some(a), Some(b)) => Ok(Value::boolean(a || b)),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn bit_and(left: &Value, right: &Value, heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_integer(), right.as_integer()) {
+        (Some(a), Some(b)) => Value::integer(a & b, heap),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn bit_or(left: &Value, right: &Value, heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_integer(), right.as_integer()) {
+        (Some(a), Some(b)) => Value::integer(a | b, heap),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn bit_xor(left: &Value, right: &Value, heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_integer(), right.as_integer()) {
+        (Some(a), Some(b)) => Value::integer(a ^ b, heap),
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn shift_left(left: &Value, right: &Value, heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_integer(), right.as_integer()) {
+        (Some(a), Some(b)) => {
+            let shifted = u32::try_from(b).ok().and_then(|b| a.checked_shl(b));
+            Value::integer(shifted.ok_or(RuntimeError::IntegerOverflow)?, heap)
+        },
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn shift_right(left: &Value, right: &Value, heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match (left.as_integer(), right.as_integer()) {
+        (Some(a), Some(b)) => {
+            let shifted = u32::try_from(b).ok().and_then(|b| a.checked_shr(b));
+            Value::integer(shifted.ok_or(RuntimeError::IntegerOverflow)?, heap)
+        },
+        (_, _) => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn not(right: &Value, _heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match right.as_boolean() {
+        Some(a) => Ok(Value::boolean(!a)),
+        None => Err(RuntimeError::TypeError),
+    }
+}
+
+pub fn neg(right: &Value, heap: &mut GcHeap) -> Result<Value, RuntimeError> {
+    match right.as_integer() {
+        Some(a) => Value::integer(a.checked_neg().ok_or(RuntimeError::IntegerOverflow)?, heap),
+        None => Err(RuntimeError::TypeError),
+    }
+}
+
+/// Returns whether a conditional jump treats `value` as true.
+pub fn is_truthy(value: &Value) -> Result<bool, RuntimeError> {
+    if let Some(a) = value.as_integer() {
+        return Ok(a > 0);
+    }
+
+    value.as_boolean().ok_or(RuntimeError::TypeError)
+}
@@ -0,0 +1,419 @@
+//! Experimental register-based Virtual Machine.
+//!
+//! This runs the [`register`](belvm_bytecode::register) instruction set
+//! instead of the stack-based one run by [`VM`](crate::VM). Operands are read
+//! from and results written to the slots of a frame directly, so most
+//! statements take fewer instructions than on the stack VM. Arithmetic and
+//! comparisons share their semantics with the stack VM.
+//!
+//! There are no call frames yet, so the whole program runs in a single frame.
+//! Global variables live in the register matching their symbol index and the
+//! compiler places its temporaries after them.
+
+use belvm_bytecode::register::Opcode;
+use belvm_bytecode::{Bytecode, DecodeError};
+use belvm_gc::gc::{DEFAULT_NURSERY_SIZE, GcHeap, GcStats, Trace, Tracer};
+use belvm_gc::snapshot::HeapSnapshot;
+
+use crate::constants::ConstantPool;
+use crate::errors::RuntimeError;
+use crate::ops;
+use crate::stack::StackValue;
+use crate::value::Value;
+
+/// Number of registers in a frame. Register operands are 8-bit, so this is
+/// enough to make every register operand a valid index.
+const REGISTER_COUNT: usize = 256;
+
+/// The register-based Virtual Machine.
+pub struct RegisterVM {
+    /// The offset of the instruction the VM is about to run.
+    ip: usize,
+
+    /// The list of register instructions the VM is executing.
+    instructions: Vec<u8>,
+
+    /// The list of constants the VM is working with.
+    constants: ConstantPool,
+
+    /// The slots of the frame.
+    registers: Box<[Value; REGISTER_COUNT]>,
+
+    /// The value the program returned.
+    result: Value,
+
+    /// The heap the objects of the VM live on. Dropping the VM frees them.
+    heap: GcHeap,
+}
+
+impl Default for RegisterVM {
+    fn default() -> Self {
+        let mut heap = GcHeap::default();
+        heap.set_nursery_size(DEFAULT_NURSERY_SIZE);
+
+        Self {
+            ip: 0,
+            instructions: Vec::new(),
+            constants: ConstantPool::default(),
+            registers: Box::new([const { Value::NULL }; REGISTER_COUNT]),
+            result: Value::NULL,
+            heap,
+        }
+    }
+}
+
+impl RegisterVM {
+    /// Executes the provided [`Bytecode`] program, which has to be encoded
+    /// with the register instruction set.
+    pub fn run(&mut self, code: Bytecode) -> Result<(), RuntimeError> {
+        self.constants.extend(code.constants);
+        self.instructions.extend(code.instructions);
+
+        while self.ip < self.instructions.len() {
+            // every value is in a register between two instructions, so
+            // garbage can be collected anywhere
+            #[cfg(feature = "gc-stress")]
+            self.maybe_collect()?;
+
+            let offset = self.ip;
+            let byte = self.instructions[offset];
+
+            let op = Opcode::from_byte(byte).ok_or(RuntimeError::UnknownInstruction(byte))?;
+            let size = op.info().size();
+
+            if offset + size > self.instructions.len() {
+                return Err(RuntimeError::MalformedInstruction(DecodeError::Truncated {
+                    offset,
+                    name: op.name(),
+                }));
+            }
+
+            self.ip = offset + size;
+
+            match op {
+                Opcode::Noop => {},
+
+                Opcode::Move => {
+                    let (dst, src) = (self.register(offset + 1), self.register(offset + 2));
+                    self.registers[dst] = self.registers[src];
+                },
+
+                Opcode::Add => self.binary(offset, ops::add)?,
+                Opcode::Sub => self.binary(offset, ops::sub)?,
+                Opcode::Mul => self.binary(offset, ops::mul)?,
+                Opcode::Div => self.binary(offset, ops::div)?,
+                Opcode::Mod => self.binary(offset, ops::rem)?,
+
+                Opcode::Constant => {
+                    let dst = self.register(offset + 1);
+                    let index = u16::from_be_bytes([self.instructions[offset + 2], self.instructions[offset + 3]]);
+
+                    self.registers[dst] = self.constants.load(index as usize, &mut self.heap)?;
+                },
+
+                Opcode::True => {
+                    let dst = self.register(offset + 1);
+                    self.registers[dst] = Value::boolean(true);
+                },
+
+                Opcode::False => {
+                    let dst = self.register(offset + 1);
+                    self.registers[dst] = Value::boolean(false);
+                },
+
+                Opcode::Null => {
+                    let dst = self.register(offset + 1);
+                    self.registers[dst] = Value::NULL;
+                },
+
+                Opcode::Equal => self.binary(offset, ops::equal)?,
+                Opcode::NotEqual => self.binary(offset, ops::not_equal)?,
+                Opcode::LessThan => self.binary(offset, ops::less_than)?,
+                Opcode::LessThanEqual => self.binary(offset, ops::less_than_equal)?,
+                Opcode::And => self.binary(offset, ops::and)?,
+                Opcode::Or => self.binary(offset, ops::or)?,
+                Opcode::BitAnd => self.binary(offset, ops::bit_and)?,
+                Opcode::BitOr => self.binary(offset, ops::bit_or)?,
+                Opcode::BitXor => self.binary(offset, ops::bit_xor)?,
+                Opcode::BitSl => self.binary(offset, ops::shift_left)?,
+                Opcode::BitSr => self.binary(offset, ops::shift_right)?,
+
+                Opcode::Bang => self.unary(offset, ops::not)?,
+                Opcode::Minus => self.unary(offset, ops::neg)?,
+
+                // loops jump back, so this is where garbage piles up
+                Opcode::Jump => {
+                    let relative = self.offset_at(offset + 1);
+                    self.jump(offset, relative)?;
+
+                    self.maybe_collect()?;
+                },
+
+                Opcode::JumpIfFalse => {
+                    let cond = self.register(offset + 1);
+
+                    if !ops::is_truthy(&self.registers[cond])? {
+                        let relative = self.offset_at(offset + 2);
+                        self.jump(offset, relative)?;
+                    }
+                },
+
+                // there is a single frame, so returning ends the program
+                Opcode::Return => {
+                    self.result = Value::NULL;
+                    self.ip = self.instructions.len();
+                },
+
+                Opcode::ReturnValue => {
+                    let src = self.register(offset + 1);
+
+                    self.result = self.registers[src];
+                    self.ip = self.instructions.len();
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs a three-address instruction at `offset`.
+    fn binary<F>(&mut self, offset: usize, f: F) -> Result<(), RuntimeError>
+    where
+        F: FnOnce(&Value, &Value, &mut GcHeap) -> Result<Value, RuntimeError>,
+    {
+        let dst = self.register(offset + 1);
+        let left = self.register(offset + 2);
+        let right = self.register(offset + 3);
+
+        self.registers[dst] = f(&self.registers[left], &self.registers[right], &mut self.heap)?;
+
+        Ok(())
+    }
+
+    /// Runs a two-address instruction at `offset`.
+    fn unary<F>(&mut self, offset: usize, f: F) -> Result<(), RuntimeError>
+    where
+        F: FnOnce(&Value, &mut GcHeap) -> Result<Value, RuntimeError>,
+    {
+        let dst = self.register(offset + 1);
+        let src = self.register(offset + 2);
+
+        self.registers[dst] = f(&self.registers[src], &mut self.heap)?;
+
+        Ok(())
+    }
+
+    /// Reads the register operand at `offset`.
+    fn register(&self, offset: usize) -> usize {
+        self.instructions[offset] as usize
+    }
+
+    /// Reads the jump offset operand at `offset`.
+    fn offset_at(&self, offset: usize) -> i64 {
+        i16::from_be_bytes([self.instructions[offset], self.instructions[offset + 1]]) as i64
+    }
+
+    /// Moves the instruction pointer relative to the next instruction, for
+    /// the jump at `offset`.
+    fn jump(&mut self, offset: usize, relative: i64) -> Result<(), RuntimeError> {
+        self.ip = self
+            .ip
+            .checked_add_signed(relative as isize)
+            .filter(|&target| target <= self.instructions.len())
+            .ok_or(RuntimeError::InvalidJump(offset))?;
+
+        Ok(())
+    }
+
+    /// Collects garbage if enough was allocated since the last collection, or
+    /// only the nursery if it is full, failing if the heap is still over its
+    /// limit afterwards.
+    fn maybe_collect(&mut self) -> Result<(), RuntimeError> {
+        let Self {
+            heap,
+            registers,
+            result,
+            constants,
+            ..
+        } = self;
+
+        let trace_roots = trace_roots(registers, result, constants);
+
+        if heap.should_collect() {
+            heap.collect(trace_roots);
+        } else if heap.should_collect_minor() {
+            heap.collect_minor(trace_roots);
+        } else {
+            return Ok(());
+        }
+
+        match self.heap.limit() {
+            Some(limit) if self.heap.is_over_limit() => Err(RuntimeError::OutOfMemory(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Frees the objects the VM can no longer reach from its registers and
+    /// constants.
+    pub fn collect_garbage(&mut self) {
+        let Self {
+            heap,
+            registers,
+            result,
+            constants,
+            ..
+        } = self;
+
+        heap.collect(trace_roots(registers, result, constants));
+    }
+
+    /// Returns the heap the objects of the VM live on.
+    pub fn heap(&self) -> &GcHeap {
+        &self.heap
+    }
+
+    /// Returns what is on the heap of the VM and what collecting it cost.
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    /// Takes a snapshot of the objects on the heap of the VM, and of what
+    /// points to them. See [`GcHeap::snapshot`].
+    pub fn heap_snapshot(&self) -> HeapSnapshot {
+        self.heap
+            .snapshot(trace_roots(&self.registers, &self.result, &self.constants))
+    }
+
+    /// Sets the most bytes of memory the heap of the VM can hold. See
+    /// [`VM::set_heap_limit`](crate::VM::set_heap_limit).
+    pub fn set_heap_limit(&mut self, limit: Option<usize>) {
+        self.heap.set_limit(limit);
+    }
+
+    /// Sets the least bytes the heap can take before the VM collects it. See
+    /// [`VM::set_gc_threshold`](crate::VM::set_gc_threshold).
+    pub fn set_gc_threshold(&mut self, bytes: usize) {
+        self.heap.set_threshold(bytes);
+    }
+
+    /// Sets how much the heap can grow past what is live before the VM
+    /// collects it again. See
+    /// [`VM::set_gc_growth_factor`](crate::VM::set_gc_growth_factor).
+    pub fn set_gc_growth_factor(&mut self, factor: f64) {
+        self.heap.set_growth_factor(factor);
+    }
+
+    /// Sets the bytes the nursery of the heap can take. See
+    /// [`VM::set_gc_nursery_size`](crate::VM::set_gc_nursery_size).
+    pub fn set_gc_nursery_size(&mut self, bytes: usize) {
+        self.heap.set_nursery_size(bytes);
+    }
+
+    /// Returns the value the program returned, or null if it didn't return
+    /// one.
+    pub fn result(&self) -> StackValue {
+        self.result.unpack()
+    }
+
+    /// Returns the value of the global variable at `index`.
+    pub fn global(&self, index: usize) -> Option<StackValue> {
+        self.registers.get(index).map(Value::unpack)
+    }
+}
+
+/// Returns a function marking the values the VM holds, naming them after
+/// where they are held.
+fn trace_roots<'a>(
+    registers: &'a [Value; REGISTER_COUNT],
+    result: &'a Value,
+    constants: &'a ConstantPool,
+) -> impl Fn(&mut Tracer) + Copy + 'a {
+    move |tracer| {
+        tracer.root("registers");
+        registers.trace(tracer);
+        tracer.root("result");
+        result.trace(tracer);
+        tracer.root("constants");
+        constants.trace(tracer);
+    }
+}
+
+impl Drop for RegisterVM {
+    fn drop(&mut self) {
+        self.instructions.clear();
+        self.constants.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use belvm_bytecode::register;
+    use belvm_bytecode::{Bytecode, Constant};
+
+    use super::*;
+
+    fn run(instructions: Vec<u8>, constants: Vec<Constant>) -> Result<RegisterVM, RuntimeError> {
+        let mut vm = RegisterVM::default();
+        vm.run(Bytecode {
+            instructions,
+            constants,
+        })?;
+
+        Ok(vm)
+    }
+
+    #[test]
+    fn three_address_add() {
+        let mut instructions = Vec::new();
+        instructions.extend(register::constant(1, 0));
+        instructions.extend(register::constant(2, 1));
+        instructions.extend(register::add(0, 1, 2));
+
+        let vm = run(instructions, vec![Constant::Integer(12), Constant::Integer(5)]).unwrap();
+
+        assert!(matches!(vm.global(0), Some(StackValue::Integer(17))));
+    }
+
+    #[test]
+    fn jump_if_false() {
+        let mut instructions = Vec::new();
+        instructions.extend(register::r#false(1));
+        instructions.extend(register::jump_if_false(1, 2));
+        instructions.extend(register::r#true(0));
+        instructions.push(register::RETURN);
+
+        let vm = run(instructions, Vec::new()).unwrap();
+
+        assert!(matches!(vm.global(0), Some(StackValue::Null)));
+    }
+
+    #[test]
+    fn jump_out_of_bounds() {
+        let mut instructions = Vec::new();
+        instructions.extend(register::r#true(0));
+        instructions.extend(register::jump(-10));
+
+        assert_eq!(
+            run(instructions.clone(), Vec::new()).err(),
+            Some(RuntimeError::InvalidJump(2))
+        );
+
+        instructions.truncate(2);
+        instructions.extend(register::jump(10));
+
+        assert_eq!(run(instructions, Vec::new()).err(), Some(RuntimeError::InvalidJump(2)));
+    }
+
+    #[test]
+    fn truncated_instruction() {
+        let result = run(vec![register::ADD, 0, 1], Vec::new());
+
+        assert!(matches!(
+            result,
+            Err(RuntimeError::MalformedInstruction(DecodeError::Truncated {
+                offset: 0,
+                ..
+            }))
+        ));
+    }
+}
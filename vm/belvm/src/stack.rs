@@ -1,20 +1,36 @@
+use belvm_gc::gc::{GcPtr, Trace, Tracer};
+
 use crate::errors::RuntimeError;
+use crate::objects::StringObject;
+use crate::value::Value;
 
-/// Default stack size of Belalang VM
-///
-/// This is currently a good enough stack size for Belalang VM. I don't really
-/// know how stack sizes are supposed to be implemented for efficiency. I still
-/// have stuff to read and things to explore.
-const STACK_SIZE: usize = 4096;
+/// Default number of frames the stack can hold
+pub const DEFAULT_RECURSION_LIMIT: usize = 1024;
+
+/// Most values the stack can hold, across all of its frames. This keeps a
+/// runaway program from eating all the memory before it hits the recursion
+/// limit.
+const MAX_STACK_SIZE: usize = 1 << 20;
+
+/// Values the stack has room for when it is created. It grows from there.
+const INITIAL_CAPACITY: usize = 256;
 
-/// Values that live on the stack
-#[derive(Default, Debug)]
+/// Values that live on the stack, unpacked from their [`Value`] form so they
+/// can be matched on
+#[derive(Default, Debug, Clone)]
 pub enum StackValue {
     Boolean(bool),
     Integer(i64),
+    Float(f64),
+
+    /// Pointer to a string on the GC heap
+    String(GcPtr<StringObject>),
 
     /// Pointer to an address in the bytecode
-    AddressPtr(u8),
+    AddressPtr(usize),
+
+    /// A function, by the index of its constant
+    Function(usize),
 
     /// Null value in the stack
     ///
@@ -31,12 +47,18 @@ pub enum StackValue {
 
 /// Belalang VM's stack implementation
 ///
-/// This stack is both the call stack and the frame stack.
+/// This stack is both the call stack and the frame stack. It starts small and
+/// grows as values are pushed, up to [`MAX_STACK_SIZE`] values and a
+/// configurable number of frames.
 pub struct Stack {
-    stack: [StackValue; STACK_SIZE],
-    cap: usize,
-    sp: usize,
+    values: Vec<Value>,
     fp: usize,
+
+    /// Number of frames on the stack
+    depth: usize,
+
+    /// Most frames the stack can hold
+    recursion_limit: usize,
 }
 
 impl Default for Stack {
@@ -45,92 +67,162 @@ impl Default for Stack {
     }
 }
 
-impl Drop for Stack {
-    fn drop(&mut self) {
-        while self.pop().is_ok() {}
-    }
-}
-
 impl Stack {
     /// Creates a new stack
     ///
     /// Pretty self explainatory.
     pub fn new() -> Self {
         Self {
-            stack: [const { StackValue::Null }; STACK_SIZE],
-            cap: STACK_SIZE,
-            sp: 0,
+            values: Vec::with_capacity(INITIAL_CAPACITY),
             fp: 0,
+            depth: 0,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
         }
     }
 
     /// Returns the stack size
     pub fn size(&self) -> usize {
-        self.sp
+        self.values.len()
+    }
+
+    /// Returns the number of frames on the stack
+    pub fn depth(&self) -> usize {
+        self.depth
     }
 
-    /// Pushes a new [`StackValue`] to the stack
-    pub fn push(&mut self, elem: StackValue) -> Result<(), RuntimeError> {
-        if self.sp >= self.cap {
-            return Err(RuntimeError::StackOverflow);
+    /// Sets the most frames the stack can hold. Pushing a frame past it is a
+    /// [`RuntimeError::StackOverflow`].
+    pub fn set_recursion_limit(&mut self, limit: usize) {
+        self.recursion_limit = limit;
+    }
+
+    /// Pushes a new [`Value`] to the stack
+    pub fn push(&mut self, elem: Value) -> Result<(), RuntimeError> {
+        if self.values.len() >= MAX_STACK_SIZE {
+            return Err(RuntimeError::StackOverflow(self.depth));
         }
 
-        self.stack[self.sp] = elem;
-        self.sp += 1;
+        self.values.push(elem);
 
         Ok(())
     }
 
-    /// Pops a [`StackValue`] from the stack
-    ///
-    /// This function uses [`std::mem::take`] to get the top-most value of the
-    /// stack, leaving a [`StackValue::Null`] behind.
-    pub fn pop(&mut self) -> Result<StackValue, RuntimeError> {
-        if self.sp == 0 {
-            Err(RuntimeError::StackUnderflow)
-        } else {
-            self.sp -= 1;
-            Ok(std::mem::take(&mut self.stack[self.sp]))
-        }
+    /// Pops a [`Value`] from the stack
+    pub fn pop(&mut self) -> Result<Value, RuntimeError> {
+        self.values.pop().ok_or(RuntimeError::StackUnderflow)
     }
 
-    /// Gets the top-most [`StackValue`] value from the stack
+    /// Gets the top-most [`Value`] value from the stack
     ///
     /// Returns the reference to the top-most value, and does not remove it.
-    pub fn top(&mut self) -> Option<&StackValue> {
-        if self.sp == 0 {
-            None
-        } else {
-            Some(&self.stack[self.sp - 1])
-        }
+    pub fn top(&mut self) -> Option<&Value> {
+        self.values.last()
     }
 
     /// Pushes a new stack frame to the stack
     ///
-    /// Typically used when going into a function scope.
-    pub fn push_frame(&mut self, locals_count: u8, return_address: u8) -> Result<(), RuntimeError> {
-        self.push(StackValue::AddressPtr(return_address))?;
-        self.push(StackValue::AddressPtr(self.fp as u8))?;
-        self.fp = self.sp;
-
-        for _ in 0..locals_count {
-            self.push(StackValue::Null)?;
+    /// The top `arity` values are the arguments, the first one being the
+    /// deepest. They become the first locals of the frame, and the rest of
+    /// the locals start out null. Typically used when going into a function
+    /// scope.
+    pub fn push_frame(&mut self, arity: usize, locals_count: usize, return_address: usize) -> Result<(), RuntimeError> {
+        if self.depth >= self.recursion_limit {
+            return Err(RuntimeError::StackOverflow(self.depth));
+        }
+
+        let base = self.arguments_base(arity)?;
+
+        // the return address and frame pointer go below the arguments
+        self.push(Value::address(return_address))?;
+        self.push(Value::address(self.fp))?;
+        self.values[base..].rotate_right(2);
+
+        self.fp = base + 2;
+        self.depth += 1;
+
+        for _ in arity..locals_count {
+            self.push(Value::NULL)?;
         }
 
         Ok(())
     }
 
-    /// Pops a stack frame from the stack
+    /// Replaces the locals of the current frame with the top `arity` values,
+    /// keeping its return address. Used for tail calls, so calling doesn't
+    /// grow the stack.
+    pub fn reuse_frame(&mut self, arity: usize, locals_count: usize) -> Result<(), RuntimeError> {
+        let base = self.arguments_base(arity)?;
+        if base < self.fp {
+            return Err(RuntimeError::StackUnderflow);
+        }
+
+        for i in 0..arity {
+            self.values.swap(self.fp + i, base + i);
+        }
+
+        self.values.truncate(self.fp + arity);
+        self.values.resize(self.fp + locals_count, Value::NULL);
+
+        Ok(())
+    }
+
+    /// Pops a stack frame from the stack, returning its return address
     ///
     /// Typically used when going out of a function scope
-    pub fn pop_frame(&mut self) -> Result<StackValue, RuntimeError> {
-        self.sp = self.fp;
+    pub fn pop_frame(&mut self) -> Result<usize, RuntimeError> {
+        if self.depth == 0 {
+            return Err(RuntimeError::StackUnderflow);
+        }
+
+        self.values.truncate(self.fp);
+
+        let fp = self.pop()?.as_address().ok_or(RuntimeError::StackUnderflow)?;
+        let return_address = self.pop()?.as_address().ok_or(RuntimeError::StackUnderflow)?;
+
+        self.fp = fp;
+        self.depth -= 1;
+
+        Ok(return_address)
+    }
 
-        if let StackValue::AddressPtr(v) = self.pop()? {
-            self.fp = v.into();
+    /// Pops every frame, leaving the values that were below the first one.
+    pub fn unwind(&mut self) {
+        while self.depth > 0 {
+            if self.pop_frame().is_err() {
+                // a frame was clobbered, so where the first one starts is
+                // lost along with everything above it
+                self.values.clear();
+                self.fp = 0;
+                self.depth = 0;
+            }
         }
+    }
+
+    /// Returns the local at `index` of the current frame.
+    pub fn local(&self, index: usize) -> Result<&Value, RuntimeError> {
+        self.values.get(self.fp + index).ok_or(RuntimeError::StackUnderflow)
+    }
+
+    /// Sets the local at `index` of the current frame.
+    pub fn set_local(&mut self, index: usize, value: Value) -> Result<(), RuntimeError> {
+        let local = self
+            .values
+            .get_mut(self.fp + index)
+            .ok_or(RuntimeError::StackUnderflow)?;
+        *local = value;
+
+        Ok(())
+    }
+
+    /// Index of the first of the top `arity` values
+    fn arguments_base(&self, arity: usize) -> Result<usize, RuntimeError> {
+        self.values.len().checked_sub(arity).ok_or(RuntimeError::StackUnderflow)
+    }
+}
 
-        self.pop()
+impl Trace for Stack {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.values.trace(tracer);
     }
 }
 
@@ -138,15 +230,22 @@ impl Stack {
 mod tests {
     #![allow(unused_allocation)]
 
+    use belvm_gc::gc::GcHeap;
+
     use super::*;
 
+    /// Creates an integer that fits in a value, so it needs no heap.
+    fn int(value: i64) -> Value {
+        Value::integer(value, &mut GcHeap::default()).unwrap()
+    }
+
     #[test]
     fn push() {
         let mut stack = Stack::new();
 
-        stack.push(StackValue::Integer(10)).unwrap();
+        stack.push(int(10)).unwrap();
 
-        assert!(matches!(stack.top().unwrap(), StackValue::Integer(10)));
+        assert!(matches!(stack.top().unwrap().unpack(), StackValue::Integer(10)));
 
         drop(stack);
     }
@@ -155,13 +254,13 @@ mod tests {
     fn pop() {
         let mut stack = Stack::new();
 
-        stack.push(StackValue::Integer(12)).unwrap();
-        stack.push(StackValue::Integer(11)).unwrap();
-        stack.push(StackValue::Integer(10)).unwrap();
+        stack.push(int(12)).unwrap();
+        stack.push(int(11)).unwrap();
+        stack.push(int(10)).unwrap();
 
-        assert!(matches!(stack.pop().unwrap(), StackValue::Integer(10)));
-        assert!(matches!(stack.pop().unwrap(), StackValue::Integer(11)));
-        assert!(matches!(stack.pop().unwrap(), StackValue::Integer(12)));
+        assert!(matches!(stack.pop().unwrap().unpack(), StackValue::Integer(10)));
+        assert!(matches!(stack.pop().unwrap().unpack(), StackValue::Integer(11)));
+        assert!(matches!(stack.pop().unwrap().unpack(), StackValue::Integer(12)));
 
         assert!(matches!(stack.pop(), Err(RuntimeError::StackUnderflow)));
     }
@@ -170,16 +269,17 @@ mod tests {
     fn push_frame() {
         let mut stack = Stack::new();
 
-        stack.push_frame(3, 12).unwrap();
+        stack.push_frame(0, 3, 12).unwrap();
 
         assert_eq!(stack.fp, 2);
-        assert_eq!(stack.sp, 5);
-
-        assert!(matches!(stack.pop().unwrap(), StackValue::Null)); // local 1
-        assert!(matches!(stack.pop().unwrap(), StackValue::Null)); // local 2
-        assert!(matches!(stack.pop().unwrap(), StackValue::Null)); // local 3
-        assert!(matches!(stack.pop().unwrap(), StackValue::AddressPtr(0))); // fp
-        assert!(matches!(stack.pop().unwrap(), StackValue::AddressPtr(12))); // return address
+        assert_eq!(stack.size(), 5);
+        assert_eq!(stack.depth(), 1);
+
+        assert!(matches!(stack.pop().unwrap().unpack(), StackValue::Null)); // local 1
+        assert!(matches!(stack.pop().unwrap().unpack(), StackValue::Null)); // local 2
+        assert!(matches!(stack.pop().unwrap().unpack(), StackValue::Null)); // local 3
+        assert!(matches!(stack.pop().unwrap().unpack(), StackValue::AddressPtr(0))); // fp
+        assert!(matches!(stack.pop().unwrap().unpack(), StackValue::AddressPtr(12))); // return address
         assert!(matches!(stack.pop(), Err(RuntimeError::StackUnderflow))); // bottom of stack
     }
 
@@ -187,10 +287,104 @@ mod tests {
     fn pop_frame() {
         let mut stack = Stack::new();
 
-        stack.push_frame(3, 12).unwrap();
-        stack.pop_frame().unwrap();
+        stack.push_frame(0, 3, 12).unwrap();
+        assert_eq!(stack.pop_frame().unwrap(), 12);
 
-        assert_eq!(stack.sp, 0);
+        assert_eq!(stack.size(), 0);
         assert_eq!(stack.fp, 0);
+        assert_eq!(stack.depth(), 0);
+    }
+
+    #[test]
+    fn pop_frame_checks_saved_addresses() {
+        let mut stack = Stack::new();
+
+        // a callee that popped its frame pointer and pushed something else
+        stack.push_frame(0, 0, 12).unwrap();
+        stack.pop().unwrap();
+        stack.push(int(1)).unwrap();
+
+        assert!(matches!(stack.pop_frame(), Err(RuntimeError::StackUnderflow)));
+    }
+
+    #[test]
+    fn full_width_addresses() {
+        let mut stack = Stack::new();
+
+        stack.push_frame(0, 0, 70_000).unwrap();
+        for _ in 0..300 {
+            stack.push(Value::NULL).unwrap();
+        }
+        stack.push_frame(0, 0, 80_000).unwrap();
+
+        assert_eq!(stack.pop_frame().unwrap(), 80_000);
+        assert_eq!(stack.fp, 2);
+
+        assert_eq!(stack.pop_frame().unwrap(), 70_000);
+    }
+
+    #[test]
+    fn grows() {
+        let mut stack = Stack::new();
+
+        for i in 0..10_000 {
+            stack.push(int(i)).unwrap();
+        }
+
+        assert_eq!(stack.size(), 10_000);
+        assert!(matches!(stack.pop().unwrap().unpack(), StackValue::Integer(9_999)));
+    }
+
+    #[test]
+    fn recursion_limit() {
+        let mut stack = Stack::new();
+        stack.set_recursion_limit(3);
+
+        for _ in 0..3 {
+            stack.push_frame(0, 1, 0).unwrap();
+        }
+
+        assert!(matches!(stack.push_frame(0, 1, 0), Err(RuntimeError::StackOverflow(3))));
+
+        stack.pop_frame().unwrap();
+        stack.push_frame(0, 1, 0).unwrap();
+    }
+
+    #[test]
+    fn arguments_become_locals() {
+        let mut stack = Stack::new();
+
+        stack.push(int(7)).unwrap();
+        stack.push(int(1)).unwrap();
+        stack.push(int(2)).unwrap();
+        stack.push_frame(2, 3, 12).unwrap();
+
+        assert_eq!(stack.size(), 6);
+        assert_eq!(stack.local(0).unwrap().as_integer(), Some(1));
+        assert_eq!(stack.local(1).unwrap().as_integer(), Some(2));
+        assert!(stack.local(2).unwrap().is_null());
+
+        assert_eq!(stack.pop_frame().unwrap(), 12);
+        assert_eq!(stack.size(), 1);
+    }
+
+    #[test]
+    fn reuse_frame() {
+        let mut stack = Stack::new();
+
+        stack.push(int(1)).unwrap();
+        stack.push_frame(1, 2, 12).unwrap();
+        stack.set_local(1, int(2)).unwrap();
+
+        // a temporary, then the arguments of the tail call
+        stack.push(Value::NULL).unwrap();
+        stack.push(int(3)).unwrap();
+        stack.reuse_frame(1, 2).unwrap();
+
+        assert_eq!(stack.depth(), 1);
+        assert_eq!(stack.size(), 4);
+        assert_eq!(stack.local(0).unwrap().as_integer(), Some(3));
+        assert!(stack.local(1).unwrap().is_null());
+        assert_eq!(stack.pop_frame().unwrap(), 12);
     }
 }
@@ -0,0 +1,314 @@
+//! Compact value representation of The Belalang VM.
+//!
+//! A [`Value`] is a single NaN-boxed 64-bit word. Floats are stored as they
+//! are, with every NaN folded into one canonical quiet NaN. That leaves the
+//! negative quiet NaNs free to carry everything else:
+//!
+//! ```text
+//! 1 11111111111 1 TTT PPPPPPPP...PPPPPPPP
+//! ^ ^           ^ ^   ^
+//! | exponent    | tag 48-bit payload
+//! sign          quiet
+//! ```
+//!
+//! The payload holds a boolean, a return address, a function, a 48-bit integer
+//! or a pointer to an object on the GC heap. Integers that don't fit in 48 bits
+//! are boxed into an [`IntegerObject`], so the language still sees 64-bit
+//! integers.
+//!
+//! Values are copied as plain words, including the ones that point to the
+//! heap. Like a [`GcPtr`], they don't keep their object alive by themselves:
+//! the VM traces the values it holds when it collects garbage.
+
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+use belvm_gc::gc::{GcHeap, GcObject, GcPtr, Trace, Tracer};
+
+use crate::errors::RuntimeError;
+use crate::objects::{IntegerObject, StringObject};
+use crate::stack::StackValue;
+
+/// The bits every boxed value starts with
+const BOXED: u64 = 0xFFF8_0000_0000_0000;
+
+/// The only NaN a float value can be
+const CANONICAL_NAN: u64 = 0x7FF8_0000_0000_0000;
+
+const TAG_SHIFT: u32 = 48;
+const PAYLOAD_MASK: u64 = (1 << TAG_SHIFT) - 1;
+
+const MIN_INLINE_INTEGER: i64 = -(1 << (TAG_SHIFT - 1));
+const MAX_INLINE_INTEGER: i64 = (1 << (TAG_SHIFT - 1)) - 1;
+
+/// Tags of the boxed values. Floats aren't boxed and get tag 0.
+mod tag {
+    pub const FLOAT: u64 = 0;
+    pub const NULL: u64 = 1;
+    pub const BOOLEAN: u64 = 2;
+    pub const INTEGER: u64 = 3;
+    pub const ADDRESS: u64 = 4;
+    pub const STRING: u64 = 5;
+    pub const INTEGER_OBJECT: u64 = 6;
+    pub const FUNCTION: u64 = 7;
+}
+
+/// A value of the VM, packed into 8 bytes
+///
+/// This is what the stack, the globals and the registers hold. Use
+/// [`Value::unpack`] to get a [`StackValue`] that can be matched on.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct Value {
+    bits: u64,
+
+    /// Values can point into the heap of the thread they were created on.
+    _marker: PhantomData<*const ()>,
+}
+
+impl Value {
+    pub const NULL: Self = Self::boxed(tag::NULL, 0);
+
+    const fn boxed(tag: u64, payload: u64) -> Self {
+        Self {
+            bits: BOXED | (tag << TAG_SHIFT) | (payload & PAYLOAD_MASK),
+            _marker: PhantomData,
+        }
+    }
+
+    fn object<T: GcObject>(tag: u64, ptr: GcPtr<T>) -> Self {
+        let address = ptr.into_raw().as_ptr() as u64;
+        assert!(address <= PAYLOAD_MASK, "heap address doesn't fit in a value");
+
+        Self::boxed(tag, address)
+    }
+
+    pub const fn boolean(value: bool) -> Self {
+        Self::boxed(tag::BOOLEAN, value as u64)
+    }
+
+    /// Creates an integer value, boxing it on `heap` if it is wider than 48
+    /// bits.
+    pub fn integer(value: i64, heap: &mut GcHeap) -> Result<Self, RuntimeError> {
+        if (MIN_INLINE_INTEGER..=MAX_INLINE_INTEGER).contains(&value) {
+            return Ok(Self::boxed(tag::INTEGER, value as u64));
+        }
+
+        let object = heap
+            .alloc(IntegerObject::new(value))
+            .map_err(|_| RuntimeError::AllocationFailed)?;
+
+        Ok(Self::object(tag::INTEGER_OBJECT, object))
+    }
+
+    pub fn float(value: f64) -> Self {
+        let bits = if value.is_nan() { CANONICAL_NAN } else { value.to_bits() };
+
+        Self {
+            bits,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn string(value: GcPtr<StringObject>) -> Self {
+        Self::object(tag::STRING, value)
+    }
+
+    pub fn address(value: usize) -> Self {
+        assert!(value as u64 <= PAYLOAD_MASK, "address doesn't fit in a value");
+
+        Self::boxed(tag::ADDRESS, value as u64)
+    }
+
+    /// Creates a function value from the index of its
+    /// [`Constant::Function`](belvm_bytecode::Constant::Function).
+    pub fn function(index: usize) -> Self {
+        assert!(index as u64 <= PAYLOAD_MASK, "function index doesn't fit in a value");
+
+        Self::boxed(tag::FUNCTION, index as u64)
+    }
+
+    fn tag(&self) -> u64 {
+        if self.bits & BOXED == BOXED {
+            (self.bits >> TAG_SHIFT) & 0x7
+        } else {
+            tag::FLOAT
+        }
+    }
+
+    fn payload(&self) -> u64 {
+        self.bits & PAYLOAD_MASK
+    }
+
+    /// Borrows the object this value points to.
+    ///
+    /// # Safety
+    ///
+    /// The value has to be tagged as pointing to a `T`.
+    unsafe fn as_object<T: GcObject>(&self) -> &T {
+        // Safety: the caller checked the tag, and the VM keeps the objects
+        // of its values alive
+        unsafe { &*(self.payload() as *const T) }
+    }
+
+    /// Returns a pointer to the object this value points to.
+    ///
+    /// # Safety
+    ///
+    /// The value has to be tagged as pointing to a `T`.
+    unsafe fn as_gc_ptr<T: GcObject>(&self) -> GcPtr<T> {
+        // Safety: objects are never at address zero, and the caller checked
+        // the tag
+        unsafe { GcPtr::from_raw(NonNull::new_unchecked(self.payload() as *mut T)) }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.bits == Self::NULL.bits
+    }
+
+    pub fn as_boolean(&self) -> Option<bool> {
+        (self.tag() == tag::BOOLEAN).then(|| self.payload() != 0)
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self.tag() {
+            // sign-extend the 48-bit payload
+            tag::INTEGER => Some(((self.bits << (64 - TAG_SHIFT)) as i64) >> (64 - TAG_SHIFT)),
+
+            // Safety: we just checked the tag
+            tag::INTEGER_OBJECT => Some(unsafe { self.as_object::<IntegerObject>() }.value),
+
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        (self.tag() == tag::FLOAT).then(|| f64::from_bits(self.bits))
+    }
+
+    pub fn as_address(&self) -> Option<usize> {
+        (self.tag() == tag::ADDRESS).then(|| self.payload() as usize)
+    }
+
+    /// Returns the constant index of the function this value is.
+    pub fn as_function(&self) -> Option<usize> {
+        (self.tag() == tag::FUNCTION).then(|| self.payload() as usize)
+    }
+
+    /// Returns the matchable form of this value.
+    pub fn unpack(&self) -> StackValue {
+        match self.tag() {
+            tag::FLOAT => StackValue::Float(f64::from_bits(self.bits)),
+            tag::NULL => StackValue::Null,
+            tag::BOOLEAN => StackValue::Boolean(self.payload() != 0),
+            tag::INTEGER | tag::INTEGER_OBJECT => StackValue::Integer(self.as_integer().unwrap()),
+            tag::ADDRESS => StackValue::AddressPtr(self.payload() as usize),
+            tag::FUNCTION => StackValue::Function(self.payload() as usize),
+
+            // Safety: we just checked the tag
+            tag::STRING => StackValue::String(unsafe { self.as_gc_ptr() }),
+
+            _ => unreachable!("invalid value tag"),
+        }
+    }
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Self::NULL
+    }
+}
+
+impl Trace for Value {
+    fn trace(&self, tracer: &mut Tracer) {
+        // Safety: the tag is checked before taking the pointer
+        match self.tag() {
+            tag::STRING => tracer.mark(unsafe { self.as_gc_ptr::<StringObject>() }),
+            tag::INTEGER_OBJECT => tracer.mark(unsafe { self.as_gc_ptr::<IntegerObject>() }),
+            _ => {},
+        }
+    }
+}
+
+impl Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.unpack().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size() {
+        assert_eq!(size_of::<Value>(), 8);
+    }
+
+    #[test]
+    fn integers() {
+        let mut heap = GcHeap::default();
+
+        for int in [0, 1, -1, MIN_INLINE_INTEGER, MAX_INLINE_INTEGER] {
+            let value = Value::integer(int, &mut heap).unwrap();
+
+            assert_eq!(value.tag(), tag::INTEGER);
+            assert_eq!(value.as_integer(), Some(int));
+        }
+
+        for int in [i64::MIN, i64::MAX, MIN_INLINE_INTEGER - 1, MAX_INLINE_INTEGER + 1] {
+            let value = Value::integer(int, &mut heap).unwrap();
+
+            assert_eq!(value.tag(), tag::INTEGER_OBJECT);
+            assert_eq!(value.as_integer(), Some(int));
+        }
+    }
+
+    #[test]
+    fn floats() {
+        for float in [0.0, -0.0, 1.5, f64::INFINITY, f64::NEG_INFINITY, f64::MIN_POSITIVE] {
+            assert_eq!(Value::float(float).as_float().map(f64::to_bits), Some(float.to_bits()));
+        }
+
+        // a NaN with the sign bit set looks like a boxed value
+        let nan = Value::float(-f64::NAN);
+        assert!(nan.as_float().unwrap().is_nan());
+        assert_eq!(nan.as_integer(), None);
+    }
+
+    #[test]
+    fn others() {
+        assert!(Value::NULL.is_null());
+        assert!(Value::default().is_null());
+        assert_eq!(Value::boolean(true).as_boolean(), Some(true));
+        assert_eq!(Value::boolean(false).as_boolean(), Some(false));
+        assert_eq!(Value::address(70_000).as_address(), Some(70_000));
+        assert_eq!(Value::function(3).as_function(), Some(3));
+        assert_eq!(Value::boolean(false).as_integer(), None);
+    }
+
+    #[test]
+    fn objects_are_traced() {
+        let mut heap = GcHeap::default();
+
+        let string = heap.alloc(StringObject::new("hello".into())).unwrap();
+        let values = [
+            Value::string(string),
+            Value::integer(i64::MAX, &mut heap).unwrap(),
+            Value::integer(1, &mut heap).unwrap(),
+        ];
+
+        let StackValue::String(unpacked) = values[0].unpack() else {
+            panic!("value is not a String!");
+        };
+        assert_eq!(unpacked.as_ptr(), string.as_ptr());
+
+        heap.collect(|tracer| values.trace(tracer));
+        assert_eq!(heap.object_count(), 2);
+
+        heap.collect(|tracer| values[1..].trace(tracer));
+        assert_eq!(heap.object_count(), 1);
+        assert_eq!(values[1].as_integer(), Some(i64::MAX));
+    }
+}
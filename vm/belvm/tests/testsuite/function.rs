@@ -0,0 +1,126 @@
+use belvm::VM;
+use belvm::errors::RuntimeError;
+use belvm::stack::StackValue;
+
+fn run(source: &str, recursion_limit: usize) -> Result<i64, RuntimeError> {
+    let bytecode = belc::compile(&source.to_owned()).unwrap();
+
+    let mut vm = VM::default();
+    vm.set_recursion_limit(recursion_limit);
+    vm.run(bytecode)?;
+
+    let StackValue::Integer(value) = vm.stack_pop()? else {
+        panic!("TOS is not an Integer!");
+    };
+
+    Ok(value)
+}
+
+#[test]
+fn calls() {
+    let source = "add := fn(a, b) { a - b }; return add(10, 3) + add(1, 1);";
+    assert_eq!(run(source, 16), Ok(7));
+}
+
+#[test]
+fn locals() {
+    let source = "
+    f := fn(a) {
+      b := a * 2;
+      b += 1;
+      b
+    };
+    return f(4);
+    ";
+    assert_eq!(run(source, 16), Ok(9));
+}
+
+#[test]
+fn recursion() {
+    let source = "
+    fact := fn(n) {
+      if (n < 2) {
+        return 1;
+      };
+
+      return n * fact(n - 1);
+    };
+    return fact(10);
+    ";
+    assert_eq!(run(source, 16), Ok(3628800));
+    assert_eq!(run(source, 5), Err(RuntimeError::StackOverflow(5)));
+}
+
+#[test]
+fn tail_calls_reuse_frames() {
+    // far deeper than the recursion limit allows without tail calls
+    let source = "
+    sum := fn(n, acc) {
+      if (n == 0) { acc } else { sum(n - 1, acc + n) }
+    };
+    return sum(100000, 0);
+    ";
+    assert_eq!(run(source, 16), Ok(5000050000));
+}
+
+#[test]
+fn mutual_tail_calls() {
+    let source = "
+    is_even := 0;
+    is_odd := fn(n) {
+      if (n == 0) {
+        return 0;
+      };
+
+      return is_even(n - 1);
+    };
+    is_even = fn(n) {
+      if (n == 0) {
+        return 1;
+      };
+
+      return is_odd(n - 1);
+    };
+    return is_even(100001);
+    ";
+    assert_eq!(run(source, 16), Ok(0));
+}
+
+#[test]
+fn errors_unwind_frames() {
+    let mut vm = VM::default();
+
+    let bytecode = belc::compile(&"f := fn(a) { a + true }; f(1);".to_owned()).unwrap();
+    assert_eq!(vm.run(bytecode), Err(RuntimeError::TypeError));
+
+    assert_eq!(vm.stack_size(), 0);
+}
+
+#[test]
+fn arity_mismatch() {
+    // `f` is reassigned, so the compiler can't tell what it calls, and the
+    // call happens deep in the stack, below which are frames it could take
+    let source = |args: &str| {
+        format!(
+            "
+            f := 0;
+            f = fn(a, b) {{ return b; }};
+            g := fn(n) {{
+              if (n == 0) {{ return f({args}); }};
+              return g(n - 1) + 1;
+            }};
+            return g(10);
+            "
+        )
+    };
+
+    assert_eq!(run(&source("1, 2"), 16), Ok(12));
+    assert_eq!(
+        run(&source(""), 16),
+        Err(RuntimeError::ArityMismatch { arity: 2, argc: 0 })
+    );
+    assert_eq!(
+        run(&source("1, 2, 3"), 16),
+        Err(RuntimeError::ArityMismatch { arity: 2, argc: 3 })
+    );
+}
@@ -0,0 +1,322 @@
+use belc::Target;
+use belvm::VM;
+use belvm::errors::RuntimeError;
+use belvm::register::RegisterVM;
+use belvm::stack::StackValue;
+use belvm_bytecode::{Bytecode, Constant, Function, opcode};
+
+#[test]
+fn loops_collect_garbage() {
+    // every iteration boxes a new wide integer, which is garbage by the next
+    let source = "i := 0; while (i < 100000) { x := 140737488355328 + i; i += 1; };";
+    let bytecode = belc::compile(&source.to_owned()).unwrap();
+
+    let mut vm = VM::default();
+    vm.run(bytecode).unwrap();
+
+    let count = vm.heap().object_count();
+    assert!(count < 50_000, "{count} objects left");
+
+    let Some(StackValue::Integer(x)) = vm.global(2) else {
+        panic!("x is not an Integer!");
+    };
+    assert_eq!(x, 140737488355328 + 99999);
+
+    vm.collect_garbage();
+    assert_eq!(vm.heap().object_count(), 1);
+}
+
+#[test]
+fn tail_calls_collect_garbage() {
+    let source = "
+    f := fn(n, x) { if (n == 0) { x } else { f(n - 1, x + 140737488355328) } };
+    return f(50000, 0);
+    ";
+    let bytecode = belc::compile(&source.to_owned()).unwrap();
+
+    let mut vm = VM::default();
+    vm.set_recursion_limit(16);
+    vm.run(bytecode).unwrap();
+
+    let count = vm.heap().object_count();
+    assert!(count < 25_000, "{count} objects left");
+
+    let StackValue::Integer(x) = vm.stack_pop().unwrap() else {
+        panic!("TOS is not an Integer!");
+    };
+    assert_eq!(x, 50000 * 140737488355328);
+}
+
+#[test]
+fn roots_survive() {
+    let source = "
+    s := \"kept\";
+    i := 0;
+    while (i < 100000) { x := 140737488355328 + i; i += 1; };
+    return s;
+    ";
+    let bytecode = belc::compile(&source.to_owned()).unwrap();
+
+    let mut vm = VM::default();
+    vm.run(bytecode).unwrap();
+    vm.collect_garbage();
+
+    let StackValue::String(string) = vm.stack_pop().unwrap() else {
+        panic!("TOS is not a String!");
+    };
+    assert_eq!(string.value, "kept");
+}
+
+#[test]
+fn heaps_are_per_vm() {
+    let source = "x := 140737488355328 + 1; y := \"string\";";
+
+    let mut first = VM::default();
+    let mut second = VM::default();
+    let mut register_vm = RegisterVM::default();
+
+    first.run(belc::compile(&source.to_owned()).unwrap()).unwrap();
+    assert_eq!(first.heap().object_count(), 2);
+    assert_eq!(second.heap().object_count(), 0);
+    assert_eq!(register_vm.heap().object_count(), 0);
+
+    // the others know nothing of the objects of the first one
+    second.collect_garbage();
+    register_vm.collect_garbage();
+    assert_eq!(first.heap().object_count(), 2);
+
+    let Some(StackValue::Integer(x)) = first.global(1) else {
+        panic!("x is not an Integer!");
+    };
+    assert_eq!(x, 140737488355329);
+}
+
+#[test]
+#[cfg_attr(feature = "gc-stress", ignore = "stress mode collects on every op")]
+fn stats() {
+    let source = "i := 0; while (i < 100000) { x := 140737488355328 + i; i += 1; };";
+
+    // without a nursery, which would collect all of this garbage on its own
+    let mut vm = VM::default();
+    vm.set_gc_nursery_size(0);
+    vm.run(belc::compile(&source.to_owned()).unwrap()).unwrap();
+
+    let stats = vm.gc_stats();
+    assert!(stats.collections > 0);
+    assert_eq!(stats.objects, vm.heap().object_count());
+    assert_eq!(stats.types.len(), 1);
+    assert_eq!(stats.types[0].name, "Integer");
+
+    // collecting more often keeps less garbage around
+    let mut eager = VM::default();
+    eager.set_gc_nursery_size(0);
+    eager.set_gc_threshold(4096);
+    eager.set_gc_growth_factor(1.5);
+    eager.run(belc::compile(&source.to_owned()).unwrap()).unwrap();
+
+    let eager_stats = eager.gc_stats();
+    assert!(eager_stats.collections > stats.collections);
+    assert!(eager_stats.bytes < stats.bytes);
+}
+
+#[test]
+fn heap_limit() {
+    // every frame holds on to a boxed integer until the call below it returns
+    let source = "
+    f := fn(n) { if (n == 0) { 0 } else { 140737488355328 + f(n - 1) } };
+    return f(1000);
+    ";
+
+    let mut vm = VM::default();
+    vm.set_heap_limit(Some(4096));
+
+    let result = vm.run(belc::compile(&source.to_owned()).unwrap());
+    assert_eq!(result, Err(RuntimeError::OutOfMemory(4096)));
+
+    // the frames are gone, so their objects can be freed
+    vm.collect_garbage();
+    assert!(!vm.heap().is_over_limit());
+}
+
+#[test]
+fn heap_limit_counts_live_objects_only() {
+    let source = "i := 0; while (i < 100000) { x := 140737488355328 + i; i += 1; };";
+
+    let mut vm = VM::default();
+    vm.set_heap_limit(Some(4096));
+    vm.run(belc::compile(&source.to_owned()).unwrap()).unwrap();
+
+    let (bytecode, _) = belc::compile_with_target(&source.to_owned(), Target::Register).unwrap();
+
+    let mut register_vm = RegisterVM::default();
+    register_vm.set_heap_limit(Some(4096));
+    register_vm.run(bytecode).unwrap();
+}
+
+#[test]
+fn sandbox_survives_malformed_calls() {
+    let function = |pointer, arity| {
+        Constant::Function(Function {
+            pointer,
+            locals_count: arity,
+            arity,
+        })
+    };
+
+    let sandbox = || {
+        let mut vm = VM::default();
+        vm.set_heap_limit(Some(4096));
+        vm.set_recursion_limit(16);
+        vm
+    };
+
+    // calls a function taking two arguments with none, from within a frame
+    let mut vm = sandbox();
+    let source = "
+    f := 0;
+    f = fn(a, b) { return b; };
+    g := fn(n) { if (n == 0) { return f(); }; return g(n - 1) + 1; };
+    g(10);
+    ";
+    let result = vm.run(belc::compile(&source.to_owned()).unwrap());
+    assert_eq!(result, Err(RuntimeError::ArityMismatch { arity: 2, argc: 0 }));
+    assert_eq!(vm.stack_size(), 0);
+
+    // the same, with bytecode no compiler would emit
+    let mut vm = sandbox();
+    let mut instructions = Vec::new();
+    instructions.extend(opcode::constant(0));
+    instructions.extend(opcode::call(0));
+    instructions.push(opcode::RETURN_VALUE);
+    instructions.extend(opcode::constant(1));
+    instructions.extend(opcode::call(0));
+    instructions.push(opcode::RETURN_VALUE);
+
+    let result = vm.run(Bytecode {
+        constants: vec![function(6, 0), function(0, 2)],
+        instructions,
+    });
+    assert_eq!(result, Err(RuntimeError::ArityMismatch { arity: 2, argc: 0 }));
+    assert_eq!(vm.stack_size(), 0);
+
+    // a function popping its own frame before returning
+    let mut vm = sandbox();
+    let mut instructions = Vec::new();
+    instructions.extend(opcode::constant(0));
+    instructions.extend(opcode::call(0));
+    instructions.push(opcode::RETURN_VALUE);
+    instructions.extend([opcode::POP, opcode::POP, opcode::TRUE, opcode::TRUE, opcode::RETURN]);
+
+    let result = vm.run(Bytecode {
+        constants: vec![function(6, 0)],
+        instructions,
+    });
+    assert_eq!(result, Err(RuntimeError::StackUnderflow));
+    assert_eq!(vm.stack_size(), 0);
+
+    // the VM is still usable
+    vm.run(Bytecode {
+        constants: vec![Constant::Integer(3)],
+        instructions: opcode::constant(1).to_vec(),
+    })
+    .unwrap();
+    assert!(matches!(vm.stack_pop(), Ok(StackValue::Integer(3))));
+}
+
+#[test]
+#[cfg_attr(feature = "gc-stress", ignore = "stress mode collects on every op")]
+fn nursery() {
+    let source = "
+    s := \"kept\";
+    i := 0;
+    while (i < 100000) { x := 140737488355328 + i; i += 1; };
+    return s;
+    ";
+
+    let mut vm = VM::default();
+    vm.set_gc_nursery_size(64 * 1024);
+    vm.run(belc::compile(&source.to_owned()).unwrap()).unwrap();
+
+    // the garbage never outgrows the nursery, so a full collection never runs
+    let stats = vm.gc_stats();
+    assert!(stats.minor_collections > 0);
+    assert_eq!(stats.collections, 0);
+
+    vm.collect_garbage();
+    let StackValue::String(string) = vm.stack_pop().unwrap() else {
+        panic!("TOS is not a String!");
+    };
+    assert_eq!(string.value, "kept");
+
+    let (bytecode, _) = belc::compile_with_target(&source.to_owned(), Target::Register).unwrap();
+
+    let mut register_vm = RegisterVM::default();
+    register_vm.set_gc_nursery_size(64 * 1024);
+    register_vm.run(bytecode).unwrap();
+
+    assert!(register_vm.gc_stats().minor_collections > 0);
+    let StackValue::String(string) = register_vm.result() else {
+        panic!("result is not a String!");
+    };
+    assert_eq!(string.value, "kept");
+}
+
+#[test]
+fn heap_snapshot_on_error() {
+    // every frame holds on to a boxed integer until the call below it returns
+    let source = "
+    f := fn(n) { if (n == 0) { 0 } else { 140737488355328 + f(n - 1) } };
+    return f(1000);
+    ";
+
+    let mut vm = VM::default();
+    vm.set_heap_limit(Some(4096));
+    vm.set_snapshot_on_error(true);
+
+    let result = vm.run(belc::compile(&source.to_owned()).unwrap());
+    assert_eq!(result, Err(RuntimeError::OutOfMemory(4096)));
+
+    // the integers the frames held on to were still alive when it failed
+    let snapshot = vm.take_error_snapshot().unwrap();
+    let integers = snapshot.objects.iter().filter(|object| object.type_name == "Integer");
+    let on_stack = integers.filter(|object| object.root_path.as_ref().is_some_and(|path| path.root == "stack"));
+    assert!(on_stack.count() > 1);
+
+    assert!(vm.take_error_snapshot().is_none());
+    assert!(vm.heap_snapshot().roots.iter().all(|root| root.name != "stack"));
+}
+
+#[test]
+fn heap_snapshot() {
+    let source = "s := \"kept\"; i := 0; while (i < 10) { x := 140737488355328 + i; i += 1; };";
+
+    let mut vm = VM::default();
+    vm.run(belc::compile(&source.to_owned()).unwrap()).unwrap();
+
+    let snapshot = vm.heap_snapshot();
+    assert_eq!(snapshot.objects.len(), vm.heap().object_count());
+
+    let string = snapshot
+        .objects
+        .iter()
+        .find(|object| object.type_name == "String")
+        .unwrap();
+    assert_eq!(string.root_path.as_ref().unwrap().root, "globals");
+
+    // only the last boxed integer is still in a global
+    let integers = snapshot.objects.iter().filter(|object| object.type_name == "Integer");
+    assert_eq!(integers.filter(|object| object.root_path.is_some()).count(), 1);
+
+    let (bytecode, _) = belc::compile_with_target(&source.to_owned(), Target::Register).unwrap();
+
+    let mut register_vm = RegisterVM::default();
+    register_vm.run(bytecode).unwrap();
+
+    let snapshot = register_vm.heap_snapshot();
+    let string = snapshot
+        .objects
+        .iter()
+        .find(|object| object.type_name == "String")
+        .unwrap();
+    assert_eq!(string.root_path.as_ref().unwrap().root, "registers");
+}
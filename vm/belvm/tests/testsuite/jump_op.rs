@@ -1,6 +1,7 @@
 use beltools_tests::IntoInstructionBytes;
 use beltools_tests::instructions;
-use belvm_bytecode::opcode;
+use belvm_bytecode::Instruction;
+use belvm_bytecode::opcode::{self, Opcode};
 
 #[test]
 fn jump() {
@@ -30,3 +31,44 @@ fn jump_if_false_op() {
         .expect_stack_top_is_bool(false)
         .expect_stack_top_is_bool(true);
 }
+
+#[test]
+fn jump_backwards() {
+    let constants = Vec::new();
+
+    let instructions = instructions![
+        Instruction::new(Opcode::Jump, &[4]),
+        opcode::TRUE,
+        Instruction::new(Opcode::Jump, &[4]),
+        opcode::FALSE,
+        Instruction::new(Opcode::JumpIfFalse, &[-8]),
+    ];
+
+    beltools_tests::VMBuilder::default()
+        .with_instructions(instructions)
+        .with_constants(constants)
+        .run_ok()
+        .expect_stack_size(1)
+        .expect_stack_top_is_bool(true);
+}
+
+#[test]
+fn wide_jump() {
+    let constants = Vec::new();
+
+    let instructions = instructions![
+        Instruction {
+            wide: true,
+            ..Instruction::new(Opcode::Jump, &[1])
+        },
+        opcode::TRUE,
+        opcode::FALSE,
+    ];
+
+    beltools_tests::VMBuilder::default()
+        .with_instructions(instructions)
+        .with_constants(constants)
+        .run_ok()
+        .expect_stack_size(1)
+        .expect_stack_top_is_bool(false);
+}
@@ -1,4 +1,8 @@
 mod boolean;
+mod function;
+mod gc;
 mod jump_op;
 mod number;
+mod semantics;
 mod stack_op;
+mod string;
@@ -1,5 +1,6 @@
 use beltools_tests::IntoInstructionBytes;
 use beltools_tests::instructions;
+use belvm::errors::RuntimeError;
 use belvm_bytecode::Constant;
 use belvm_bytecode::opcode;
 
@@ -41,6 +42,32 @@ fn arithmetic_op_modulo() {
     test_arithmetic_op(12, 5, opcode::MOD, 2);
 }
 
+fn test_arithmetic_err(a: i64, b: i64, op: u8, err: RuntimeError) {
+    let constants = vec![Constant::Integer(a), Constant::Integer(b)];
+
+    let instructions = instructions![opcode::constant(0), opcode::constant(1), op,];
+
+    let result = beltools_tests::VMBuilder::default()
+        .with_instructions(instructions)
+        .with_constants(constants)
+        .run_err();
+
+    assert_eq!(result, err);
+}
+
+#[test]
+fn arithmetic_op_overflow() {
+    test_arithmetic_err(i64::MAX, 1, opcode::ADD, RuntimeError::IntegerOverflow);
+    test_arithmetic_err(i64::MIN, -1, opcode::DIV, RuntimeError::IntegerOverflow);
+    test_arithmetic_err(1, 64, opcode::BIT_SL, RuntimeError::IntegerOverflow);
+}
+
+#[test]
+fn arithmetic_op_division_by_zero() {
+    test_arithmetic_err(12, 0, opcode::DIV, RuntimeError::DivisionByZero);
+    test_arithmetic_err(12, 0, opcode::MOD, RuntimeError::DivisionByZero);
+}
+
 fn test_comparison_op(a: i64, b: i64, op: u8, c: bool) {
     let constants = vec![Constant::Integer(a), Constant::Integer(b)];
 
@@ -0,0 +1,147 @@
+//! Programs run on both the stack VM, with and without superinstructions or
+//! the peephole optimizer, and the register VM, which have to agree on the
+//! result.
+
+use belc::Target;
+use belvm::VM;
+use belvm::errors::RuntimeError;
+use belvm::register::RegisterVM;
+use belvm::stack::StackValue;
+
+fn describe(value: &StackValue) -> String {
+    match value {
+        StackValue::Boolean(boolean) => boolean.to_string(),
+        StackValue::Integer(int) => int.to_string(),
+        StackValue::Float(float) => format!("{float:?}"),
+        StackValue::String(string) => format!("{:?}", string.value),
+        StackValue::AddressPtr(address) => format!("address {address}"),
+        StackValue::Function(index) => format!("fn#{index}"),
+        StackValue::Null => "null".into(),
+    }
+}
+
+fn run_stack(source: &str, superinstructions: bool) -> Result<String, RuntimeError> {
+    let (bytecode, _) = belc::compile_with_target(&source.to_owned(), Target::Stack).unwrap();
+
+    let mut vm = VM::default();
+    vm.set_superinstructions(superinstructions);
+    vm.run(bytecode)?;
+
+    Ok(describe(&vm.stack_pop()?))
+}
+
+fn run_optimized(source: &str) -> Result<String, RuntimeError> {
+    let (mut bytecode, _) = belc::compile_with_target(&source.to_owned(), Target::Stack).unwrap();
+    belc::optimize(&mut bytecode).unwrap();
+
+    let mut vm = VM::default();
+    vm.run(bytecode)?;
+
+    Ok(describe(&vm.stack_pop()?))
+}
+
+fn run_register(source: &str) -> Result<String, RuntimeError> {
+    let (bytecode, _) = belc::compile_with_target(&source.to_owned(), Target::Register).unwrap();
+
+    let mut vm = RegisterVM::default();
+    vm.run(bytecode)?;
+
+    Ok(describe(&vm.result()))
+}
+
+#[track_caller]
+fn test_semantics(source: &str, expected: Result<&str, RuntimeError>) {
+    let expected = expected.map(str::to_owned);
+
+    assert_eq!(run_stack(source, true), expected, "stack VM");
+    assert_eq!(run_stack(source, false), expected, "stack VM without superinstructions");
+    assert_eq!(run_optimized(source), expected, "stack VM with optimized bytecode");
+    assert_eq!(run_register(source), expected, "register VM");
+}
+
+#[test]
+fn arithmetic() {
+    test_semantics("a := 7; b := 3; return a * b - a / b + a % b;", Ok("20"));
+    test_semantics("a := 6; return -a << 2;", Ok("-24"));
+    test_semantics("a := 12; b := 10; return (a & b) | (a ^ b);", Ok("14"));
+}
+
+#[test]
+fn wide_integers() {
+    // integers wider than 48 bits are boxed on the heap
+    test_semantics("a := 140737488355327; return a + 1;", Ok("140737488355328"));
+    test_semantics("a := -140737488355328; return a - 1;", Ok("-140737488355329"));
+    test_semantics("a := 9223372036854775807; b := a - 1; return b < a;", Ok("true"));
+    test_semantics("a := 140737488355328; return a - 1 == 140737488355327;", Ok("true"));
+}
+
+#[test]
+fn garbage() {
+    // enough boxed integers to trigger collections while the loop runs
+    test_semantics(
+        "s := \"kept\"; i := 0; while (i < 50000) { x := 140737488355328 + i; i += 1; }; return s;",
+        Ok("\"kept\""),
+    );
+}
+
+#[test]
+fn comparisons() {
+    test_semantics("a := 1; b := 2; return a < b && !(a == b);", Ok("true"));
+    test_semantics("a := 1; b := 2; return a >= b || a != b;", Ok("true"));
+}
+
+#[test]
+fn assignment_ops() {
+    test_semantics("a := 5; a += 2; a *= 3; a -= 1; a /= 4; return a;", Ok("5"));
+    test_semantics("a := 1; b := a; a = 2; return b;", Ok("1"));
+    test_semantics("a := 1; b := (a = a + 1) + a; return b;", Ok("4"));
+}
+
+#[test]
+fn conditionals() {
+    test_semantics("a := 3; b := if (a > 2) { 10 } else { 20 }; return b;", Ok("10"));
+    test_semantics("a := 1; b := if (a > 2) { 10 }; return b;", Ok("null"));
+    test_semantics(
+        "a := 2; b := if (a == 1) { 10 } else if (a == 2) { 20 } else { 30 }; return b;",
+        Ok("20"),
+    );
+}
+
+#[test]
+fn loops() {
+    test_semantics(
+        "sum := 0; i := 0; while (i < 100) { sum += i; i += 1; }; return sum;",
+        Ok("4950"),
+    );
+    test_semantics(
+        "a := 0; b := 1; i := 0; while (i < 50) { t := a + b; a = b; b = t; i += 1; }; return a;",
+        Ok("12586269025"),
+    );
+}
+
+#[test]
+fn literals() {
+    test_semantics("a := \"hi\"; return a;", Ok("\"hi\""));
+    test_semantics("a := 1.5; return a;", Ok("1.5"));
+}
+
+#[test]
+fn errors() {
+    test_semantics(
+        "a := 9223372036854775807; return a + 1;",
+        Err(RuntimeError::IntegerOverflow),
+    );
+    test_semantics("a := 0; return 1 / a;", Err(RuntimeError::DivisionByZero));
+    test_semantics("a := true; return a + 1;", Err(RuntimeError::TypeError));
+}
+
+#[test]
+fn non_boolean_conditions() {
+    // the branches are empty, so the optimizer is left with a jump to the
+    // next instruction that is only there to check the condition
+    let source = "x := \"a\"; if (x) { } else { }; return 1;";
+    assert_eq!(run_stack(source, true), Err(RuntimeError::TypeError));
+    assert_eq!(run_optimized(source), Err(RuntimeError::TypeError));
+
+    test_semantics("x := 1; y := 0; if (x) { y = 2; } else { y = 3; }; return y;", Ok("2"));
+}
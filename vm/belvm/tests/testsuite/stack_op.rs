@@ -1,5 +1,7 @@
 use beltools_tests::IntoInstructionBytes;
 use beltools_tests::instructions;
+use belvm::errors::RuntimeError;
+use belvm::stack::StackValue;
 use belvm_bytecode::Constant;
 use belvm_bytecode::opcode;
 
@@ -16,3 +18,28 @@ fn pop() {
         .expect_stack_size(1)
         .expect_stack_top_is_int(12);
 }
+
+#[test]
+fn null_constant() {
+    let instructions = instructions![opcode::constant(0)];
+
+    let mut vm = beltools_tests::VMBuilder::default()
+        .with_instructions(instructions)
+        .with_constants(vec![Constant::Null])
+        .run_ok()
+        .expect_stack_size(1)
+        .into_vm();
+
+    assert!(matches!(vm.stack_pop().unwrap(), StackValue::Null));
+}
+
+#[test]
+fn constant_out_of_the_pool() {
+    let instructions = instructions![opcode::constant(5)];
+
+    let err = beltools_tests::VMBuilder::default()
+        .with_instructions(instructions)
+        .run_err();
+
+    assert_eq!(err, RuntimeError::InvalidConstant(0));
+}
@@ -0,0 +1,39 @@
+use beltools_tests::IntoInstructionBytes;
+use beltools_tests::instructions;
+use belvm::stack::StackValue;
+use belvm_bytecode::Constant;
+use belvm_bytecode::opcode;
+
+#[test]
+fn string_constant() {
+    let constants = vec![Constant::String("hello".into())];
+
+    let instructions = instructions![opcode::constant(0)];
+
+    beltools_tests::VMBuilder::default()
+        .with_instructions(instructions)
+        .with_constants(constants)
+        .run_ok()
+        .expect_stack_size(1)
+        .expect_stack_top_is_string("hello");
+}
+
+#[test]
+fn string_constants_share_objects() {
+    let constants = vec![Constant::String("hello".into())];
+
+    let instructions = instructions![opcode::constant(0), opcode::constant(0)];
+
+    let mut vm = beltools_tests::VMBuilder::default()
+        .with_instructions(instructions)
+        .with_constants(constants)
+        .run_ok()
+        .expect_stack_size(2)
+        .into_vm();
+
+    let (StackValue::String(a), StackValue::String(b)) = (vm.stack_pop().unwrap(), vm.stack_pop().unwrap()) else {
+        panic!("TOS is not a String!");
+    };
+
+    assert_eq!(a.as_ptr(), b.as_ptr());
+}
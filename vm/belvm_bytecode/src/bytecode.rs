@@ -8,8 +8,15 @@ use std::io::{Cursor, Read};
 use bincode::{Decode, Encode, config, decode_from_slice, encode_to_vec};
 use crc32fast::Hasher;
 
-static BEL_MAGIC: [u8; 4] = [0xBEu8, 0x1Au8, 0x1Au8, 0x9Cu8];
-static BEL_VERSION: u16 = 1;
+/// Magic number every encoded bytecode file starts with.
+pub static BEL_MAGIC: [u8; 4] = [0xBEu8, 0x1Au8, 0x1Au8, 0x9Cu8];
+
+/// Version of the bytecode format written by [`Bytecode::into_bytes`].
+pub static BEL_VERSION: u16 = 2;
+
+/// Size of the header in front of the encoded bytecode: magic number,
+/// version and checksum.
+const HEADER_SIZE: usize = 10;
 
 /// Constants used in the Belalang bytecode
 ///
@@ -20,15 +27,34 @@ pub enum Constant {
     #[default]
     Null,
     Integer(i64),
+    Float(f64),
     Boolean(bool),
     String(String),
+    Function(Function),
+}
+
+/// A compiled function referenced from the constant pool
+///
+/// Function bodies live in the same instruction stream as the main program,
+/// appended after it. `pointer` is the offset of the first instruction of the
+/// body.
+#[derive(Encode, Decode, Debug, Clone, PartialEq)]
+pub struct Function {
+    /// Offset of the function body in the instruction stream
+    pub pointer: usize,
+
+    /// Number of local slots the function needs, including its parameters
+    pub locals_count: usize,
+
+    /// Number of parameters the function takes
+    pub arity: usize,
 }
 
 /// A compiled bytecode object for the Belalang VM
 ///
 /// This contains the instruction stream and associated constant pool needed for
 /// execution by the virtual machine.
-#[derive(Encode, Decode)]
+#[derive(Encode, Decode, Clone)]
 pub struct Bytecode {
     /// The instructions to be executed
     ///
@@ -39,6 +65,19 @@ pub struct Bytecode {
     pub constants: Vec<Constant>,
 }
 
+/// Header stored in front of encoded [`Bytecode`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BytecodeHeader {
+    /// Version of the bytecode format
+    pub version: u16,
+
+    /// CRC32 checksum of the encoded payload
+    pub checksum: u32,
+
+    /// Size of the encoded payload following the header, in bytes
+    pub payload_size: usize,
+}
+
 /// List of errors when decoding bytes into [`Bytecode`].
 #[derive(thiserror::Error, Debug)]
 pub enum BytecodeDecodeError {
@@ -48,6 +87,10 @@ pub enum BytecodeDecodeError {
     MagicNumber,
     #[error("invalid version")]
     Version,
+    #[error("unexpected end of input")]
+    Truncated,
+    #[error("malformed payload: {0}")]
+    Payload(#[from] bincode::error::DecodeError),
 }
 
 impl Bytecode {
@@ -70,39 +113,61 @@ impl Bytecode {
         buffer
     }
 
-    /// Decodes bytecode into an array of bytes.
-    pub fn from_bytes(buffer: &[u8]) -> Result<Self, BytecodeDecodeError> {
+    /// Returns `true` if `buffer` starts with [`BEL_MAGIC`].
+    pub fn is_encoded(buffer: &[u8]) -> bool {
+        buffer.starts_with(&BEL_MAGIC)
+    }
+
+    /// Reads and validates the header of encoded bytecode without decoding
+    /// the payload.
+    pub fn read_header(buffer: &[u8]) -> Result<BytecodeHeader, BytecodeDecodeError> {
         let mut cursor = Cursor::new(buffer);
 
         let mut magic = [0u8; 4];
-        cursor.read_exact(&mut magic).unwrap();
+        cursor
+            .read_exact(&mut magic)
+            .map_err(|_| BytecodeDecodeError::Truncated)?;
         if magic != BEL_MAGIC {
             return Err(BytecodeDecodeError::MagicNumber);
         }
 
         let mut version = [0u8; 2];
-        cursor.read_exact(&mut version).unwrap();
+        cursor
+            .read_exact(&mut version)
+            .map_err(|_| BytecodeDecodeError::Truncated)?;
         let version = u16::from_le_bytes(version);
         if version != BEL_VERSION {
             return Err(BytecodeDecodeError::Version);
         }
 
-        let mut stored_cksum = [0u8; 4];
-        cursor.read_exact(&mut stored_cksum).unwrap();
-        let stored_cksum = u32::from_le_bytes(stored_cksum);
+        let mut checksum = [0u8; 4];
+        cursor
+            .read_exact(&mut checksum)
+            .map_err(|_| BytecodeDecodeError::Truncated)?;
+        let checksum = u32::from_le_bytes(checksum);
+
+        Ok(BytecodeHeader {
+            version,
+            checksum,
+            payload_size: buffer.len() - HEADER_SIZE,
+        })
+    }
 
-        let start = cursor.position() as usize;
-        let encoded_data = &buffer[start..];
+    /// Decodes bytecode from an array of bytes.
+    pub fn from_bytes(buffer: &[u8]) -> Result<Self, BytecodeDecodeError> {
+        let header = Self::read_header(buffer)?;
+
+        let encoded_data = &buffer[HEADER_SIZE..];
         let mut hasher = Hasher::new();
         hasher.update(encoded_data);
         let computed_cksum = hasher.finalize();
 
-        if stored_cksum != computed_cksum {
+        if header.checksum != computed_cksum {
             return Err(BytecodeDecodeError::Checksum);
         }
 
         let config = config::standard();
-        let (decoded, _) = decode_from_slice(encoded_data, config).unwrap();
+        let (decoded, _) = decode_from_slice(encoded_data, config)?;
 
         Ok(decoded)
     }
@@ -208,4 +273,31 @@ mod tests {
             Err(BytecodeDecodeError::Checksum)
         ));
     }
+
+    #[test]
+    fn errors_truncated_header() {
+        assert!(matches!(
+            Bytecode::from_bytes(&BEL_MAGIC),
+            Err(BytecodeDecodeError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn reads_header() {
+        let original_bytecode = Bytecode {
+            instructions: vec![1, 2, 3, 4, 5],
+            constants: vec![Constant::Integer(12345)],
+        };
+
+        let bytes = original_bytecode.into_bytes();
+        let header = Bytecode::read_header(&bytes).unwrap();
+
+        assert!(Bytecode::is_encoded(&bytes));
+        assert_eq!(header.version, BEL_VERSION);
+        assert_eq!(
+            header.checksum,
+            u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]])
+        );
+        assert_eq!(header.payload_size, bytes.len() - 10);
+    }
 }
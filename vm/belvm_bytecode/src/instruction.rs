@@ -0,0 +1,477 @@
+//! Typed view over encoded instructions.
+//!
+//! Consumers of bytecode (the VM, the disassembler, optimization passes and
+//! tests) decode instructions through [`Instruction`] instead of reading bytes
+//! by hand. The layout of every opcode comes from the opcode table in
+//! [`crate::opcode`].
+//!
+//! Operands that don't fit their declared width are encoded behind a
+//! [`WIDE`](crate::opcode::WIDE) prefix, which doubles the width of every
+//! operand of the instruction. [`Instruction::encode`] adds the prefix when
+//! needed and [`Instruction::decode`] reads it transparently.
+
+use std::ops::{Deref, DerefMut};
+
+use crate::opcode::{Opcode, OperandType};
+
+/// Maximum number of operands an instruction can carry
+pub const MAX_OPERANDS: usize = 2;
+
+/// Operand values of an [`Instruction`], in encoding order
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Operands {
+    values: [i64; MAX_OPERANDS],
+    len: usize,
+}
+
+impl Operands {
+    /// Creates a list of operands.
+    ///
+    /// # Panics
+    /// Panics if more than [`MAX_OPERANDS`] values are given.
+    pub fn new(values: &[i64]) -> Self {
+        let mut operands = Self {
+            values: [0; MAX_OPERANDS],
+            len: values.len(),
+        };
+
+        operands.values[..values.len()].copy_from_slice(values);
+        operands
+    }
+}
+
+impl Deref for Operands {
+    type Target = [i64];
+
+    fn deref(&self) -> &Self::Target {
+        &self.values[..self.len]
+    }
+}
+
+impl DerefMut for Operands {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.values[..self.len]
+    }
+}
+
+/// List of errors when decoding an [`Instruction`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("unexpected end of instructions at {0:#06x}")]
+    UnexpectedEnd(usize),
+
+    #[error("unknown opcode {byte:#04x} at {offset:#06x}")]
+    UnknownOpcode { offset: usize, byte: u8 },
+
+    #[error("truncated operands for {name} at {offset:#06x}")]
+    Truncated { offset: usize, name: &'static str },
+
+    #[error("WIDE prefix before {name} at {offset:#06x}, which takes no operands")]
+    InvalidWide { offset: usize, name: &'static str },
+}
+
+/// List of errors when encoding an [`Instruction`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    #[error("{name} expects {expected} operands, got {got}")]
+    OperandCount {
+        name: &'static str,
+        expected: usize,
+        got: usize,
+    },
+
+    #[error("operand {value} of {name} does not fit in {ty:?}")]
+    OperandOutOfRange {
+        name: &'static str,
+        ty: OperandType,
+        value: i64,
+    },
+}
+
+/// A single decoded instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    /// Offset of the instruction in the instruction stream, including its
+    /// [`WIDE`](crate::opcode::WIDE) prefix if it has one
+    pub offset: usize,
+
+    /// The opcode of the instruction
+    pub op: Opcode,
+
+    /// Operand values, laid out as declared in the opcode table
+    pub operands: Operands,
+
+    /// Whether the instruction is encoded with a
+    /// [`WIDE`](crate::opcode::WIDE) prefix even if its operands would fit
+    /// without it. Operands that need the prefix always get it.
+    pub wide: bool,
+}
+
+impl Instruction {
+    /// Creates an instruction at offset zero, ready to be encoded.
+    pub fn new(op: Opcode, operands: &[i64]) -> Self {
+        Self {
+            offset: 0,
+            op,
+            operands: Operands::new(operands),
+            wide: false,
+        }
+    }
+
+    /// Decodes the instruction starting at `bytes[offset]`.
+    pub fn decode(bytes: &[u8], offset: usize) -> Result<Self, DecodeError> {
+        let read_op = |at: usize| {
+            let Some(&byte) = bytes.get(at) else {
+                return Err(DecodeError::UnexpectedEnd(at));
+            };
+
+            Opcode::from_byte(byte).ok_or(DecodeError::UnknownOpcode { offset: at, byte })
+        };
+
+        let mut op = read_op(offset)?;
+        let wide = op == Opcode::Wide;
+
+        if wide {
+            op = match read_op(offset + 1) {
+                Err(DecodeError::UnexpectedEnd(_)) => {
+                    return Err(DecodeError::Truncated { offset, name: "WIDE" });
+                },
+                result => result?,
+            };
+
+            if op.operands().is_empty() || op == Opcode::Wide {
+                return Err(DecodeError::InvalidWide {
+                    offset,
+                    name: op.name(),
+                });
+            }
+        }
+
+        let info = op.info();
+        let size = Self::encoded_size(op, wide);
+        if offset + size > bytes.len() {
+            return Err(DecodeError::Truncated {
+                offset,
+                name: info.name,
+            });
+        }
+
+        let mut values = [0; MAX_OPERANDS];
+        let mut i = offset + 1 + wide as usize;
+
+        for (value, ty) in values.iter_mut().zip(info.operands) {
+            let b = &bytes[i..];
+
+            *value = match (ty, wide) {
+                (OperandType::U8, false) => b[0] as i64,
+                (OperandType::U16, false) => u16::from_be_bytes([b[0], b[1]]) as i64,
+                (OperandType::I16, false) => i16::from_be_bytes([b[0], b[1]]) as i64,
+                (OperandType::U8, true) => u16::from_be_bytes([b[0], b[1]]) as i64,
+                (OperandType::U16, true) => u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as i64,
+                (OperandType::I16, true) => i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as i64,
+            };
+
+            i += if wide { ty.wide_width() } else { ty.width() };
+        }
+
+        Ok(Self {
+            offset,
+            op,
+            operands: Operands::new(&values[..info.operands.len()]),
+            wide,
+        })
+    }
+
+    fn encoded_size(op: Opcode, wide: bool) -> usize {
+        let info = op.info();
+
+        if wide {
+            2 + info.operands.iter().map(|ty| ty.wide_width()).sum::<usize>()
+        } else {
+            info.size()
+        }
+    }
+
+    /// Returns `true` if the instruction is encoded with a
+    /// [`WIDE`](crate::opcode::WIDE) prefix, either because [`Self::wide`] is
+    /// set or because an operand doesn't fit its declared width.
+    pub fn is_wide(&self) -> bool {
+        self.wide
+            || self
+                .operands
+                .iter()
+                .zip(self.op.operands())
+                .any(|(&value, ty)| !ty.fits(value))
+    }
+
+    /// Appends the encoded instruction to `buffer`.
+    ///
+    /// The offset of the instruction is ignored.
+    pub fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), EncodeError> {
+        let info = self.op.info();
+
+        if info.operands.len() != self.operands.len() {
+            return Err(EncodeError::OperandCount {
+                name: info.name,
+                expected: info.operands.len(),
+                got: self.operands.len(),
+            });
+        }
+
+        for (&value, &ty) in self.operands.iter().zip(info.operands) {
+            if !ty.fits_wide(value) {
+                return Err(EncodeError::OperandOutOfRange {
+                    name: info.name,
+                    ty,
+                    value,
+                });
+            }
+        }
+
+        let wide = self.is_wide();
+        if wide {
+            buffer.push(Opcode::Wide.byte());
+        }
+
+        buffer.push(self.op.byte());
+
+        // every value was range checked above, so the casts are lossless
+        for (&value, &ty) in self.operands.iter().zip(info.operands) {
+            match (ty, wide) {
+                (OperandType::U8, false) => buffer.push(value as u8),
+                (OperandType::U16, false) => buffer.extend((value as u16).to_be_bytes()),
+                (OperandType::I16, false) => buffer.extend((value as i16).to_be_bytes()),
+                (OperandType::U8, true) => buffer.extend((value as u16).to_be_bytes()),
+                (OperandType::U16, true) => buffer.extend((value as u32).to_be_bytes()),
+                (OperandType::I16, true) => buffer.extend((value as i32).to_be_bytes()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Encodes the instruction into a new buffer.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut buffer = Vec::with_capacity(self.size());
+        self.encode(&mut buffer)?;
+
+        Ok(buffer)
+    }
+
+    /// Encoded size of the instruction in bytes, including the
+    /// [`WIDE`](crate::opcode::WIDE) prefix if it needs one.
+    pub fn size(&self) -> usize {
+        Self::encoded_size(self.op, self.is_wide())
+    }
+
+    /// Offset of the instruction following this one.
+    pub fn next_offset(&self) -> usize {
+        self.offset + self.size()
+    }
+
+    /// Returns `true` for [`Opcode::Jump`] and [`Opcode::JumpIfFalse`].
+    pub fn is_jump(&self) -> bool {
+        matches!(self.op, Opcode::Jump | Opcode::JumpIfFalse)
+    }
+
+    /// Absolute offset a jump lands on, or `None` if this is not a jump or the
+    /// target would be before the start of the stream.
+    ///
+    /// Jump offsets are relative to the next instruction.
+    pub fn jump_target(&self) -> Option<usize> {
+        if !self.is_jump() {
+            return None;
+        }
+
+        let target = self.next_offset() as i64 + self.operands[0];
+        usize::try_from(target).ok()
+    }
+}
+
+/// Iterator over the instructions of an instruction stream.
+///
+/// See [`instructions`].
+pub struct Instructions<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Iterator for Instructions<'_> {
+    type Item = Result<Instruction, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.bytes.len() {
+            return None;
+        }
+
+        let result = Instruction::decode(self.bytes, self.offset);
+
+        self.offset = match &result {
+            Ok(instruction) => instruction.next_offset(),
+            Err(DecodeError::UnknownOpcode { offset, .. } | DecodeError::InvalidWide { offset, .. }) => offset + 1,
+            Err(_) => self.bytes.len(),
+        };
+
+        Some(result)
+    }
+}
+
+/// Decodes every instruction in `bytes`, in order.
+///
+/// An unknown opcode or a misplaced [`WIDE`](crate::opcode::WIDE) prefix
+/// yields an error and decoding resumes at the next byte. A truncated
+/// instruction yields an error and ends the iteration.
+pub fn instructions(bytes: &[u8]) -> Instructions<'_> {
+    Instructions { bytes, offset: 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcode;
+
+    #[test]
+    fn decode_roundtrip() {
+        let instructions = [
+            Instruction::new(Opcode::Pop, &[]),
+            Instruction::new(Opcode::Constant, &[65534]),
+            Instruction::new(Opcode::Jump, &[-12]),
+            Instruction::new(Opcode::GetLocal, &[3]),
+            Instruction::new(Opcode::MakeArray, &[2]),
+        ];
+
+        for instruction in instructions {
+            let bytes = instruction.to_bytes().unwrap();
+
+            assert_eq!(bytes.len(), instruction.size());
+            assert_eq!(Instruction::decode(&bytes, 0), Ok(instruction));
+        }
+    }
+
+    #[test]
+    fn encoding_matches_encoders() {
+        let bytes = Instruction::new(Opcode::JumpIfFalse, &[-3]).to_bytes().unwrap();
+
+        assert_eq!(bytes, opcode::jump_if_false(-3));
+    }
+
+    #[test]
+    fn wide_roundtrip() {
+        let instructions = [
+            Instruction::new(Opcode::Constant, &[70000]),
+            Instruction::new(Opcode::Jump, &[-40000]),
+            Instruction::new(Opcode::GetLocal, &[300]),
+        ];
+
+        for instruction in instructions {
+            let bytes = instruction.to_bytes().unwrap();
+
+            assert_eq!(bytes[0], opcode::WIDE);
+            assert_eq!(bytes.len(), instruction.size());
+            assert_eq!(
+                Instruction::decode(&bytes, 0),
+                Ok(Instruction {
+                    wide: true,
+                    ..instruction
+                })
+            );
+        }
+
+        let bytes = Instruction::new(Opcode::GetLocal, &[300]).to_bytes().unwrap();
+        assert_eq!(bytes, vec![opcode::WIDE, opcode::GET_LOCAL, 0x01, 0x2C]);
+    }
+
+    #[test]
+    fn forced_wide() {
+        let instruction = Instruction {
+            wide: true,
+            ..Instruction::new(Opcode::Constant, &[1])
+        };
+
+        assert_eq!(
+            instruction.to_bytes().unwrap(),
+            vec![opcode::WIDE, opcode::CONSTANT, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn invalid_wide() {
+        assert_eq!(
+            Instruction::decode(&[opcode::WIDE, opcode::POP], 0),
+            Err(DecodeError::InvalidWide { offset: 0, name: "POP" })
+        );
+        assert_eq!(
+            Instruction::decode(&[opcode::WIDE], 0),
+            Err(DecodeError::Truncated {
+                offset: 0,
+                name: "WIDE"
+            })
+        );
+        assert_eq!(
+            Instruction::decode(&[opcode::WIDE, opcode::CONSTANT, 0, 0], 0),
+            Err(DecodeError::Truncated {
+                offset: 0,
+                name: "CONSTANT"
+            })
+        );
+    }
+
+    #[test]
+    fn encode_errors() {
+        let mut buffer = vec![opcode::TRUE];
+
+        assert_eq!(
+            Instruction::new(Opcode::GetLocal, &[65536]).encode(&mut buffer),
+            Err(EncodeError::OperandOutOfRange {
+                name: "GET_LOCAL",
+                ty: OperandType::U8,
+                value: 65536,
+            })
+        );
+        assert_eq!(
+            Instruction::new(Opcode::Pop, &[1]).encode(&mut buffer),
+            Err(EncodeError::OperandCount {
+                name: "POP",
+                expected: 0,
+                got: 1,
+            })
+        );
+        assert_eq!(buffer, vec![opcode::TRUE]);
+    }
+
+    #[test]
+    fn iterates_with_offsets() {
+        let mut bytes = Vec::new();
+        bytes.extend(opcode::constant(1));
+        bytes.push(0xFF);
+        bytes.extend(opcode::jump(-5));
+        bytes.push(opcode::CONSTANT);
+
+        let decoded: Vec<_> = instructions(&bytes).collect();
+
+        assert_eq!(
+            decoded,
+            vec![
+                Ok(Instruction {
+                    offset: 0,
+                    op: Opcode::Constant,
+                    operands: Operands::new(&[1]),
+                    wide: false,
+                }),
+                Err(DecodeError::UnknownOpcode { offset: 3, byte: 0xFF }),
+                Ok(Instruction {
+                    offset: 4,
+                    op: Opcode::Jump,
+                    operands: Operands::new(&[-5]),
+                    wide: false,
+                }),
+                Err(DecodeError::Truncated {
+                    offset: 7,
+                    name: "CONSTANT",
+                }),
+            ]
+        );
+
+        assert_eq!(decoded[2].as_ref().unwrap().jump_target(), Some(2));
+    }
+}
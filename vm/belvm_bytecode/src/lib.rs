@@ -1,4 +1,7 @@
 mod bytecode;
+mod instruction;
 pub mod opcode;
+pub mod register;
 
 pub use bytecode::*;
+pub use instruction::*;
@@ -3,220 +3,196 @@
 //! This module defines the bytecode instruction set architecture of The
 //! Belalang VM. Each opcode represents an operation that the VM can execute,
 //! encoded as single-byte values followed by optional operands.
+//!
+//! The opcode table below is the single source of truth for opcode values,
+//! mnemonics, operand layouts and encoders. Everything else (the compiler, the
+//! VM and the disassembler) goes through the items it generates, usually via
+//! [`Instruction`](crate::Instruction).
+
+use belvm_macros::opcodes;
+
+/// Type of an operand following an opcode. Operands are encoded big-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandType {
+    U8,
+    U16,
+    I16,
+}
 
-/// No operation -- Does nothing (1 byte)
-pub const NOOP: u8 = 0x00;
+impl OperandType {
+    /// Encoded width of the operand in bytes
+    pub fn width(self) -> usize {
+        match self {
+            OperandType::U8 => 1,
+            OperandType::U16 | OperandType::I16 => 2,
+        }
+    }
 
-/// Stack operation -- Pop from stack (1 byte)
-pub const POP: u8 = 0x01;
+    /// Encoded width of the operand in bytes after a [`WIDE`] prefix
+    pub fn wide_width(self) -> usize {
+        self.width() * 2
+    }
 
-/// Arithmetic operation -- Add top two stack values (1 byte)
-pub const ADD: u8 = 0x10;
+    /// Returns `true` if `value` can be encoded without a [`WIDE`] prefix
+    pub fn fits(self, value: i64) -> bool {
+        match self {
+            OperandType::U8 => u8::try_from(value).is_ok(),
+            OperandType::U16 => u16::try_from(value).is_ok(),
+            OperandType::I16 => i16::try_from(value).is_ok(),
+        }
+    }
 
-/// Arithmetic operation -- Subtract top two stack values (1 byte)
-pub const SUB: u8 = 0x11;
+    /// Returns `true` if `value` can be encoded with a [`WIDE`] prefix
+    pub fn fits_wide(self, value: i64) -> bool {
+        match self {
+            OperandType::U8 => u16::try_from(value).is_ok(),
+            OperandType::U16 => u32::try_from(value).is_ok(),
+            OperandType::I16 => i32::try_from(value).is_ok(),
+        }
+    }
+}
 
-/// Arithmetic operation -- Multiply top two stack values (1 byte)
-pub const MUL: u8 = 0x12;
+/// Metadata of an opcode, as declared in the opcode table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    /// Mnemonic of the opcode
+    pub name: &'static str,
 
-/// Arithmetic operation -- Divide top two stack values (1 byte)
-pub const DIV: u8 = 0x13;
+    /// Operands following the opcode, in encoding order
+    pub operands: &'static [OperandType],
+}
 
-/// Arithmetic operation -- Modulo of top two stack values (1 byte)
-pub const MOD: u8 = 0x14;
+impl OpcodeInfo {
+    /// Encoded size of the instruction in bytes, including the opcode
+    pub fn size(&self) -> usize {
+        1 + self.operands.iter().map(|operand| operand.width()).sum::<usize>()
+    }
+}
 
-/// Constants -- Load constant from constant pool (3 bytes: opcode + 16-bit
-/// index)
-pub const CONSTANT: u8 = 0x20;
+opcodes! {
+    /// No operation -- Does nothing
+    NOOP = 0x00,
 
-/// Constants -- Push boolean value `true` (1 byte)
-pub const TRUE: u8 = 0x21;
+    /// Stack operation -- Pop from stack
+    POP = 0x01,
 
-/// Constants -- Push boolean value `false` (1 byte)
-pub const FALSE: u8 = 0x22;
+    /// Prefix -- Doubles the width of every operand of the next instruction,
+    /// so 8-bit operands become 16-bit and 16-bit operands become 32-bit
+    WIDE = 0x0F,
 
-/// Constants -- Push null value (1 byte)
-pub const NULL: u8 = 0x23;
+    /// Arithmetic operation -- Add top two stack values
+    ADD = 0x10,
 
-/// Comparison operation -- Compares top two stack values for equality (1 byte)
-pub const EQUAL: u8 = 0x30;
+    /// Arithmetic operation -- Subtract top two stack values
+    SUB = 0x11,
 
-/// Comparison operation -- Compares top two stack values for inequality (1
-/// byte)
-pub const NOT_EQUAL: u8 = 0x31;
+    /// Arithmetic operation -- Multiply top two stack values
+    MUL = 0x12,
 
-/// Comparison operation -- TOS-1 < TOS (1 byte)
-pub const LESS_THAN: u8 = 0x32;
+    /// Arithmetic operation -- Divide top two stack values
+    DIV = 0x13,
 
-/// Comparison operation -- TOS-1 <= TOS (1 byte)
-pub const LESS_THAN_EQUAL: u8 = 0x33;
+    /// Arithmetic operation -- Modulo of top two stack values
+    MOD = 0x14,
 
-/// Logical operation -- TOS-1 && TOS (1 byte)
-pub const AND: u8 = 0x40;
+    /// Constants -- Load constant from constant pool
+    CONSTANT = 0x20 (index: u16),
 
-/// Logical operation -- TOS-1 || TOS (1 byte)
-pub const OR: u8 = 0x41;
+    /// Constants -- Push boolean value `true`
+    TRUE = 0x21,
 
-/// Logical operation -- TOS-1 bit and TOS (1 byte)
-pub const BIT_AND: u8 = 0x50;
+    /// Constants -- Push boolean value `false`
+    FALSE = 0x22,
 
-/// Logical operation -- TOS-1 bit or TOS (1 byte)
-pub const BIT_OR: u8 = 0x51;
+    /// Constants -- Push null value
+    NULL = 0x23,
 
-/// Logical operation -- TOS-1 bit xor TOS (1 byte)
-pub const BIT_XOR: u8 = 0x52;
+    /// Comparison operation -- Compares top two stack values for equality
+    EQUAL = 0x30,
 
-/// Logical operation -- TOS-1 << TOS (1 byte)
-pub const BIT_SL: u8 = 0x53;
+    /// Comparison operation -- Compares top two stack values for inequality
+    NOT_EQUAL = 0x31,
 
-/// Logical operation -- TOS-1 >> TOS (1 byte)
-pub const BIT_SR: u8 = 0x54;
+    /// Comparison operation -- TOS-1 < TOS
+    LESS_THAN = 0x32,
 
-/// Unary operation -- !TOS (1 byte)
-pub const BANG: u8 = 0x60;
+    /// Comparison operation -- TOS-1 <= TOS
+    LESS_THAN_EQUAL = 0x33,
 
-/// Unary operation -- -TOS (1 byte)
-pub const MINUS: u8 = 0x61;
+    /// Logical operation -- TOS-1 && TOS
+    AND = 0x40,
 
-/// Jump operation -- Unconditional jump (3 bytes: opcode + 16-bit offset)
-pub const JUMP: u8 = 0x70;
+    /// Logical operation -- TOS-1 || TOS
+    OR = 0x41,
 
-/// Jump operation -- Conditional jump if popped TOS is false (3 bytes: opcode +
-/// 16-bit offset)
-pub const JUMP_IF_FALSE: u8 = 0x71;
+    /// Logical operation -- TOS-1 bit and TOS
+    BIT_AND = 0x50,
 
-/// Global variable -- Set global variable (3 bytes: opcode + 16-bit index)
-pub const SET_GLOBAL: u8 = 0x80;
+    /// Logical operation -- TOS-1 bit or TOS
+    BIT_OR = 0x51,
 
-/// Global variable -- Get global variable (3 bytes: opcode + 16-bit index)
-pub const GET_GLOBAL: u8 = 0x81;
+    /// Logical operation -- TOS-1 bit xor TOS
+    BIT_XOR = 0x52,
 
-/// Local variable -- Get local variable (3 bytes: opcode + 16-bit index)
-pub const SET_LOCAL: u8 = 0x90;
+    /// Logical operation -- TOS-1 << TOS
+    BIT_SL = 0x53,
 
-/// Local variable -- Set local variable (3 bytes: opcode + 16-bit index)
-pub const GET_LOCAL: u8 = 0x91;
+    /// Logical operation -- TOS-1 >> TOS
+    BIT_SR = 0x54,
 
-/// Functions -- Builtin function lookup (2 bytes: opcode + 8-bit index)
-pub const GET_BUILTIN: u8 = 0xA0;
+    /// Unary operation -- !TOS
+    BANG = 0x60,
 
-/// Functions -- Function call (2 bytes: opcode + 8 bit-index)
-pub const CALL: u8 = 0xB0;
+    /// Unary operation -- -TOS
+    MINUS = 0x61,
 
-/// Functions -- Void return (1 byte)
-pub const RETURN: u8 = 0xB1;
+    /// Jump operation -- Unconditional jump, relative to the next instruction
+    JUMP = 0x70 (offset: i16),
 
-/// Functions -- Valued return (1 byte)
-pub const RETURN_VALUE: u8 = 0xB2;
+    /// Jump operation -- Conditional jump if popped TOS is false, relative to
+    /// the next instruction
+    JUMP_IF_FALSE = 0x71 (offset: i16),
 
-/// Arrays -- Array creation (2 bytes: opcode + 8-bit element count)
-pub const MAKE_ARRAY: u8 = 0xC0;
+    /// Global variable -- Set global variable
+    SET_GLOBAL = 0x80 (index: u16),
 
-/// Arrays -- Array indexing (1 byte)
-pub const INDEX: u8 = 0xC1;
+    /// Global variable -- Get global variable
+    GET_GLOBAL = 0x81 (index: u16),
 
-/// Encodes a [`CONSTANT`] instruction with 16-bit index
-///
-/// # Arguments
-/// * `v` - Constant pool index (0-65535)
-///
-/// # Returns
-/// 3-byte array: [[`CONSTANT`], hi_byte, lo_byte]
-pub fn constant(v: u16) -> [u8; 3] {
-    [CONSTANT, (v >> 8) as u8, (v & 0xFF) as u8]
-}
+    /// Local variable -- Set local variable
+    SET_LOCAL = 0x90 (index: u8),
 
-/// Encodes a [`JUMP`] instruction with 16-bit offset
-///
-/// # Arguments
-/// * `v` - The jump offset (0-65535)
-///
-/// # Returns
-/// 3-byte array: [[`JUMP`], hi_byte, lo_byte]
-pub fn jump(v: u16) -> [u8; 3] {
-    [JUMP, (v >> 8) as u8, (v & 0xFF) as u8]
-}
+    /// Local variable -- Get local variable
+    GET_LOCAL = 0x91 (index: u8),
 
-/// Encodes a [`JUMP_IF_FALSE`] instruction with 16-bit offset
-///
-/// # Arguments
-/// * `v` - The jump offset (0-65535)
-///
-/// # Returns
-/// 3-byte array: [[`JUMP_IF_FALSE`], hi_byte, lo_byte]
-pub fn jump_if_false(v: u16) -> [u8; 3] {
-    [JUMP_IF_FALSE, (v >> 8) as u8, (v & 0xFF) as u8]
-}
+    /// Functions -- Builtin function lookup
+    GET_BUILTIN = 0xA0 (index: u8),
 
-/// Encodes a [`SET_GLOBAL`] instruction with 16-bit global index
-///
-/// # Arguments
-/// * `v` - Global variable index (0-65535)
-///
-/// # Returns
-/// 3-byte array: [[`SET_GLOBAL`], hi_byte, lo_byte]
-pub fn set_global(v: u16) -> [u8; 3] {
-    [SET_GLOBAL, (v >> 8) as u8, (v & 0xFF) as u8]
-}
+    /// Functions -- Call the function on TOS with its `argc` arguments below
+    /// it
+    CALL = 0xB0 (argc: u8),
 
-/// Encodes a [`GET_GLOBAL`] instruction with 16-bit global index
-///
-/// # Arguments
-/// * `v` - Global variable index (0-65535)
-///
-/// # Returns
-/// 3-byte array: [[`GET_GLOBAL`], hi_byte, lo_byte]
-pub fn get_global(v: u16) -> [u8; 3] {
-    [GET_GLOBAL, (v >> 8) as u8, (v & 0xFF) as u8]
-}
+    /// Functions -- Void return
+    RETURN = 0xB1,
 
-/// Encodes a [`SET_LOCAL`] instruction with 8-bit local index
-///
-/// # Arguments
-/// * `v` - Local variable slot (0-255)
-///
-/// # Returns
-/// 2-byte array: [[`SET_LOCAL`], index]
-pub fn set_local(v: u8) -> [u8; 2] {
-    [SET_LOCAL, v]
-}
+    /// Functions -- Valued return
+    RETURN_VALUE = 0xB2,
 
-/// Encodes a [`GET_LOCAL`] instruction with 8-bit local index
-///
-/// # Arguments
-/// * `v` - Local variable slot (0-255)
-///
-/// # Returns
-/// 2-byte array: [[`GET_LOCAL`], index]
-pub fn get_local(v: u8) -> [u8; 2] {
-    [GET_LOCAL, v]
-}
+    /// Functions -- Call the function on TOS with its `argc` arguments below
+    /// it, reusing the frame of the current function
+    TAIL_CALL = 0xB3 (argc: u8),
 
-/// Encodes a [`GET_BUILTIN`] instruction with 8-bit function index
-///
-/// # Arguments
-/// * `v` - Builtin function identifier (0-255)
-///
-/// # Returns
-/// 2-byte array: [[`GET_BUILTIN`], index]
-pub fn get_builtin(v: u8) -> [u8; 2] {
-    [GET_BUILTIN, v]
-}
+    /// Arrays -- Array creation from elements popped off the stack
+    MAKE_ARRAY = 0xC0 (count: u8),
 
-/// Encodes an [`MAKE_ARRAY`] instruction with 8-bit element count
-///
-/// # Arguments
-/// * `v` - Number of elements to pop from stack (0-255)
-///
-/// # Returns
-/// 2-byte array: [[`MAKE_ARRAY`], count]
-pub fn make_array(v: u8) -> [u8; 2] {
-    [MAKE_ARRAY, v]
+    /// Arrays -- Array indexing
+    INDEX = 0xC1,
 }
 
 #[cfg(test)]
 mod tests {
     use crate::opcode;
+    use crate::opcode::{Opcode, OperandType};
 
     #[test]
     fn constant() {
@@ -227,4 +203,39 @@ mod tests {
         assert_eq!(bytes[1], 255);
         assert_eq!(bytes[2], 254);
     }
+
+    #[test]
+    fn jump() {
+        assert_eq!(opcode::jump(-3), [opcode::JUMP, 0xFF, 0xFD]);
+    }
+
+    #[test]
+    fn info() {
+        let info = opcode::info(opcode::GET_LOCAL).unwrap();
+
+        assert_eq!(info.name, "GET_LOCAL");
+        assert_eq!(info.operands, &[OperandType::U8]);
+        assert_eq!(info.size(), 2);
+        assert!(opcode::info(0xFF).is_none());
+    }
+
+    #[test]
+    fn operand_widths() {
+        assert!(OperandType::U8.fits(255));
+        assert!(!OperandType::U8.fits(256));
+        assert!(OperandType::U8.fits_wide(256));
+        assert!(OperandType::I16.fits(-32768));
+        assert!(!OperandType::I16.fits(40000));
+        assert!(OperandType::I16.fits_wide(-40000));
+        assert!(!OperandType::U16.fits_wide(-1));
+        assert_eq!(OperandType::U16.wide_width(), 4);
+    }
+
+    #[test]
+    fn opcode_from_byte() {
+        assert_eq!(Opcode::from_byte(opcode::JUMP_IF_FALSE), Some(Opcode::JumpIfFalse));
+        assert_eq!(Opcode::JumpIfFalse.byte(), opcode::JUMP_IF_FALSE);
+        assert_eq!(Opcode::JumpIfFalse.name(), "JUMP_IF_FALSE");
+        assert_eq!(Opcode::from_byte(0xFF), None);
+    }
 }
@@ -0,0 +1,121 @@
+//! Register-based instruction set of The Belalang VM.
+//!
+//! This is an experimental alternative to the stack-based instruction set in
+//! [`opcode`](crate::opcode). Instead of pushing and popping operands, every
+//! instruction names the frame slots (registers) it reads and writes, so
+//! `a = b + c` is a single three-address `ADD`. Registers are 8-bit, so a
+//! frame has at most 256 of them.
+//!
+//! Opcode values mirror the stack instruction set where an equivalent exists.
+//! There is no `WIDE` prefix.
+
+use belvm_macros::opcodes;
+
+use crate::opcode::{OpcodeInfo, OperandType};
+
+opcodes! {
+    /// No operation -- Does nothing
+    NOOP = 0x00,
+
+    /// Register operation -- R[dst] = R[src]
+    MOVE = 0x01 (dst: u8, src: u8),
+
+    /// Arithmetic operation -- R[dst] = R[left] + R[right]
+    ADD = 0x10 (dst: u8, left: u8, right: u8),
+
+    /// Arithmetic operation -- R[dst] = R[left] - R[right]
+    SUB = 0x11 (dst: u8, left: u8, right: u8),
+
+    /// Arithmetic operation -- R[dst] = R[left] * R[right]
+    MUL = 0x12 (dst: u8, left: u8, right: u8),
+
+    /// Arithmetic operation -- R[dst] = R[left] / R[right]
+    DIV = 0x13 (dst: u8, left: u8, right: u8),
+
+    /// Arithmetic operation -- R[dst] = R[left] % R[right]
+    MOD = 0x14 (dst: u8, left: u8, right: u8),
+
+    /// Constants -- Load constant from constant pool into R[dst]
+    CONSTANT = 0x20 (dst: u8, index: u16),
+
+    /// Constants -- R[dst] = true
+    TRUE = 0x21 (dst: u8),
+
+    /// Constants -- R[dst] = false
+    FALSE = 0x22 (dst: u8),
+
+    /// Constants -- R[dst] = null
+    NULL = 0x23 (dst: u8),
+
+    /// Comparison operation -- R[dst] = R[left] == R[right]
+    EQUAL = 0x30 (dst: u8, left: u8, right: u8),
+
+    /// Comparison operation -- R[dst] = R[left] != R[right]
+    NOT_EQUAL = 0x31 (dst: u8, left: u8, right: u8),
+
+    /// Comparison operation -- R[dst] = R[left] < R[right]
+    LESS_THAN = 0x32 (dst: u8, left: u8, right: u8),
+
+    /// Comparison operation -- R[dst] = R[left] <= R[right]
+    LESS_THAN_EQUAL = 0x33 (dst: u8, left: u8, right: u8),
+
+    /// Logical operation -- R[dst] = R[left] && R[right]
+    AND = 0x40 (dst: u8, left: u8, right: u8),
+
+    /// Logical operation -- R[dst] = R[left] || R[right]
+    OR = 0x41 (dst: u8, left: u8, right: u8),
+
+    /// Logical operation -- R[dst] = R[left] bit and R[right]
+    BIT_AND = 0x50 (dst: u8, left: u8, right: u8),
+
+    /// Logical operation -- R[dst] = R[left] bit or R[right]
+    BIT_OR = 0x51 (dst: u8, left: u8, right: u8),
+
+    /// Logical operation -- R[dst] = R[left] bit xor R[right]
+    BIT_XOR = 0x52 (dst: u8, left: u8, right: u8),
+
+    /// Logical operation -- R[dst] = R[left] << R[right]
+    BIT_SL = 0x53 (dst: u8, left: u8, right: u8),
+
+    /// Logical operation -- R[dst] = R[left] >> R[right]
+    BIT_SR = 0x54 (dst: u8, left: u8, right: u8),
+
+    /// Unary operation -- R[dst] = !R[src]
+    BANG = 0x60 (dst: u8, src: u8),
+
+    /// Unary operation -- R[dst] = -R[src]
+    MINUS = 0x61 (dst: u8, src: u8),
+
+    /// Jump operation -- Unconditional jump, relative to the next instruction
+    JUMP = 0x70 (offset: i16),
+
+    /// Jump operation -- Conditional jump if R[cond] is false, relative to
+    /// the next instruction
+    JUMP_IF_FALSE = 0x71 (cond: u8, offset: i16),
+
+    /// Functions -- Void return
+    RETURN = 0xB1,
+
+    /// Functions -- Return R[src]
+    RETURN_VALUE = 0xB2 (src: u8),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opcode::OperandType;
+    use crate::register;
+
+    #[test]
+    fn three_address() {
+        assert_eq!(register::add(3, 1, 2), [register::ADD, 3, 1, 2]);
+
+        let info = register::info(register::ADD).unwrap();
+        assert_eq!(info.operands, &[OperandType::U8; 3]);
+        assert_eq!(info.size(), 4);
+    }
+
+    #[test]
+    fn jump_if_false() {
+        assert_eq!(register::jump_if_false(4, -3), [register::JUMP_IF_FALSE, 4, 0xFF, 0xFD]);
+    }
+}
@@ -3,3 +3,45 @@ pub enum MemoryError {
     #[error("allocation failed")]
     AllocationFailed,
 }
+
+/// A problem found by [`GcHeap::verify`](crate::gc::GcHeap::verify)
+///
+/// Addresses are the ones of the objects involved.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum HeapError {
+    #[error("heap counts {counted} objects, but holds {found}")]
+    ObjectCount { counted: usize, found: usize },
+
+    #[error("heap counts {counted} bytes, but its objects take {found}")]
+    ByteCount { counted: usize, found: usize },
+
+    #[error("object at {0:#x} is on the heap twice")]
+    Duplicate(usize),
+
+    #[error("object at {0:#x} doesn't have the layout of its header")]
+    WrongLayout(usize),
+
+    #[error("object at {0:#x} has unknown type {1:#x}")]
+    UnknownType(usize, u32),
+
+    #[error("object at {0:#x} is still marked")]
+    Marked(usize),
+
+    #[error("object at {0:#x} is in the wrong generation")]
+    WrongGeneration(usize),
+
+    #[error("remembered object at {0:#x} isn't on the heap or not flagged as remembered")]
+    Remembered(usize),
+
+    #[error("heap remembers {counted} objects, but {found} are flagged as remembered")]
+    RememberedCount { counted: usize, found: usize },
+
+    #[error("pointer to {0:#x} doesn't point to an object on the heap")]
+    DanglingPointer(usize),
+
+    #[error("weak pointers to {0:#x} weren't cleared along with their object")]
+    WeakRef(usize),
+
+    #[error("old object at {object:#x} points to young object at {young:#x} without being remembered")]
+    Unremembered { object: usize, young: usize },
+}
@@ -1,18 +1,47 @@
-use std::alloc::{Layout, alloc};
+use std::alloc::{Layout, alloc, dealloc};
 use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::ptr::{NonNull, drop_in_place};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use crate::errors::MemoryError;
+use crate::errors::{HeapError, MemoryError};
+use crate::nursery::{self, BLOCK_SIZE, Nursery};
+use crate::snapshot::{HeapSnapshot, ObjectSnapshot, RootSnapshot};
+
+/// Default bytes the heap can take before its first collection
+pub const DEFAULT_THRESHOLD: usize = 1 << 20;
+
+/// Default factor the live bytes are multiplied by to get the bytes the heap
+/// can take before its next collection
+pub const DEFAULT_GROWTH_FACTOR: f64 = 2.0;
+
+/// Bytes of nursery the VMs start with. A heap has no nursery until
+/// [`GcHeap::set_nursery_size`] gives it one.
+pub const DEFAULT_NURSERY_SIZE: usize = 256 * 1024;
 
 #[derive(Clone)]
 pub struct GcObjectHeader {
     pub obj_type: u32,
-    pub ref_count: Cell<usize>,
-    pub is_marked: bool,
+    pub is_marked: Cell<bool>,
+
+    /// Whether the object is in the nursery and hasn't survived a collection
+    /// yet
+    pub is_young: bool,
+
+    /// Whether the object was allocated in a block of the nursery rather than
+    /// on its own
+    pub in_block: bool,
+
+    /// Whether the object is in the remembered set of the heap
+    pub is_remembered: Cell<bool>,
+
+    /// Layout the object was allocated with, used to free it
+    pub layout: Layout,
     pub next: Option<NonNull<dyn GcObject>>,
 }
 
@@ -20,14 +49,62 @@ impl GcObjectHeader {
     pub fn new<T: GcObject>() -> Self {
         Self {
             obj_type: T::r#type(),
-            ref_count: Cell::new(0),
-            is_marked: false,
+            is_marked: Cell::new(false),
+            is_young: false,
+            in_block: false,
+            is_remembered: Cell::new(false),
+            layout: Layout::new::<T>(),
             next: None,
         }
     }
 }
 
-pub trait GcObject {
+/// Objects that can point to other objects on the heap
+///
+/// The collector calls [`Trace::trace`] on every object it reaches, which has
+/// to hand each of the pointers it holds to the [`Tracer`]. An object that
+/// forgets one of them will see it freed from under it.
+pub trait Trace {
+    fn trace(&self, tracer: &mut Tracer);
+}
+
+impl<T: GcObject + 'static> Trace for GcPtr<T> {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark(*self);
+    }
+}
+
+impl<T: Trace> Trace for [T] {
+    fn trace(&self, tracer: &mut Tracer) {
+        for item in self {
+            item.trace(tracer);
+        }
+    }
+}
+
+impl<T: Trace> Trace for Vec<T> {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.as_slice().trace(tracer);
+    }
+}
+
+impl<T: Trace> Trace for Option<T> {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Some(item) = self {
+            item.trace(tracer);
+        }
+    }
+}
+
+impl<K, V: Trace, S> Trace for HashMap<K, V, S> {
+    fn trace(&self, tracer: &mut Tracer) {
+        for value in self.values() {
+            value.trace(tracer);
+        }
+    }
+}
+
+pub trait GcObject: Trace {
     fn header(&self) -> &GcObjectHeader;
     fn header_mut(&mut self) -> &mut GcObjectHeader;
 
@@ -44,20 +121,313 @@ pub trait GcObject {
         let hash = hasher.finish();
         hash as u32
     }
+
+    /// Runs right before the object is reclaimed, to clean up what it holds
+    /// outside of the heap, like a file handle. Does nothing by default.
+    ///
+    /// Every object a collection reclaims is finalized before any of them is
+    /// freed, so unlike [`Drop`], a finalizer can still use the objects it
+    /// points to. Finalizers run in no particular order, after the weak
+    /// pointers to the objects were cleared.
+    fn finalize(&mut self) {}
+}
+
+/// Marks the objects reachable from the roots of a collection
+///
+/// Marked objects are queued rather than traced right away, so deeply nested
+/// objects don't overflow the native stack.
+#[derive(Default)]
+pub struct Tracer {
+    worklist: Vec<NonNull<dyn GcObject>>,
+    mode: Mode,
 }
 
+/// What a [`Tracer`] does with the pointers it comes across
 #[derive(Default)]
+enum Mode {
+    /// Marks every object, for a full collection
+    #[default]
+    Full,
+
+    /// Only marks young objects, for a minor collection
+    Minor,
+
+    /// Checks the pointers rather than marking anything, for a verification
+    Verify(Verifier),
+
+    /// Records the pointers without following them, for a snapshot
+    Record(Recorder),
+}
+
+impl Tracer {
+    fn new(mode: Mode) -> Self {
+        Self {
+            worklist: Vec::new(),
+            mode,
+        }
+    }
+
+    /// Marks the object `ptr` points to, and later everything it points to.
+    pub fn mark<T: GcObject + 'static>(&mut self, ptr: GcPtr<T>) {
+        let header = match &mut self.mode {
+            Mode::Full => ptr.header(),
+
+            // old objects survive a minor collection no matter what
+            Mode::Minor if !ptr.header().is_young => return,
+            Mode::Minor => ptr.header(),
+
+            Mode::Verify(verifier) => {
+                if verifier.visit(ptr.ptr) {
+                    self.worklist.push(ptr.ptr);
+                }
+
+                return;
+            },
+
+            Mode::Record(recorder) => {
+                recorder.record(ptr.ptr);
+                return;
+            },
+        };
+
+        if !header.is_marked.replace(true) {
+            self.worklist.push(ptr.ptr);
+        }
+    }
+
+    /// Names the roots marked from now on, which is what
+    /// [heap snapshots](GcHeap::snapshot) show them as. Does nothing when
+    /// collecting.
+    pub fn root(&mut self, name: &str) {
+        if let Mode::Record(recorder) = &mut self.mode {
+            recorder.names.push(name.to_owned());
+        }
+    }
+
+    fn drain(&mut self) {
+        while let Some(object) = self.worklist.pop() {
+            if let Mode::Verify(verifier) = &mut self.mode {
+                verifier.parent = Some(object);
+            }
+
+            // Safety: only objects that were reachable, and so still alive,
+            // are queued
+            unsafe { object.as_ref() }.trace(self);
+        }
+    }
+}
+
+/// Records the pointers a [`Tracer`] comes across for a heap snapshot
+struct Recorder {
+    /// Names given to the roots, the last one being the current one
+    names: Vec<String>,
+
+    /// Addresses pointed to, with the index of the name of the root they
+    /// were marked as
+    references: Vec<(usize, usize)>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self {
+            names: vec![String::from("roots")],
+            references: Vec::new(),
+        }
+    }
+}
+
+impl Recorder {
+    fn record(&mut self, object: NonNull<dyn GcObject>) {
+        self.references.push((self.names.len() - 1, object.as_ptr().addr()));
+    }
+}
+
+/// Checks the pointers a [`Tracer`] comes across when verifying a heap
+struct Verifier {
+    /// Addresses of the objects on the heap
+    live: HashSet<usize>,
+
+    /// Addresses of the objects reached so far
+    visited: HashSet<usize>,
+
+    /// Object being traced, or `None` while tracing the roots
+    parent: Option<NonNull<dyn GcObject>>,
+
+    /// First problem found
+    error: Option<HeapError>,
+}
+
+impl Verifier {
+    /// Checks a pointer to `object`, returning whether it has to be traced.
+    fn visit(&mut self, object: NonNull<dyn GcObject>) -> bool {
+        let address = object.as_ptr().addr();
+
+        // a dangling pointer can't even be dereferenced to read its header
+        if !self.live.contains(&address) {
+            self.error.get_or_insert(HeapError::DanglingPointer(address));
+            return false;
+        }
+
+        if let Some(parent) = self.parent {
+            // Safety: only objects on the heap are traced
+            let (parent_header, header) = unsafe { (parent.as_ref().header(), object.as_ref().header()) };
+
+            // a minor collection would miss the young object
+            if header.is_young && !parent_header.is_young && !parent_header.is_remembered.get() {
+                self.error.get_or_insert(HeapError::Unremembered {
+                    object: parent.as_ptr().addr(),
+                    young: address,
+                });
+            }
+        }
+
+        self.visited.insert(address)
+    }
+}
+
+/// What is on a [`GcHeap`] and what its collections cost
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GcStats {
+    /// Number of objects on the heap
+    pub objects: usize,
+
+    /// Bytes taken by the objects on the heap
+    pub bytes: usize,
+
+    /// Bytes of memory the heap holds. This is more than [`GcStats::bytes`]
+    /// when the blocks objects are allocated in aren't full.
+    pub reserved: usize,
+
+    /// Objects on the heap by type, the ones taking the most bytes first
+    pub types: Vec<TypeStats>,
+
+    /// Number of full collections run
+    pub collections: usize,
+
+    /// Number of minor collections run, which only collect the nursery
+    pub minor_collections: usize,
+
+    /// Time spent in collections, minor ones included
+    pub total_pause: Duration,
+
+    /// Time spent in the longest collection
+    pub max_pause: Duration,
+}
+
+/// The objects of one type on a [`GcHeap`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeStats {
+    /// The [`GcObjectHeader::obj_type`] of the objects
+    pub obj_type: u32,
+
+    /// The [`GcObject::type_name`] of the objects
+    pub name: String,
+
+    pub objects: usize,
+    pub bytes: usize,
+}
+
+/// A mark-and-sweep heap
+///
+/// Every object is linked into a list through its header. A collection marks
+/// the objects reachable from the roots it is given, then walks the list and
+/// frees the rest.
+///
+/// The heap can also be generational. With a nursery, small objects are bump
+/// allocated in it and start out young. A minor collection only marks young
+/// objects, from the roots and the remembered set: the old objects a young
+/// one may have been stored in, which [`GcHeap::write_barrier`] records. Young
+/// objects that survive are promoted to the old generation where they are,
+/// and the others are dropped without walking the old generation at all.
 pub struct GcHeap {
+    /// Objects of the old generation
     pub start: Option<NonNull<dyn GcObject>>,
+
+    nursery: Nursery,
+
+    /// Old objects that may point to young ones
+    remembered: Vec<NonNull<dyn GcObject>>,
+
+    /// Number of objects on the heap
+    objects: usize,
+
+    /// Bytes taken by the objects on the heap
+    bytes_allocated: usize,
+
+    /// Bytes taken by the objects in blocks of the nursery, young or
+    /// promoted, which are counted with their blocks instead
+    block_bytes: usize,
+
+    /// Bytes allocated past which [`GcHeap::should_collect`] says yes
+    threshold: usize,
+
+    /// Least bytes the heap can take before a collection
+    min_threshold: usize,
+
+    /// Factor the live bytes are multiplied by to get the next threshold
+    growth_factor: f64,
+
+    /// Most bytes of memory the heap should hold
+    limit: Option<usize>,
+
+    /// Bytes the nursery was asked to take, which the limit can cut down
+    nursery_size: usize,
+
+    /// Names of the types allocated so far, by their `obj_type`
+    type_names: HashMap<u32, String>,
+
+    /// Objects with weak pointers to them, by address
+    weak_refs: HashMap<usize, WeakRef>,
+
+    /// Whether anything was allocated since the last collection
+    allocated_since_collection: bool,
+
+    collections: usize,
+    minor_collections: usize,
+    total_pause: Duration,
+    max_pause: Duration,
+
     _marker: PhantomData<GcObjectHeader>,
 }
 
+impl Default for GcHeap {
+    fn default() -> Self {
+        Self {
+            start: None,
+            nursery: Nursery::default(),
+            remembered: Vec::new(),
+            objects: 0,
+            bytes_allocated: 0,
+            block_bytes: 0,
+            threshold: DEFAULT_THRESHOLD,
+            min_threshold: DEFAULT_THRESHOLD,
+            growth_factor: DEFAULT_GROWTH_FACTOR,
+            limit: None,
+            nursery_size: 0,
+            type_names: HashMap::new(),
+            weak_refs: HashMap::new(),
+            allocated_since_collection: false,
+            collections: 0,
+            minor_collections: 0,
+            total_pause: Duration::ZERO,
+            max_pause: Duration::ZERO,
+            _marker: PhantomData,
+        }
+    }
+}
+
 impl GcHeap {
-    pub fn alloc<T: GcObject>(&mut self, object: T) -> Result<GcPtr<T>, MemoryError> {
-        let layout = Layout::new::<T>();
+    pub fn alloc<T: GcObject + 'static>(&mut self, object: T) -> Result<GcPtr<T>, MemoryError> {
+        let GcObjectHeader { obj_type, layout, .. } = *object.header();
+        debug_assert_eq!(layout, Layout::new::<T>(), "object header has the wrong layout");
+
+        let young = self.nursery.alloc(layout);
 
         let base_ptr: *mut T = unsafe {
-            let ptr = alloc(layout) as *mut T;
+            let ptr = match young {
+                Some(ptr) => ptr.as_ptr() as *mut T,
+                None => alloc(layout) as *mut T,
+            };
 
             if ptr.is_null() {
                 return Err(MemoryError::AllocationFailed);
@@ -69,32 +439,665 @@ impl GcHeap {
         };
 
         // Safety: base_ptr was just created in this function call
-        let ptr = base_ptr as *mut dyn GcObject;
-        unsafe { (*ptr).header_mut().next = self.start };
+        let ptr = unsafe { NonNull::new_unchecked(base_ptr as *mut dyn GcObject) };
 
-        // Safety: base_ptr was just created in this function call
-        unsafe { self.start = Some(NonNull::new_unchecked(ptr)) };
+        if young.is_some() {
+            // Safety: base_ptr was just created in this function call
+            let header = unsafe { (*base_ptr).header_mut() };
+            header.is_young = true;
+            header.in_block = true;
 
-        // Safety: base_ptr was just created in this function call
-        unsafe { Ok(GcPtr::new(&mut *base_ptr)) }
+            self.nursery.objects.push(ptr);
+            self.block_bytes += layout.size();
+        } else {
+            // Safety: base_ptr was just created in this function call
+            unsafe { (*base_ptr).header_mut().next = self.start };
+            self.start = Some(ptr);
+
+            // it may have been created with pointers to young objects
+            if self.is_generational() {
+                self.remember(ptr);
+            }
+        }
+
+        self.objects += 1;
+        self.bytes_allocated += layout.size();
+        self.allocated_since_collection = true;
+        self.type_names.entry(obj_type).or_insert_with(T::type_name);
+
+        // Safety: base_ptr was just created in this function call. The pointer
+        // is derived from it rather than from a reference, so it stays valid
+        // when the heap writes to the header later.
+        unsafe { Ok(GcPtr::from_raw(NonNull::new_unchecked(base_ptr))) }
+    }
+
+    /// Returns the number of bytes taken by the objects on the heap.
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    /// Returns the number of bytes of memory the heap holds.
+    ///
+    /// Objects in the nursery are bump allocated in blocks of
+    /// [`BLOCK_SIZE`](crate::nursery::BLOCK_SIZE), and a block lives as long as
+    /// any object promoted in it, so a few small objects can hold on to a lot
+    /// more memory than they take. Blocks are counted whole, whether the
+    /// nursery is still allocating from them or they only hold old objects.
+    pub fn bytes_reserved(&self) -> usize {
+        self.bytes_allocated - self.block_bytes + self.nursery.reserved()
+    }
+
+    /// Returns the number of objects on the heap.
+    pub fn object_count(&self) -> usize {
+        self.objects
+    }
+
+    /// Returns the number of full collections run so far.
+    pub fn collections(&self) -> usize {
+        self.collections
+    }
+
+    /// Returns the number of minor collections run so far.
+    pub fn minor_collections(&self) -> usize {
+        self.minor_collections
+    }
+
+    /// Returns what is on the heap and what its collections cost.
+    ///
+    /// This walks every object on the heap to count them by type.
+    pub fn stats(&self) -> GcStats {
+        let mut types: HashMap<u32, TypeStats> = HashMap::new();
+
+        for object in self.objects() {
+            // Safety: every object on the heap is alive
+            let header = unsafe { object.as_ref() }.header();
+
+            let stats = types.entry(header.obj_type).or_insert_with(|| TypeStats {
+                obj_type: header.obj_type,
+                name: self.type_names[&header.obj_type].clone(),
+                objects: 0,
+                bytes: 0,
+            });
+            stats.objects += 1;
+            stats.bytes += header.layout.size();
+        }
+
+        let mut types: Vec<TypeStats> = types.into_values().collect();
+        types.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+
+        GcStats {
+            objects: self.objects,
+            bytes: self.bytes_allocated,
+            reserved: self.bytes_reserved(),
+            types,
+            collections: self.collections,
+            minor_collections: self.minor_collections,
+            total_pause: self.total_pause,
+            max_pause: self.max_pause,
+        }
+    }
+
+    /// Sets the least bytes the heap can take before a collection, which is
+    /// also when the first one runs. Defaults to [`DEFAULT_THRESHOLD`].
+    pub fn set_threshold(&mut self, bytes: usize) {
+        self.min_threshold = bytes;
+        self.threshold = self.next_threshold();
+    }
+
+    /// Sets the factor the bytes still live after a collection are multiplied
+    /// by to get the bytes the heap can take before the next one. Defaults to
+    /// [`DEFAULT_GROWTH_FACTOR`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is less than 1, which would collect on every
+    /// allocation once enough is live.
+    pub fn set_growth_factor(&mut self, factor: f64) {
+        assert!(factor >= 1.0, "growth factor must be at least 1, got {factor}");
+
+        self.growth_factor = factor;
+        self.threshold = self.next_threshold();
+    }
+
+    /// Sets the most bytes of memory the heap should hold, as counted by
+    /// [`GcHeap::bytes_reserved`], or removes the limit. There is none by
+    /// default.
+    ///
+    /// Allocating past the limit still works. It is up to the owner of the
+    /// heap to collect it once [`GcHeap::should_collect`] says so, and to give
+    /// up if it is still [over the limit](GcHeap::is_over_limit) afterwards.
+    ///
+    /// The nursery is cut down to at most half of the limit, leaving the rest
+    /// to the old generation.
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+        self.resize_nursery();
+    }
+
+    /// Returns the most bytes of memory the heap should hold, if there is a
+    /// limit.
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Returns whether the heap holds more bytes of memory than its limit.
+    pub fn is_over_limit(&self) -> bool {
+        self.limit.is_some_and(|limit| self.bytes_reserved() > limit)
+    }
+
+    /// Sets the bytes the nursery can take, rounded up to whole blocks of
+    /// [`BLOCK_SIZE`]. Zero, the default, turns the nursery off and makes
+    /// every object old from the start. A [limit](GcHeap::set_limit) can cut
+    /// the nursery down.
+    ///
+    /// Objects already in the nursery stay there until the next collection.
+    pub fn set_nursery_size(&mut self, bytes: usize) {
+        self.nursery_size = bytes;
+        self.resize_nursery();
+    }
+
+    /// Returns the bytes the nursery can take.
+    pub fn nursery_size(&self) -> usize {
+        self.nursery.capacity()
+    }
+
+    fn resize_nursery(&mut self) {
+        // whole blocks, rounded down so they fit
+        let max = self
+            .limit
+            .map_or(usize::MAX, |limit| limit / 2 / BLOCK_SIZE * BLOCK_SIZE);
+
+        self.nursery.set_capacity(self.nursery_size.min(max));
+    }
+
+    /// Whether old objects can point to young ones, so the remembered set has
+    /// to be kept
+    fn is_generational(&self) -> bool {
+        self.nursery.capacity() > 0 || !self.nursery.objects.is_empty()
+    }
+
+    /// Returns a weak pointer to the object `ptr` points to, which tells when
+    /// a collection reclaims it.
+    pub fn downgrade<T: GcObject + 'static>(&mut self, ptr: GcPtr<T>) -> WeakGcPtr<T> {
+        let weak = self
+            .weak_refs
+            .entry(ptr.ptr.as_ptr().addr())
+            .or_insert_with(|| WeakRef {
+                object: ptr.ptr,
+                alive: Rc::new(Cell::new(true)),
+            });
+
+        WeakGcPtr {
+            ptr: ptr.ptr,
+            alive: Rc::clone(&weak.alive),
+        }
+    }
+
+    /// Records that a pointer was stored in `object`.
+    ///
+    /// A minor collection doesn't trace old objects, so an old object that
+    /// points to a young one has to be in the remembered set for the young
+    /// one to survive. Call this every time a pointer is stored in an object
+    /// after it was allocated, before the next collection.
+    pub fn write_barrier<T: GcObject + 'static>(&mut self, object: GcPtr<T>) {
+        if !object.header().is_young && self.is_generational() {
+            self.remember(object.ptr);
+        }
+    }
+
+    fn remember(&mut self, object: NonNull<dyn GcObject>) {
+        // Safety: the object was just allocated or is pointed to by a GcPtr
+        if !unsafe { object.as_ref() }.header().is_remembered.replace(true) {
+            self.remembered.push(object);
+        }
+    }
+
+    /// Iterates over the objects on the heap, young ones first.
+    fn objects(&self) -> impl Iterator<Item = NonNull<dyn GcObject>> {
+        let old = std::iter::successors(self.start, |object| {
+            // Safety: every object in the list is alive
+            unsafe { object.as_ref() }.header().next
+        });
+
+        self.nursery.objects.iter().copied().chain(old)
+    }
+
+    fn next_threshold(&self) -> usize {
+        let grown = self.bytes_allocated as f64 * self.growth_factor;
+        self.min_threshold.max(grown as usize)
+    }
+
+    /// Returns whether enough was allocated since the last collection to run
+    /// another one, or the heap went over its limit.
+    ///
+    /// With the `gc-stress` feature, a single allocation is enough.
+    pub fn should_collect(&self) -> bool {
+        let stressed = cfg!(feature = "gc-stress") && self.allocated_since_collection;
+
+        stressed || self.bytes_allocated >= self.threshold || self.is_over_limit()
+    }
+
+    /// Returns whether the nursery is full, so a minor collection should run.
+    pub fn should_collect_minor(&self) -> bool {
+        self.nursery.is_full
+    }
+
+    /// Frees every object that isn't reachable from the roots `trace_roots`
+    /// marks.
+    ///
+    /// Pointers to freed objects dangle, so everything that is still used has
+    /// to be marked, including objects only held by the caller.
+    ///
+    /// With the `gc-stress` feature, the heap is [verified](GcHeap::verify)
+    /// before and after the collection, which is why the roots can be traced
+    /// more than once.
+    pub fn collect<F>(&mut self, trace_roots: F)
+    where
+        F: Fn(&mut Tracer),
+    {
+        self.stress_verify("before a collection", &trace_roots);
+        let start = Instant::now();
+
+        // every object is traced, so nothing has to be remembered
+        for object in self.remembered.drain(..) {
+            // Safety: remembered objects are alive until a full collection
+            unsafe { object.as_ref() }.header().is_remembered.set(false);
+        }
+
+        let mut tracer = Tracer::default();
+        trace_roots(&mut tracer);
+        tracer.drain();
+
+        self.finalize(false);
+        self.sweep();
+        self.promote();
+        self.threshold = self.next_threshold();
+
+        self.collections += 1;
+        self.allocated_since_collection = false;
+        self.record_pause(start);
+        self.stress_verify("after a collection", &trace_roots);
+    }
+
+    /// Frees the young objects that aren't reachable from the roots
+    /// `trace_roots` marks or the remembered set, and promotes the others.
+    ///
+    /// Like [`GcHeap::collect`], everything that is still used has to be
+    /// marked. Old objects are left alone, even unreachable ones.
+    pub fn collect_minor<F>(&mut self, trace_roots: F)
+    where
+        F: Fn(&mut Tracer),
+    {
+        self.stress_verify("before a minor collection", &trace_roots);
+        let start = Instant::now();
+
+        let mut tracer = Tracer::new(Mode::Minor);
+        trace_roots(&mut tracer);
+
+        for object in self.remembered.drain(..) {
+            // Safety: remembered objects are alive until a full collection
+            let object = unsafe { object.as_ref() };
+            object.header().is_remembered.set(false);
+            object.trace(&mut tracer);
+        }
+
+        tracer.drain();
+        self.finalize(true);
+        self.promote();
+
+        self.minor_collections += 1;
+        self.allocated_since_collection = false;
+        self.record_pause(start);
+        self.stress_verify("after a minor collection", &trace_roots);
+    }
+
+    /// Checks that the heap is sound, and that every object reachable from
+    /// the roots `trace_roots` marks is on it.
+    ///
+    /// The headers of the objects have to be consistent with the heap: right
+    /// layout, known type, no marks left over from a collection, and in the
+    /// generation and remembered set they say they are in. Reachable objects
+    /// can only point to objects on the heap, and an old one pointing to a
+    /// young one has to be remembered. Only the first problem found is
+    /// returned.
+    ///
+    /// This only reads the heap, and never dereferences a pointer before
+    /// checking that it points to an object on the heap, so it is sound to
+    /// call on a broken heap as long as the list of objects is intact. Like
+    /// [`GcHeap::collect`], everything that is still used has to be marked.
+    pub fn verify<F>(&self, trace_roots: F) -> Result<(), HeapError>
+    where
+        F: FnOnce(&mut Tracer),
+    {
+        let mut live = HashSet::new();
+        let mut bytes = 0;
+        let mut remembered = 0;
+
+        // the young objects come first, so an old object can't be young
+        let young = self.nursery.objects.len();
+
+        for (index, object) in self.objects().enumerate() {
+            let address = object.as_ptr().addr();
+
+            // more objects than counted could mean the list loops back
+            if index >= self.objects {
+                return Err(HeapError::ObjectCount {
+                    counted: self.objects,
+                    found: index + 1,
+                });
+            }
+
+            if !live.insert(address) {
+                return Err(HeapError::Duplicate(address));
+            }
+
+            // Safety: the object is on the heap
+            let object = unsafe { object.as_ref() };
+            let header = object.header();
+
+            if header.layout != Layout::for_value(object) {
+                return Err(HeapError::WrongLayout(address));
+            }
+
+            if !self.type_names.contains_key(&header.obj_type) {
+                return Err(HeapError::UnknownType(address, header.obj_type));
+            }
+
+            if header.is_marked.get() {
+                return Err(HeapError::Marked(address));
+            }
+
+            if header.is_young != (index < young) || (header.is_young && !header.in_block) {
+                return Err(HeapError::WrongGeneration(address));
+            }
+
+            bytes += header.layout.size();
+            remembered += header.is_remembered.get() as usize;
+        }
+
+        if live.len() != self.objects {
+            return Err(HeapError::ObjectCount {
+                counted: self.objects,
+                found: live.len(),
+            });
+        }
+
+        if bytes != self.bytes_allocated {
+            return Err(HeapError::ByteCount {
+                counted: self.bytes_allocated,
+                found: bytes,
+            });
+        }
+
+        for object in &self.remembered {
+            let address = object.as_ptr().addr();
+
+            // Safety: the object is checked to be on the heap first
+            if !live.contains(&address) || !unsafe { object.as_ref() }.header().is_remembered.get() {
+                return Err(HeapError::Remembered(address));
+            }
+        }
+
+        // an object can only be flagged by being in the remembered set
+        if remembered != self.remembered.len() {
+            return Err(HeapError::RememberedCount {
+                counted: self.remembered.len(),
+                found: remembered,
+            });
+        }
+
+        // weak pointers are cleared before their object is reclaimed
+        for (&address, weak) in &self.weak_refs {
+            if !weak.alive.get() || !live.contains(&address) {
+                return Err(HeapError::WeakRef(address));
+            }
+        }
+
+        let mut tracer = Tracer::new(Mode::Verify(Verifier {
+            live,
+            visited: HashSet::new(),
+            parent: None,
+            error: None,
+        }));
+
+        trace_roots(&mut tracer);
+        tracer.drain();
+
+        match tracer.mode {
+            Mode::Verify(Verifier { error: Some(err), .. }) => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Takes a snapshot of the objects on the heap and the pointers between
+    /// them, with the roots `trace_roots` marks. Name the roots with
+    /// [`Tracer::root`] to tell them apart.
+    ///
+    /// Pointers are only recorded, never followed, so taking a snapshot is
+    /// sound even with dangling pointers around.
+    pub fn snapshot<F>(&self, trace_roots: F) -> HeapSnapshot
+    where
+        F: FnOnce(&mut Tracer),
+    {
+        let objects = self.objects().map(|object| {
+            // Safety: every object on the heap is alive
+            let object = unsafe { object.as_ref() };
+            let header = object.header();
+
+            let mut tracer = Tracer::new(Mode::Record(Recorder::default()));
+            object.trace(&mut tracer);
+
+            let Mode::Record(recorder) = tracer.mode else {
+                unreachable!("tracer changed its mode");
+            };
+
+            ObjectSnapshot {
+                id: (object as *const dyn GcObject).addr(),
+                type_name: self.type_names[&header.obj_type].clone(),
+                size: header.layout.size(),
+                young: header.is_young,
+                references: recorder.references.into_iter().map(|(_, address)| address).collect(),
+                root_path: None,
+            }
+        });
+        let objects = objects.collect();
+
+        let mut tracer = Tracer::new(Mode::Record(Recorder::default()));
+        trace_roots(&mut tracer);
+
+        let Mode::Record(recorder) = tracer.mode else {
+            unreachable!("tracer changed its mode");
+        };
+
+        let roots = recorder.references.into_iter().map(|(name, object)| RootSnapshot {
+            name: recorder.names[name].clone(),
+            object,
+        });
+
+        HeapSnapshot::new(objects, roots.collect())
+    }
+
+    /// Verifies the heap with the `gc-stress` feature, panicking if it is
+    /// broken.
+    fn stress_verify<F>(&self, when: &str, trace_roots: F)
+    where
+        F: Fn(&mut Tracer),
+    {
+        if cfg!(feature = "gc-stress")
+            && let Err(err) = self.verify(trace_roots)
+        {
+            panic!("heap is broken {when}: {err}");
+        }
+    }
+
+    fn record_pause(&mut self, start: Instant) {
+        let pause = start.elapsed();
+        self.total_pause += pause;
+        self.max_pause = self.max_pause.max(pause);
+    }
+
+    /// Clears the weak pointers to the objects the collection is about to
+    /// reclaim, then finalizes them, before any of them is freed. A minor
+    /// collection only reclaims young objects.
+    fn finalize(&mut self, minor: bool) {
+        let is_dying = |object: NonNull<dyn GcObject>| {
+            // Safety: the heap's objects are alive until they are reclaimed
+            let header = unsafe { object.as_ref() }.header();
+            !header.is_marked.get() && (header.is_young || !minor)
+        };
+
+        // weak pointers no one holds anymore are forgotten too
+        self.weak_refs.retain(|_, weak| {
+            weak.alive.set(!is_dying(weak.object));
+            weak.alive.get() && Rc::strong_count(&weak.alive) > 1
+        });
+
+        // the young objects come first, and they are all a minor collection
+        // walks
+        let count = if minor {
+            self.nursery.objects.len()
+        } else {
+            self.objects
+        };
+
+        for mut object in self.objects().take(count) {
+            if is_dying(object) {
+                // Safety: the object is alive until it is reclaimed
+                unsafe { object.as_mut() }.finalize();
+            }
+        }
+    }
+
+    /// Moves the marked young objects to the old generation, clearing their
+    /// marks, and drops the others.
+    fn promote(&mut self) {
+        let mut freed = 0;
+        let mut freed_bytes = 0;
+
+        for mut object in std::mem::take(&mut self.nursery.objects) {
+            // Safety: young objects are alive until the nursery is reset
+            let header = unsafe { object.as_mut() }.header_mut();
+
+            if header.is_marked.replace(false) {
+                header.is_young = false;
+                header.next = self.start;
+                self.start = Some(object);
+
+                // Safety: the object was allocated in a block of the nursery
+                unsafe { nursery::retain(object) };
+            } else {
+                freed += 1;
+                freed_bytes += header.layout.size();
+
+                // Safety: the object is unreachable, and its memory goes back
+                // to the nursery
+                unsafe { drop_in_place(object.as_ptr()) };
+            }
+        }
+
+        self.nursery.reset();
+        self.objects -= freed;
+        self.bytes_allocated -= freed_bytes;
+        self.block_bytes -= freed_bytes;
+    }
+
+    /// Frees the unmarked objects and clears the marks of the others.
+    fn sweep(&mut self) {
+        let mut freed = 0;
+        let mut freed_bytes = 0;
+        let mut link = &mut self.start;
+
+        while let Some(mut object) = *link {
+            // Safety: every object in the list is alive
+            let header = unsafe { object.as_mut() }.header_mut();
+
+            if header.is_marked.replace(false) {
+                link = &mut header.next;
+                continue;
+            }
+
+            *link = header.next;
+            freed += 1;
+            freed_bytes += header.layout.size();
+
+            if header.in_block {
+                self.block_bytes -= header.layout.size();
+            }
+
+            // Safety: the object is unreachable and was just unlinked
+            if unsafe { free(object) } {
+                self.nursery.retained -= 1;
+            }
+        }
+
+        self.objects -= freed;
+        self.bytes_allocated -= freed_bytes;
     }
 }
 
 impl Drop for GcHeap {
     fn drop(&mut self) {
-        self.start = None;
+        for weak in self.weak_refs.values() {
+            weak.alive.set(false);
+        }
+
+        for mut object in self.objects() {
+            // Safety: every object on the heap is alive until the heap is gone
+            unsafe { object.as_mut() }.finalize();
+        }
+
+        for object in self.nursery.objects.drain(..) {
+            // Safety: young objects are alive, and their memory goes with the
+            // nursery
+            unsafe { drop_in_place(object.as_ptr()) };
+        }
+
+        let mut current = self.start.take();
+
+        while let Some(object) = current {
+            // Safety: every object in the list is alive, and nothing can use
+            // them once the heap is gone
+            current = unsafe { object.as_ref() }.header().next;
+            unsafe { free(object) };
+        }
+    }
+}
+
+/// Drops an object and gives its memory back. Returns whether that freed
+/// the block of the nursery it was promoted in.
+///
+/// # Safety
+///
+/// `object` has to be allocated by a [`GcHeap`] and never used again.
+unsafe fn free(object: NonNull<dyn GcObject>) -> bool {
+    let ptr = object.as_ptr();
+
+    // Safety: the caller guarantees the object is alive, and the layout is
+    // the one it was allocated with
+    unsafe {
+        let GcObjectHeader { layout, in_block, .. } = *(*ptr).header();
+        drop_in_place(ptr);
+
+        if in_block {
+            return nursery::release(object);
+        }
+
+        dealloc(ptr as *mut u8, layout);
     }
+
+    false
 }
 
+/// A pointer to an object on a [`GcHeap`]
+///
+/// Pointers are plain copies of an address. They don't keep their object
+/// alive; only being reachable from the roots of a collection does.
 pub struct GcPtr<T: GcObject + ?Sized> {
     ptr: NonNull<T>,
 }
 
 impl<T: GcObject + ?Sized> GcPtr<T> {
     pub fn new(ptr: &mut T) -> Self {
-        let base = ptr.header();
-        base.ref_count.set(base.ref_count.get() + 1);
-
         // Safety: pointer is valid since we just received it
         Self {
             ptr: unsafe { NonNull::new_unchecked(ptr) },
@@ -104,22 +1107,38 @@ impl<T: GcObject + ?Sized> GcPtr<T> {
     pub fn as_ptr(&self) -> *mut T {
         self.ptr.as_ptr()
     }
+
+    /// Returns the address of the object, so it can be stored elsewhere and
+    /// turned back with [`GcPtr::from_raw`].
+    pub fn into_raw(self) -> NonNull<T> {
+        self.ptr
+    }
+
+    /// Turns an address returned by [`GcPtr::into_raw`] back into a pointer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` has to come from [`GcPtr::into_raw`], and its object must not
+    /// have been freed by a collection since.
+    pub unsafe fn from_raw(ptr: NonNull<T>) -> Self {
+        Self { ptr }
+    }
 }
 
 impl<T: GcObject + ?Sized> Deref for GcPtr<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        // Safety: we maintain the invariant that ptr is always valid when GcPtr is
-        // created
+        // Safety: we maintain the invariant that ptr is always valid when GcPtr
+        // is created
         unsafe { self.ptr.as_ref() }
     }
 }
 
 impl<T: GcObject + ?Sized> DerefMut for GcPtr<T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        // Safety: we maintain the invariant that ptr is always valid when GcPtr is
-        // created
+        // Safety: we maintain the invariant that ptr is always valid when GcPtr
+        // is created
         unsafe { self.ptr.as_mut() }
     }
 }
@@ -132,21 +1151,55 @@ impl<T: GcObject + ?Sized> Debug for GcPtr<T> {
 
 impl<T: GcObject + ?Sized> Clone for GcPtr<T> {
     fn clone(&self) -> Self {
-        let base = self.header();
-        base.ref_count.set(base.ref_count.get() + 1);
+        *self
+    }
+}
+
+impl<T: GcObject + ?Sized> Copy for GcPtr<T> {}
+
+/// Where the heap keeps track of the weak pointers to an object
+struct WeakRef {
+    object: NonNull<dyn GcObject>,
+
+    /// Shared with every [`WeakGcPtr`] to the object
+    alive: Rc<Cell<bool>>,
+}
 
-        Self { ptr: self.ptr }
+/// A pointer to an object on a [`GcHeap`] that doesn't keep it alive
+///
+/// Unlike a [`GcPtr`], it knows when its object was reclaimed, so it can be
+/// kept around outside of the roots, like in a cache. Get one with
+/// [`GcHeap::downgrade`].
+pub struct WeakGcPtr<T: GcObject + ?Sized> {
+    ptr: NonNull<T>,
+    alive: Rc<Cell<bool>>,
+}
+
+impl<T: GcObject + ?Sized> WeakGcPtr<T> {
+    /// Returns a pointer to the object, unless a collection reclaimed it or
+    /// its heap was dropped.
+    ///
+    /// The pointer is an ordinary [`GcPtr`], so the object has to be marked
+    /// from then on to stay alive.
+    pub fn upgrade(&self) -> Option<GcPtr<T>> {
+        self.alive.get().then_some(GcPtr { ptr: self.ptr })
     }
 }
 
-impl<T: GcObject + ?Sized> Drop for GcPtr<T> {
-    fn drop(&mut self) {
-        let base = self.header();
-        base.ref_count.set(base.ref_count.get() - 1);
+impl<T: GcObject + ?Sized> Debug for WeakGcPtr<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.alive.get() {
+            true => write!(f, "Weak({:?})", self.ptr),
+            false => write!(f, "Weak(reclaimed)"),
+        }
+    }
+}
 
-        if base.ref_count.get() == 0 {
-            // Safety: we just checked that ref count is zero
-            unsafe { drop_in_place(self.ptr.as_ptr()) };
+impl<T: GcObject + ?Sized> Clone for WeakGcPtr<T> {
+    fn clone(&self) -> Self {
+        Self {
+            ptr: self.ptr,
+            alive: Rc::clone(&self.alive),
         }
     }
 }
@@ -0,0 +1,211 @@
+//! Bump allocation of young objects.
+//!
+//! The nursery hands out memory from fixed-size blocks by bumping a cursor.
+//! Objects are never moved: when some of them survive a collection they are
+//! promoted where they are, and their block leaves the nursery. From then on
+//! the block counts the old objects left in it, and is freed with the last
+//! one.
+
+use std::alloc::{Layout, alloc, dealloc, handle_alloc_error};
+use std::ptr::NonNull;
+
+use crate::gc::GcObject;
+
+/// Size of the blocks the nursery allocates from. Blocks are aligned to their
+/// size, so an object finds its block by masking its address.
+pub const BLOCK_SIZE: usize = 32 * 1024;
+
+/// Biggest object the nursery takes. Bigger ones go straight to the old
+/// generation.
+const MAX_OBJECT_SIZE: usize = BLOCK_SIZE / 8;
+
+/// Start of every block
+struct BlockHeader {
+    /// Number of old objects in the block
+    live: usize,
+}
+
+/// Offset of the first object in a block
+const BLOCK_START: usize = size_of::<BlockHeader>();
+
+fn block_layout() -> Layout {
+    Layout::from_size_align(BLOCK_SIZE, BLOCK_SIZE).unwrap()
+}
+
+/// Where objects are allocated until they survive a collection
+pub(crate) struct Nursery {
+    /// Blocks young objects are allocated from. None of them hold old
+    /// objects.
+    blocks: Vec<NonNull<BlockHeader>>,
+
+    /// Most blocks the nursery can take
+    capacity: usize,
+
+    /// Index of the block being allocated from
+    current: usize,
+
+    /// Offset of the next free byte in the current block
+    cursor: usize,
+
+    /// Objects allocated in the nursery since the last collection
+    pub objects: Vec<NonNull<dyn GcObject>>,
+
+    /// Set once an object didn't fit in the nursery
+    pub is_full: bool,
+
+    /// Number of blocks that left the nursery with promoted objects in them
+    /// and weren't freed yet
+    pub retained: usize,
+}
+
+impl Default for Nursery {
+    fn default() -> Self {
+        Self {
+            blocks: Vec::new(),
+            capacity: 0,
+            current: 0,
+            cursor: BLOCK_START,
+            objects: Vec::new(),
+            is_full: false,
+            retained: 0,
+        }
+    }
+}
+
+impl Nursery {
+    /// Returns the bytes the nursery can take.
+    pub fn capacity(&self) -> usize {
+        self.capacity * BLOCK_SIZE
+    }
+
+    /// Sets the bytes the nursery can take, rounded up to whole blocks.
+    pub fn set_capacity(&mut self, bytes: usize) {
+        self.capacity = bytes.div_ceil(BLOCK_SIZE);
+    }
+
+    /// Returns the bytes taken by the blocks of the nursery and by the ones
+    /// that left it, however much of them their objects use.
+    pub fn reserved(&self) -> usize {
+        (self.blocks.len() + self.retained) * BLOCK_SIZE
+    }
+
+    /// Returns room for an object with `layout`, or `None` if it doesn't fit.
+    pub fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        if layout.size() > MAX_OBJECT_SIZE || layout.align() > BLOCK_SIZE {
+            return None;
+        }
+
+        loop {
+            if let Some(&block) = self.blocks.get(self.current) {
+                let start = self.cursor.next_multiple_of(layout.align());
+
+                if start + layout.size() <= BLOCK_SIZE {
+                    self.cursor = start + layout.size();
+
+                    // Safety: the object ends within the block
+                    return Some(unsafe { block.cast::<u8>().add(start) });
+                }
+
+                self.current += 1;
+                self.cursor = BLOCK_START;
+                continue;
+            }
+
+            if self.blocks.len() >= self.capacity {
+                // a minor collection can't make room in a nursery that is
+                // turned off, only get rid of the objects left in it
+                self.is_full = self.capacity > 0 || !self.objects.is_empty();
+                return None;
+            }
+
+            // Safety: blocks aren't zero-sized
+            let block = unsafe { alloc(block_layout()) } as *mut BlockHeader;
+            let Some(block) = NonNull::new(block) else {
+                handle_alloc_error(block_layout());
+            };
+
+            // Safety: the block was just allocated
+            unsafe { block.write(BlockHeader { live: 0 }) };
+            self.blocks.push(block);
+        }
+    }
+
+    /// Starts over once every young object was promoted or dropped. Blocks
+    /// holding promoted objects leave the nursery.
+    pub fn reset(&mut self) {
+        debug_assert!(self.objects.is_empty(), "nursery reset with young objects left");
+
+        // Safety: blocks of the nursery are alive
+        let count = self.blocks.len();
+        self.blocks.retain(|block| unsafe { block.as_ref() }.live == 0);
+        self.retained += count - self.blocks.len();
+
+        // blocks over the capacity are left over from a bigger nursery
+        while self.blocks.len() > self.capacity {
+            let block = self.blocks.pop().unwrap();
+
+            // Safety: the block holds no objects
+            unsafe { dealloc(block.as_ptr() as *mut u8, block_layout()) };
+        }
+
+        self.current = 0;
+        self.cursor = BLOCK_START;
+        self.is_full = false;
+    }
+}
+
+impl Drop for Nursery {
+    fn drop(&mut self) {
+        for block in self.blocks.drain(..) {
+            // Safety: blocks of the nursery only hold young objects, which the
+            // heap dropped already
+            unsafe { dealloc(block.as_ptr() as *mut u8, block_layout()) };
+        }
+    }
+}
+
+fn block_of(object: NonNull<dyn GcObject>) -> NonNull<BlockHeader> {
+    object
+        .cast::<u8>()
+        .map_addr(|addr| {
+            // a block never starts at address zero
+            (addr.get() & !(BLOCK_SIZE - 1)).try_into().unwrap()
+        })
+        .cast()
+}
+
+/// Counts a promoted object in its block.
+///
+/// # Safety
+///
+/// `object` has to be allocated by a [`Nursery`].
+pub(crate) unsafe fn retain(object: NonNull<dyn GcObject>) {
+    // Safety: the block outlives the objects in it
+    unsafe { block_of(object).as_mut().live += 1 };
+}
+
+/// Stops counting a freed old object in its block, freeing the block with the
+/// last one. Returns whether the block was freed, so the caller can stop
+/// counting it in [`Nursery::retained`].
+///
+/// # Safety
+///
+/// `object` has to be an old object allocated by a [`Nursery`], and never used
+/// again.
+pub(crate) unsafe fn release(object: NonNull<dyn GcObject>) -> bool {
+    let mut block = block_of(object);
+
+    // Safety: the block outlives the objects in it, and it left the nursery
+    // when the object was promoted, so nothing else frees it
+    unsafe {
+        block.as_mut().live -= 1;
+
+        if block.as_ref().live > 0 {
+            return false;
+        }
+
+        dealloc(block.as_ptr() as *mut u8, block_layout());
+    }
+
+    true
+}
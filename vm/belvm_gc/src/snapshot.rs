@@ -0,0 +1,165 @@
+//! Snapshots of the object graph of a heap, for finding out what keeps
+//! memory alive.
+//!
+//! A snapshot is taken with [`GcHeap::snapshot`](crate::gc::GcHeap::snapshot)
+//! and written out as JSON. Objects never move, so an object has the same id
+//! in every snapshot taken while it lives, and the objects are sorted by id:
+//! diffing two snapshots of the same heap shows what was allocated and freed
+//! in between.
+//!
+//! Ids are addresses, though, and the address of a freed object gets reused.
+//! An object freed between two snapshots and a new one allocated at the same
+//! address look like a single object that survived, so compare their type
+//! names and sizes too before trusting that it did.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+/// The objects on a heap and the pointers between them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HeapSnapshot {
+    /// Bytes taken by the objects on the heap
+    pub bytes: usize,
+
+    /// Pointers to the objects the heap was given as roots
+    pub roots: Vec<RootSnapshot>,
+
+    /// Objects on the heap, by id
+    pub objects: Vec<ObjectSnapshot>,
+}
+
+/// A pointer from a root to an object
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RootSnapshot {
+    /// Name the root was given with [`Tracer::root`](crate::gc::Tracer::root)
+    pub name: String,
+
+    /// Id of the object pointed to
+    pub object: usize,
+}
+
+/// An object on a heap
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ObjectSnapshot {
+    /// Address of the object
+    pub id: usize,
+
+    /// Name of the type of the object, from
+    /// [`GcObject::type_name`](crate::gc::GcObject::type_name)
+    pub type_name: String,
+
+    /// Bytes taken by the object
+    pub size: usize,
+
+    /// Whether the object is in the nursery
+    pub young: bool,
+
+    /// Ids of the objects this one points to
+    pub references: Vec<usize>,
+
+    /// How the object is reached from the roots, or `None` if it isn't and
+    /// the next collection frees it
+    pub root_path: Option<RootPath>,
+}
+
+/// The last step of the shortest path from a root to an object
+///
+/// The whole path is found by following the retainers back to the root, see
+/// [`HeapSnapshot::path_to`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RootPath {
+    /// Name of the root the path starts from
+    pub root: String,
+
+    /// Id of the object before this one on the path, or `None` if the root
+    /// points to this one
+    pub retainer: Option<usize>,
+
+    /// Number of objects on the path, this one included
+    pub length: usize,
+}
+
+impl HeapSnapshot {
+    /// Sorts the objects and finds the shortest path to each of them from the
+    /// roots, in the order they were marked.
+    pub(crate) fn new(mut objects: Vec<ObjectSnapshot>, mut roots: Vec<RootSnapshot>) -> Self {
+        objects.sort_by_key(|object| object.id);
+
+        // the same root can be marked more than once, not always in a row
+        let mut seen = HashSet::new();
+        roots.retain(|root| seen.insert((root.name.clone(), root.object)));
+
+        let index: HashMap<usize, usize> = objects.iter().enumerate().map(|(i, object)| (object.id, i)).collect();
+        let mut queue = VecDeque::new();
+
+        for root in &roots {
+            let Some(&i) = index.get(&root.object) else {
+                continue;
+            };
+
+            if objects[i].root_path.is_none() {
+                objects[i].root_path = Some(RootPath {
+                    root: root.name.clone(),
+                    retainer: None,
+                    length: 1,
+                });
+                queue.push_back(i);
+            }
+        }
+
+        // breadth first, so the first path found to an object is the shortest
+        while let Some(i) = queue.pop_front() {
+            let path = objects[i].root_path.clone().unwrap();
+
+            for reference in objects[i].references.clone() {
+                let Some(&j) = index.get(&reference) else {
+                    continue;
+                };
+
+                if objects[j].root_path.is_none() {
+                    objects[j].root_path = Some(RootPath {
+                        root: path.root.clone(),
+                        retainer: Some(objects[i].id),
+                        length: path.length + 1,
+                    });
+                    queue.push_back(j);
+                }
+            }
+        }
+
+        Self {
+            bytes: objects.iter().map(|object| object.size).sum(),
+            roots,
+            objects,
+        }
+    }
+
+    /// Returns the object with id `id`.
+    pub fn object(&self, id: usize) -> Option<&ObjectSnapshot> {
+        let i = self.objects.binary_search_by_key(&id, |object| object.id).ok()?;
+        Some(&self.objects[i])
+    }
+
+    /// Returns the ids of the objects on the shortest path from a root to the
+    /// object with id `id`, starting with the one the root points to, or
+    /// `None` if the object is unreachable.
+    pub fn path_to(&self, id: usize) -> Option<Vec<usize>> {
+        let mut path = vec![id];
+        let mut retainer = self.object(id)?.root_path.as_ref()?.retainer;
+
+        while let Some(id) = retainer {
+            path.push(id);
+            retainer = self.object(id)?.root_path.as_ref()?.retainer;
+        }
+
+        path.reverse();
+        Some(path)
+    }
+
+    /// Writes the snapshot as pretty-printed JSON.
+    pub fn write_json<W: Write>(&self, writer: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(writer, self).map_err(io::Error::from)
+    }
+}
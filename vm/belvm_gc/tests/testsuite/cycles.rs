@@ -0,0 +1,197 @@
+//! Self-referential structures, which reference counting could never free.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use belvm_gc::gc::{GcHeap, GcObject, GcObjectHeader, GcPtr, Trace, Tracer};
+
+thread_local! {
+    /// Number of objects dropped on this thread
+    static DROPPED: Cell<usize> = const { Cell::new(0) };
+}
+
+fn dropped() -> usize {
+    DROPPED.with(Cell::get)
+}
+
+#[derive(Clone, Copy)]
+enum Value {
+    Integer(i64),
+    Array(GcPtr<Array>),
+    Map(GcPtr<Map>),
+    Closure(GcPtr<Closure>),
+}
+
+impl Trace for Value {
+    fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            Value::Integer(_) => {},
+            Value::Array(array) => array.trace(tracer),
+            Value::Map(map) => map.trace(tracer),
+            Value::Closure(closure) => closure.trace(tracer),
+        }
+    }
+}
+
+macro_rules! object {
+    ($name:ident { $field:ident: $type:ty }) => {
+        struct $name {
+            header: GcObjectHeader,
+            $field: $type,
+        }
+
+        impl $name {
+            fn alloc(heap: &mut GcHeap) -> GcPtr<Self> {
+                heap.alloc(Self {
+                    header: GcObjectHeader::new::<Self>(),
+                    $field: Default::default(),
+                })
+                .unwrap()
+            }
+        }
+
+        impl Trace for $name {
+            fn trace(&self, tracer: &mut Tracer) {
+                self.$field.trace(tracer);
+            }
+        }
+
+        impl GcObject for $name {
+            fn header(&self) -> &GcObjectHeader {
+                &self.header
+            }
+
+            fn header_mut(&mut self) -> &mut GcObjectHeader {
+                &mut self.header
+            }
+
+            fn type_name() -> String {
+                stringify!($name).into()
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                DROPPED.with(|dropped| dropped.set(dropped.get() + 1));
+            }
+        }
+    };
+}
+
+object!(Array { items: Vec<Value> });
+object!(Map { entries: HashMap<String, Value> });
+object!(Closure { captures: Vec<Value> });
+
+#[track_caller]
+fn assert_empty(heap: &GcHeap) {
+    assert_eq!(heap.object_count(), 0);
+    assert_eq!(heap.bytes_allocated(), 0);
+}
+
+#[test]
+fn array_containing_itself() {
+    let mut heap = GcHeap::default();
+
+    let mut array = Array::alloc(&mut heap);
+    array.items.push(Value::Integer(1));
+    let itself = Value::Array(array);
+    array.items.push(itself);
+
+    heap.collect(|tracer| tracer.mark(array));
+    assert_eq!(heap.object_count(), 1);
+
+    heap.collect(|_| {});
+    assert_empty(&heap);
+    assert_eq!(dropped(), 1);
+}
+
+#[test]
+fn arrays_containing_each_other() {
+    let mut heap = GcHeap::default();
+
+    let mut first = Array::alloc(&mut heap);
+    let mut second = Array::alloc(&mut heap);
+    first.items.push(Value::Array(second));
+    second.items.push(Value::Array(first));
+
+    // either one keeps the whole cycle alive
+    heap.collect(|tracer| tracer.mark(second));
+    assert_eq!(heap.object_count(), 2);
+
+    heap.collect(|_| {});
+    assert_empty(&heap);
+    assert_eq!(dropped(), 2);
+}
+
+#[test]
+fn map_containing_itself() {
+    let mut heap = GcHeap::default();
+
+    let mut map = Map::alloc(&mut heap);
+    let mut array = Array::alloc(&mut heap);
+    let itself = Value::Map(map);
+    map.entries.insert("self".into(), itself);
+    map.entries.insert("items".into(), Value::Array(array));
+    array.items.push(Value::Map(map));
+
+    heap.collect(|tracer| tracer.mark(array));
+    assert_eq!(heap.object_count(), 2);
+
+    heap.collect(|_| {});
+    assert_empty(&heap);
+    assert_eq!(dropped(), 2);
+}
+
+#[test]
+fn closure_capturing_itself() {
+    let mut heap = GcHeap::default();
+
+    // a recursive closure captures the variable it is stored in
+    let mut closure = Closure::alloc(&mut heap);
+    let mut environment = Map::alloc(&mut heap);
+    environment.entries.insert("f".into(), Value::Closure(closure));
+    closure.captures.push(Value::Map(environment));
+    let itself = Value::Closure(closure);
+    closure.captures.push(itself);
+
+    heap.collect(|tracer| tracer.mark(environment));
+    assert_eq!(heap.object_count(), 2);
+
+    heap.collect(|_| {});
+    assert_empty(&heap);
+    assert_eq!(dropped(), 2);
+}
+
+#[test]
+fn garbage_cycle_next_to_live_objects() {
+    let mut heap = GcHeap::default();
+
+    let mut live = Array::alloc(&mut heap);
+    let mut garbage = Array::alloc(&mut heap);
+    let itself = Value::Array(garbage);
+    garbage.items.push(itself);
+    garbage.items.push(Value::Array(live));
+    live.items.push(Value::Integer(2));
+
+    heap.collect(|tracer| tracer.mark(live));
+    assert_eq!(heap.object_count(), 1);
+    assert_eq!(dropped(), 1);
+
+    let Value::Integer(value) = live.items[0] else {
+        panic!("item is not an Integer!");
+    };
+    assert_eq!(value, 2);
+}
+
+#[test]
+fn dropping_the_heap_frees_cycles() {
+    let mut heap = GcHeap::default();
+
+    let mut array = Array::alloc(&mut heap);
+    let mut closure = Closure::alloc(&mut heap);
+    array.items.push(Value::Closure(closure));
+    closure.captures.push(Value::Array(array));
+
+    drop(heap);
+    assert_eq!(dropped(), 2);
+}
@@ -0,0 +1,150 @@
+//! Finalizers, which clean up after objects before they are reclaimed.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use belvm_gc::gc::{GcHeap, GcObject, GcObjectHeader, GcPtr, Trace, Tracer, WeakGcPtr};
+use belvm_gc::nursery::BLOCK_SIZE;
+
+/// Names of the resources closed so far, in order
+type Closed = Rc<RefCell<Vec<String>>>;
+
+/// An object wrapping a resource of the host, like a file handle
+struct Resource {
+    header: GcObjectHeader,
+    name: String,
+    closed: Closed,
+
+    /// A resource the finalizer reads from
+    parent: Option<GcPtr<Resource>>,
+
+    /// A weak pointer the finalizer checks
+    weak: Option<WeakGcPtr<Resource>>,
+}
+
+impl Resource {
+    fn alloc(heap: &mut GcHeap, name: &str, closed: &Closed) -> GcPtr<Resource> {
+        heap.alloc(Resource {
+            header: GcObjectHeader::new::<Self>(),
+            name: name.into(),
+            closed: Rc::clone(closed),
+            parent: None,
+            weak: None,
+        })
+        .unwrap()
+    }
+}
+
+impl Trace for Resource {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.parent.trace(tracer);
+    }
+}
+
+impl GcObject for Resource {
+    fn header(&self) -> &GcObjectHeader {
+        &self.header
+    }
+
+    fn header_mut(&mut self) -> &mut GcObjectHeader {
+        &mut self.header
+    }
+
+    fn type_name() -> String {
+        String::from("Resource")
+    }
+
+    fn finalize(&mut self) {
+        let mut name = match self.parent {
+            Some(parent) => format!("{}/{}", parent.name, self.name),
+            None => self.name.clone(),
+        };
+
+        if self.weak.as_ref().is_some_and(|weak| weak.upgrade().is_none()) {
+            name.push_str(" (weak cleared)");
+        }
+
+        self.closed.borrow_mut().push(name);
+    }
+}
+
+fn closed(closed: &Closed) -> Vec<String> {
+    let mut names = closed.borrow().clone();
+    names.sort();
+    names
+}
+
+#[test]
+fn finalized_when_reclaimed() {
+    let mut heap = GcHeap::default();
+    let log = Closed::default();
+
+    let kept = Resource::alloc(&mut heap, "kept", &log);
+    Resource::alloc(&mut heap, "file", &log);
+
+    heap.collect(|tracer| tracer.mark(kept));
+    assert_eq!(closed(&log), ["file"]);
+
+    // finalized only once
+    heap.collect(|tracer| tracer.mark(kept));
+    assert_eq!(closed(&log), ["file"]);
+}
+
+#[test]
+fn finalizers_can_use_reclaimed_objects() {
+    let mut heap = GcHeap::default();
+    let log = Closed::default();
+
+    // whichever is freed first, the child's finalizer reads its parent
+    let parent = Resource::alloc(&mut heap, "dir", &log);
+    let mut child = Resource::alloc(&mut heap, "file", &log);
+    child.parent = Some(parent);
+
+    heap.collect(|_| {});
+    assert_eq!(closed(&log), ["dir", "dir/file"]);
+}
+
+#[test]
+fn young_objects() {
+    let mut heap = GcHeap::default();
+    heap.set_nursery_size(BLOCK_SIZE);
+    let log = Closed::default();
+
+    let old = Resource::alloc(&mut heap, "old", &log);
+    heap.collect_minor(|tracer| tracer.mark(old));
+
+    Resource::alloc(&mut heap, "young", &log);
+    heap.collect_minor(|_| {});
+    assert_eq!(closed(&log), ["young"]);
+
+    heap.collect(|_| {});
+    assert_eq!(closed(&log), ["old", "young"]);
+}
+
+#[test]
+fn finalized_when_the_heap_is_dropped() {
+    let mut heap = GcHeap::default();
+    heap.set_nursery_size(BLOCK_SIZE);
+    let log = Closed::default();
+
+    let old = Resource::alloc(&mut heap, "old", &log);
+    heap.collect_minor(|tracer| tracer.mark(old));
+    let mut young = Resource::alloc(&mut heap, "young", &log);
+    young.parent = Some(old);
+    heap.write_barrier(young);
+
+    drop(heap);
+    assert_eq!(closed(&log), ["old", "old/young"]);
+}
+
+#[test]
+fn weak_pointers_are_cleared_first() {
+    let mut heap = GcHeap::default();
+    let log = Closed::default();
+
+    let mut resource = Resource::alloc(&mut heap, "file", &log);
+    resource.weak = Some(heap.downgrade(resource));
+
+    heap.collect(|_| {});
+    assert_eq!(closed(&log), ["file (weak cleared)"]);
+}
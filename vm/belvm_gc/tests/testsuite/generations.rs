@@ -0,0 +1,273 @@
+//! Collections with a nursery, where young objects are collected on their own.
+
+use belvm_gc::gc::{GcHeap, GcObject, GcObjectHeader, GcPtr, Trace, Tracer};
+use belvm_gc::nursery::BLOCK_SIZE;
+
+use super::{Integer, Node};
+
+/// Too big for the nursery
+struct Big {
+    header: GcObjectHeader,
+    children: Vec<GcPtr<Node>>,
+    _bytes: [u8; BLOCK_SIZE],
+}
+
+impl Big {
+    fn new(children: Vec<GcPtr<Node>>) -> Big {
+        Self {
+            header: GcObjectHeader::new::<Self>(),
+            children,
+            _bytes: [0; BLOCK_SIZE],
+        }
+    }
+}
+
+impl Trace for Big {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.children.trace(tracer);
+    }
+}
+
+impl GcObject for Big {
+    fn header(&self) -> &GcObjectHeader {
+        &self.header
+    }
+
+    fn header_mut(&mut self) -> &mut GcObjectHeader {
+        &mut self.header
+    }
+
+    fn type_name() -> String {
+        String::from("Big")
+    }
+}
+
+fn generational_heap() -> GcHeap {
+    let mut heap = GcHeap::default();
+    heap.set_nursery_size(BLOCK_SIZE);
+    heap
+}
+
+#[test]
+fn nursery_is_off_by_default() {
+    let mut heap = GcHeap::default();
+    assert_eq!(heap.nursery_size(), 0);
+
+    let object = heap.alloc(Integer::new(1)).unwrap();
+    assert!(!object.header().is_young);
+}
+
+#[test]
+fn nursery_off_never_asks_for_minor_collections() {
+    let mut heap = GcHeap::default();
+    for i in 0..100 {
+        heap.alloc(Integer::new(i)).unwrap();
+    }
+    assert!(!heap.should_collect_minor());
+
+    // cut down to nothing by the limit
+    let mut heap = generational_heap();
+    heap.set_limit(Some(1000));
+    for i in 0..100 {
+        heap.alloc(Integer::new(i)).unwrap();
+    }
+    assert!(!heap.should_collect_minor());
+}
+
+#[test]
+fn nursery_size_is_rounded_to_blocks() {
+    let mut heap = GcHeap::default();
+
+    heap.set_nursery_size(1);
+    assert_eq!(heap.nursery_size(), BLOCK_SIZE);
+
+    heap.set_nursery_size(BLOCK_SIZE * 2 + 1);
+    assert_eq!(heap.nursery_size(), BLOCK_SIZE * 3);
+}
+
+#[test]
+fn limit_cuts_down_the_nursery() {
+    let mut heap = generational_heap();
+
+    heap.set_limit(Some(4096));
+    assert_eq!(heap.nursery_size(), 0);
+
+    heap.set_limit(Some(BLOCK_SIZE * 3));
+    assert_eq!(heap.nursery_size(), BLOCK_SIZE);
+
+    heap.set_limit(None);
+    heap.set_nursery_size(BLOCK_SIZE * 4);
+    assert_eq!(heap.nursery_size(), BLOCK_SIZE * 4);
+}
+
+#[test]
+fn promoted_objects_count_their_blocks() {
+    let mut heap = generational_heap();
+
+    // every survivor keeps the block it was allocated in
+    let mut kept = Vec::new();
+    for i in 0..10 {
+        kept.push(heap.alloc(Integer::new(i)).unwrap());
+        heap.collect_minor(|tracer| kept.iter().for_each(|object| tracer.mark(*object)));
+    }
+
+    assert_eq!(heap.bytes_allocated(), size_of::<Integer>() * 10);
+    assert!(heap.bytes_reserved() >= BLOCK_SIZE * 10);
+    assert_eq!(heap.stats().reserved, heap.bytes_reserved());
+
+    heap.set_limit(Some(BLOCK_SIZE * 5));
+    assert!(heap.is_over_limit());
+
+    heap.collect(|_| {});
+    assert!(heap.bytes_reserved() <= BLOCK_SIZE * 2);
+    assert!(!heap.is_over_limit());
+}
+
+#[test]
+fn minor_collection_promotes_survivors() {
+    let mut heap = generational_heap();
+
+    let kept = heap.alloc(Integer::new(1)).unwrap();
+    heap.alloc(Integer::new(2)).unwrap();
+    assert!(kept.header().is_young);
+
+    heap.collect_minor(|tracer| tracer.mark(kept));
+
+    assert_eq!(heap.object_count(), 1);
+    assert_eq!(heap.bytes_allocated(), size_of::<Integer>());
+    assert_eq!(heap.minor_collections(), 1);
+    assert_eq!(heap.collections(), 0);
+    assert!(!kept.header().is_young);
+    assert_eq!(kept.value, 1);
+
+    // promoted objects are only freed by a full collection
+    heap.collect_minor(|_| {});
+    assert_eq!(heap.object_count(), 1);
+
+    heap.collect(|_| {});
+    assert_eq!(heap.object_count(), 0);
+    assert_eq!(heap.bytes_allocated(), 0);
+}
+
+#[test]
+fn minor_collection_traces_young_children() {
+    let mut heap = generational_heap();
+
+    let leaf = heap.alloc(Node::new(vec![])).unwrap();
+    let root = heap.alloc(Node::new(vec![leaf])).unwrap();
+    heap.alloc(Node::new(vec![leaf])).unwrap();
+
+    heap.collect_minor(|tracer| tracer.mark(root));
+    assert_eq!(heap.object_count(), 2);
+    assert_eq!(root.children[0].children.len(), 0);
+}
+
+#[test]
+fn full_collection_promotes_survivors() {
+    let mut heap = generational_heap();
+
+    let kept = heap.alloc(Integer::new(1)).unwrap();
+    heap.alloc(Integer::new(2)).unwrap();
+
+    heap.collect(|tracer| tracer.mark(kept));
+
+    assert_eq!(heap.object_count(), 1);
+    assert!(!kept.header().is_young);
+}
+
+#[test]
+fn write_barrier_keeps_young_objects_alive() {
+    let mut heap = GcHeap::default();
+    let mut old = heap.alloc(Node::new(vec![])).unwrap();
+
+    heap.set_nursery_size(BLOCK_SIZE);
+    let young = heap.alloc(Node::new(vec![])).unwrap();
+    old.children.push(young);
+    heap.write_barrier(old);
+
+    // the old root isn't traced by a minor collection, the barrier is what
+    // keeps its child alive
+    heap.collect_minor(|tracer| tracer.mark(old));
+    assert_eq!(heap.object_count(), 2);
+    assert!(!young.header().is_young);
+
+    old.children.clear();
+    heap.collect(|tracer| tracer.mark(old));
+    assert_eq!(heap.object_count(), 1);
+}
+
+#[test]
+fn old_objects_are_remembered_when_allocated() {
+    let mut heap = generational_heap();
+
+    // an object too big for the nursery is old from the start, and can be
+    // created pointing to young ones without a barrier
+    let young = heap.alloc(Node::new(vec![])).unwrap();
+    let old = heap.alloc(Big::new(vec![young])).unwrap();
+    assert!(!old.header().is_young);
+
+    heap.collect_minor(|_| {});
+    assert_eq!(heap.object_count(), 2);
+    assert!(!young.header().is_young);
+}
+
+#[test]
+fn full_nursery_falls_back_to_the_old_generation() {
+    let mut heap = generational_heap();
+
+    let first = heap.alloc(Integer::new(0)).unwrap();
+    while !heap.should_collect_minor() {
+        heap.alloc(Integer::new(1)).unwrap();
+    }
+
+    let overflow = heap.alloc(Integer::new(2)).unwrap();
+    assert!(!overflow.header().is_young);
+
+    // the object that didn't fit and the overflow are old already
+    heap.collect_minor(|tracer| tracer.mark(first));
+    assert!(!heap.should_collect_minor());
+    assert_eq!(heap.object_count(), 3);
+
+    // the nursery has room again
+    assert!(heap.alloc(Integer::new(3)).unwrap().header().is_young);
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn many_minor_collections() {
+    let mut heap = generational_heap();
+
+    let mut list = heap.alloc(Node::new(vec![])).unwrap();
+    for i in 0..100_000 {
+        let node = heap.alloc(Node::new(vec![list])).unwrap();
+
+        // every tenth node stays in the list
+        if i % 10 == 0 {
+            list = node;
+        }
+
+        if heap.should_collect_minor() {
+            heap.collect_minor(|tracer| tracer.mark(list));
+        }
+    }
+
+    // the nodes that didn't fit in the nursery are left for a full collection
+    heap.collect(|tracer| tracer.mark(list));
+    assert_eq!(heap.object_count(), 10_001);
+    assert!(heap.minor_collections() > 1);
+
+    // frees the promoted objects along with the blocks they were in
+    heap.collect(|_| {});
+    assert_eq!(heap.object_count(), 0);
+}
+
+#[test]
+fn dropping_the_heap_frees_young_objects() {
+    let mut heap = generational_heap();
+
+    let old = heap.alloc(Integer::new(1)).unwrap();
+    heap.collect_minor(|tracer| tracer.mark(old));
+    heap.alloc(Node::new(vec![])).unwrap();
+
+    drop(heap);
+}
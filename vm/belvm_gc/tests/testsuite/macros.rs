@@ -0,0 +1,91 @@
+use belvm_gc::gc::{GcHeap, GcObject, GcObjectHeader, GcPtr};
+use belvm_macros::belalang_object;
+
+use crate::{Integer, Node};
+
+#[belalang_object(name = "Array")]
+struct Array {
+    items: Vec<GcPtr<Integer>>,
+    next: Option<GcPtr<Array>>,
+    #[trace]
+    nodes: Vec<Option<Node>>,
+    capacity: usize,
+}
+
+impl Array {
+    fn new(items: Vec<GcPtr<Integer>>, next: Option<GcPtr<Array>>) -> Self {
+        Self {
+            header: GcObjectHeader::new::<Self>(),
+            capacity: items.len(),
+            items,
+            next,
+            nodes: Vec::new(),
+        }
+    }
+}
+
+#[belalang_object(name = "Pair")]
+struct Pair<T: GcObject + 'static> {
+    first: GcPtr<T>,
+    second: GcPtr<T>,
+}
+
+#[test]
+fn implements_gc_object() {
+    let mut heap = GcHeap::default();
+    let array = heap.alloc(Array::new(vec![], None)).unwrap();
+
+    assert_eq!(Array::type_name(), "Array");
+    assert_eq!(array.header().obj_type, Array::r#type());
+    assert_eq!(array.capacity, 0);
+}
+
+#[test]
+fn traces_pointer_fields() {
+    let mut heap = GcHeap::default();
+
+    let one = heap.alloc(Integer::new(1)).unwrap();
+    let two = heap.alloc(Integer::new(2)).unwrap();
+    let tail = heap.alloc(Array::new(vec![two], None)).unwrap();
+    let head = heap.alloc(Array::new(vec![one], Some(tail))).unwrap();
+    heap.alloc(Integer::new(3)).unwrap();
+
+    heap.collect(|tracer| tracer.mark(head));
+    assert_eq!(heap.object_count(), 4);
+    assert_eq!(tail.items[0].value, 2);
+
+    heap.collect(|tracer| tracer.mark(tail));
+    assert_eq!(heap.object_count(), 2);
+}
+
+#[test]
+fn traces_marked_fields() {
+    let mut heap = GcHeap::default();
+
+    let leaf = heap.alloc(Node::new(vec![])).unwrap();
+    let mut array = Array::new(vec![], None);
+    array.nodes.push(Some(Node::new(vec![leaf])));
+    let array = heap.alloc(array).unwrap();
+
+    heap.collect(|tracer| tracer.mark(array));
+    assert_eq!(heap.object_count(), 2);
+}
+
+#[test]
+fn supports_generics() {
+    let mut heap = GcHeap::default();
+
+    let first = heap.alloc(Integer::new(1)).unwrap();
+    let second = heap.alloc(Integer::new(2)).unwrap();
+    let pair = heap
+        .alloc(Pair {
+            header: GcObjectHeader::new::<Pair<Integer>>(),
+            first,
+            second,
+        })
+        .unwrap();
+
+    heap.collect(|tracer| tracer.mark(pair));
+    assert_eq!(heap.object_count(), 3);
+    assert_eq!(Pair::<Integer>::type_name(), "Pair");
+}
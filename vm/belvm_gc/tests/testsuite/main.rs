@@ -1,5 +1,17 @@
-use belvm_gc::gc::{GcHeap, GcObject, GcObjectHeader};
-use belvm_gc::with_heap;
+//! Run these under miri with `cargo miri test -p belvm_gc`, with the
+//! `gc-stress` feature to verify the heap around every collection. The
+//! slowest tests are skipped there.
+
+mod cycles;
+mod finalizers;
+mod generations;
+mod macros;
+mod snapshot;
+mod stats;
+mod verify;
+mod weak;
+
+use belvm_gc::gc::{GcHeap, GcObject, GcObjectHeader, GcPtr, Trace, Tracer};
 
 #[derive(Clone)]
 struct Integer {
@@ -16,6 +28,10 @@ impl Integer {
     }
 }
 
+impl Trace for Integer {
+    fn trace(&self, _tracer: &mut Tracer) {}
+}
+
 impl GcObject for Integer {
     fn header(&self) -> &GcObjectHeader {
         &self.header
@@ -48,6 +64,10 @@ impl Float {
     }
 }
 
+impl Trace for Float {
+    fn trace(&self, _tracer: &mut Tracer) {}
+}
+
 impl GcObject for Float {
     fn header(&self) -> &GcObjectHeader {
         &self.header
@@ -65,32 +85,41 @@ impl GcObject for Float {
     }
 }
 
-#[test]
-fn increments_ref_count() {
-    let int = with_heap(|heap| heap.alloc(Integer::new(1)).unwrap());
-    assert_eq!(int.header().ref_count.get(), 1);
+struct Node {
+    header: GcObjectHeader,
+    children: Vec<GcPtr<Node>>,
 }
 
-#[test]
-#[allow(unused_variables)]
-fn increments_ref_count_2() {
-    let int = with_heap(|heap| heap.alloc(Integer::new(1)).unwrap());
-    let int2 = int.clone(); // should increment the ref_count to 2
-
-    assert_eq!(int.header().ref_count.get(), 2);
+impl Node {
+    pub fn new(children: Vec<GcPtr<Node>>) -> Node {
+        Self {
+            header: GcObjectHeader::new::<Self>(),
+            children,
+        }
+    }
 }
 
-#[test]
-fn drop_decrements_ref_count() {
-    let int = with_heap(|heap| heap.alloc(Integer::new(1)).unwrap());
-    let int2 = int.clone();
+impl Trace for Node {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.children.trace(tracer);
+    }
+}
 
-    assert_eq!(int.header().ref_count.get(), 2); // from 2
-    assert_eq!(int2.header().ref_count.get(), 2);
+impl GcObject for Node {
+    fn header(&self) -> &GcObjectHeader {
+        &self.header
+    }
 
-    drop(int2);
+    fn header_mut(&mut self) -> &mut GcObjectHeader {
+        &mut self.header
+    }
 
-    assert_eq!(int.header().ref_count.get(), 1); // to 1
+    fn type_name() -> String
+    where
+        Self: Sized,
+    {
+        String::from("Node")
+    }
 }
 
 #[test]
@@ -133,3 +162,68 @@ fn test_heap_drop() {
 
     // no assertions needed --- if it doesn't crash, the test passes
 }
+
+#[test]
+fn collect_frees_unreachable() {
+    let mut heap = GcHeap::default();
+
+    let kept = heap.alloc(Integer::new(1)).unwrap();
+    heap.alloc(Float::new(2.0)).unwrap();
+    heap.alloc(Integer::new(3)).unwrap();
+
+    heap.collect(|tracer| tracer.mark(kept));
+
+    assert_eq!(heap.object_count(), 1);
+    assert_eq!(heap.bytes_allocated(), size_of::<Integer>());
+    assert_eq!(kept.value, 1);
+}
+
+#[test]
+fn collect_traces_children() {
+    let mut heap = GcHeap::default();
+
+    let leaf = heap.alloc(Node::new(vec![])).unwrap();
+    let middle = heap.alloc(Node::new(vec![leaf, leaf])).unwrap();
+    let root = heap.alloc(Node::new(vec![middle])).unwrap();
+    heap.alloc(Node::new(vec![leaf])).unwrap();
+
+    heap.collect(|tracer| tracer.mark(root));
+    assert_eq!(heap.object_count(), 3);
+
+    // marks are cleared, so the next collection starts over
+    heap.collect(|tracer| tracer.mark(middle));
+    assert_eq!(heap.object_count(), 2);
+    assert_eq!(middle.children.len(), 2);
+
+    heap.collect(|_| {});
+    assert_eq!(heap.object_count(), 0);
+    assert_eq!(heap.bytes_allocated(), 0);
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn collect_deep_structures() {
+    let mut heap = GcHeap::default();
+
+    let mut list = heap.alloc(Node::new(vec![])).unwrap();
+    for _ in 0..100_000 {
+        list = heap.alloc(Node::new(vec![list])).unwrap();
+    }
+
+    heap.collect(|tracer| tracer.mark(list));
+    assert_eq!(heap.object_count(), 100_001);
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn should_collect() {
+    let mut heap = GcHeap::default();
+    assert!(!heap.should_collect());
+
+    while !heap.should_collect() {
+        heap.alloc(Integer::new(1)).unwrap();
+    }
+
+    heap.collect(|_| {});
+    assert!(!heap.should_collect());
+}
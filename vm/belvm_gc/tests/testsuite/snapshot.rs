@@ -0,0 +1,124 @@
+//! Heap snapshots, which show what is on the heap and what keeps it there.
+
+use belvm_gc::gc::{GcHeap, GcPtr};
+use belvm_gc::snapshot::RootSnapshot;
+
+use super::{Integer, Node};
+
+#[test]
+fn objects_and_references() {
+    let mut heap = GcHeap::default();
+
+    let leaf = heap.alloc(Node::new(vec![])).unwrap();
+    let root = heap.alloc(Node::new(vec![leaf, leaf])).unwrap();
+    let number = heap.alloc(Integer::new(1)).unwrap();
+
+    let snapshot = heap.snapshot(|tracer| tracer.mark(root));
+    assert_eq!(snapshot.objects.len(), 3);
+    assert_eq!(snapshot.bytes, heap.bytes_allocated());
+
+    // objects are sorted by id, so snapshots can be diffed
+    assert!(snapshot.objects.is_sorted_by_key(|object| object.id));
+
+    let object = snapshot.object(root.as_ptr().addr()).unwrap();
+    assert_eq!(object.type_name, "Node");
+    assert_eq!(object.size, size_of::<Node>());
+    assert_eq!(object.references, [leaf.as_ptr().addr(), leaf.as_ptr().addr()]);
+
+    let object = snapshot.object(number.as_ptr().addr()).unwrap();
+    assert_eq!(object.type_name, "Integer");
+    assert!(object.references.is_empty());
+}
+
+#[test]
+fn root_paths() {
+    let mut heap = GcHeap::default();
+
+    let garbage = heap.alloc(Node::new(vec![])).unwrap();
+    let deep = heap.alloc(Node::new(vec![])).unwrap();
+    let middle = heap.alloc(Node::new(vec![deep])).unwrap();
+    let first = heap.alloc(Node::new(vec![middle])).unwrap();
+    let second = heap.alloc(Node::new(vec![deep])).unwrap();
+
+    let snapshot = heap.snapshot(|tracer| {
+        tracer.root("first");
+        tracer.mark(first);
+        tracer.root("second");
+        tracer.mark(second);
+    });
+
+    assert_eq!(
+        snapshot.roots,
+        [
+            RootSnapshot {
+                name: "first".into(),
+                object: first.as_ptr().addr(),
+            },
+            RootSnapshot {
+                name: "second".into(),
+                object: second.as_ptr().addr(),
+            },
+        ]
+    );
+
+    // the shortest path wins, even if it starts at a later root
+    let path = snapshot
+        .object(deep.as_ptr().addr())
+        .unwrap()
+        .root_path
+        .as_ref()
+        .unwrap();
+    assert_eq!(path.root, "second");
+    assert_eq!(path.retainer, Some(second.as_ptr().addr()));
+    assert_eq!(path.length, 2);
+
+    let ids = |nodes: &[GcPtr<Node>]| nodes.iter().map(|node| node.as_ptr().addr()).collect();
+    assert_eq!(snapshot.path_to(middle.as_ptr().addr()), Some(ids(&[first, middle])));
+    assert_eq!(snapshot.path_to(deep.as_ptr().addr()), Some(ids(&[second, deep])));
+
+    // garbage isn't reachable
+    let object = snapshot.object(garbage.as_ptr().addr()).unwrap();
+    assert_eq!(object.root_path, None);
+    assert_eq!(snapshot.path_to(garbage.as_ptr().addr()), None);
+}
+
+#[test]
+fn duplicate_roots() {
+    let mut heap = GcHeap::default();
+
+    let first = heap.alloc(Node::new(vec![])).unwrap();
+    let second = heap.alloc(Node::new(vec![])).unwrap();
+
+    let snapshot = heap.snapshot(|tracer| {
+        tracer.mark(first);
+        tracer.mark(second);
+        tracer.mark(first);
+    });
+
+    let roots: Vec<_> = snapshot.roots.iter().map(|root| root.object).collect();
+    assert_eq!(roots, [first.as_ptr().addr(), second.as_ptr().addr()]);
+}
+
+#[test]
+fn json() {
+    let mut heap = GcHeap::default();
+
+    let leaf = heap.alloc(Node::new(vec![])).unwrap();
+    let root = heap.alloc(Node::new(vec![leaf])).unwrap();
+
+    let snapshot = heap.snapshot(|tracer| tracer.mark(root));
+    let mut json = Vec::new();
+    snapshot.write_json(&mut json).unwrap();
+
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    let objects = json["objects"].as_array().unwrap();
+    assert_eq!(objects.len(), 2);
+
+    let leaf = objects
+        .iter()
+        .find(|object| object["id"] == leaf.as_ptr().addr())
+        .unwrap();
+    assert_eq!(leaf["type_name"], "Node");
+    assert_eq!(leaf["root_path"]["root"], "roots");
+    assert_eq!(leaf["root_path"]["retainer"], root.as_ptr().addr());
+}
@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use belvm_gc::gc::{DEFAULT_THRESHOLD, GcHeap, GcObject, GcObjectHeader, Trace, Tracer};
+
+struct Small {
+    header: GcObjectHeader,
+}
+
+struct Large {
+    header: GcObjectHeader,
+    _payload: [u64; 16],
+}
+
+macro_rules! leaf_object {
+    ($name:ident) => {
+        impl Trace for $name {
+            fn trace(&self, _tracer: &mut Tracer) {}
+        }
+
+        impl GcObject for $name {
+            fn header(&self) -> &GcObjectHeader {
+                &self.header
+            }
+
+            fn header_mut(&mut self) -> &mut GcObjectHeader {
+                &mut self.header
+            }
+
+            fn type_name() -> String {
+                stringify!($name).into()
+            }
+        }
+    };
+}
+
+leaf_object!(Small);
+leaf_object!(Large);
+
+fn small() -> Small {
+    Small {
+        header: GcObjectHeader::new::<Small>(),
+    }
+}
+
+fn large() -> Large {
+    Large {
+        header: GcObjectHeader::new::<Large>(),
+        _payload: [0; 16],
+    }
+}
+
+#[test]
+fn counts_objects_by_type() {
+    let mut heap = GcHeap::default();
+
+    let kept = heap.alloc(small()).unwrap();
+    heap.alloc(small()).unwrap();
+    heap.alloc(small()).unwrap();
+    heap.alloc(large()).unwrap();
+
+    let stats = heap.stats();
+    assert_eq!(stats.objects, 4);
+    assert_eq!(stats.bytes, 3 * size_of::<Small>() + size_of::<Large>());
+    assert_eq!(stats.collections, 0);
+    assert_eq!(stats.total_pause, Duration::ZERO);
+
+    let names: Vec<_> = stats.types.iter().map(|ty| (ty.name.as_str(), ty.objects)).collect();
+    assert_eq!(names, [("Large", 1), ("Small", 3)]);
+    assert_eq!(stats.types[1].obj_type, Small::r#type());
+    assert_eq!(stats.types[1].bytes, 3 * size_of::<Small>());
+
+    heap.collect(|tracer| tracer.mark(kept));
+
+    let stats = heap.stats();
+    assert_eq!(stats.objects, 1);
+    assert_eq!(stats.bytes, size_of::<Small>());
+    assert_eq!(stats.types.len(), 1);
+    assert_eq!(stats.collections, 1);
+    assert!(stats.max_pause <= stats.total_pause);
+}
+
+#[test]
+#[cfg_attr(feature = "gc-stress", ignore = "stress mode collects after every allocation")]
+fn threshold() {
+    let mut heap = GcHeap::default();
+    heap.set_threshold(10 * size_of::<Small>());
+
+    for _ in 0..9 {
+        heap.alloc(small()).unwrap();
+    }
+    assert!(!heap.should_collect());
+
+    let kept = heap.alloc(small()).unwrap();
+    assert!(heap.should_collect());
+
+    // the threshold never goes below the one that was set
+    heap.collect(|tracer| tracer.mark(kept));
+    assert_eq!(heap.object_count(), 1);
+    assert!(!heap.should_collect());
+}
+
+#[test]
+#[cfg_attr(feature = "gc-stress", ignore = "stress mode collects after every allocation")]
+fn growth_factor() {
+    let mut heap = GcHeap::default();
+    heap.set_threshold(0);
+    heap.set_growth_factor(3.0);
+
+    let objects: Vec<_> = (0..4).map(|_| heap.alloc(small()).unwrap()).collect();
+    heap.collect(|tracer| objects.trace(tracer));
+
+    // four live objects, so the heap can take twelve before the next one
+    for _ in 0..7 {
+        heap.alloc(small()).unwrap();
+    }
+    assert!(!heap.should_collect());
+
+    heap.alloc(small()).unwrap();
+    assert!(heap.should_collect());
+}
+
+#[test]
+#[should_panic(expected = "growth factor must be at least 1")]
+fn growth_factor_below_one() {
+    GcHeap::default().set_growth_factor(0.5);
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+#[cfg_attr(feature = "gc-stress", ignore = "stress mode collects after every allocation")]
+fn default_threshold() {
+    let mut heap = GcHeap::default();
+
+    while heap.bytes_allocated() + size_of::<Large>() < DEFAULT_THRESHOLD {
+        heap.alloc(large()).unwrap();
+    }
+    assert!(!heap.should_collect());
+}
+
+#[test]
+#[cfg_attr(feature = "gc-stress", ignore = "stress mode collects after every allocation")]
+fn limit() {
+    let mut heap = GcHeap::default();
+    heap.set_limit(Some(2 * size_of::<Small>()));
+    assert_eq!(heap.limit(), Some(2 * size_of::<Small>()));
+
+    let kept = heap.alloc(small()).unwrap();
+    heap.alloc(small()).unwrap();
+    assert!(!heap.is_over_limit());
+    assert!(!heap.should_collect());
+
+    // going over the limit asks for a collection, long before the threshold
+    heap.alloc(small()).unwrap();
+    assert!(heap.is_over_limit());
+    assert!(heap.should_collect());
+
+    heap.collect(|tracer| tracer.mark(kept));
+    assert!(!heap.is_over_limit());
+
+    heap.set_limit(None);
+    for _ in 0..10 {
+        heap.alloc(small()).unwrap();
+    }
+    assert!(!heap.is_over_limit());
+}
@@ -0,0 +1,90 @@
+//! The heap verifier, which has to catch broken heaps without dereferencing
+//! anything it shouldn't, so it is sound under miri too.
+
+use belvm_gc::errors::HeapError;
+use belvm_gc::gc::{GcHeap, GcObject};
+use belvm_gc::nursery::BLOCK_SIZE;
+
+use super::{Integer, Node};
+
+#[test]
+fn sound_heap() {
+    let mut heap = GcHeap::default();
+    heap.set_nursery_size(BLOCK_SIZE);
+
+    let leaf = heap.alloc(Node::new(vec![])).unwrap();
+    let root = heap.alloc(Node::new(vec![leaf, leaf])).unwrap();
+    heap.alloc(Integer::new(1)).unwrap();
+    assert_eq!(heap.verify(|tracer| tracer.mark(root)), Ok(()));
+
+    heap.collect_minor(|tracer| tracer.mark(root));
+    heap.alloc(Node::new(vec![root])).unwrap();
+    assert_eq!(heap.verify(|tracer| tracer.mark(root)), Ok(()));
+
+    heap.collect(|_| {});
+    assert_eq!(heap.verify(|_| {}), Ok(()));
+}
+
+#[test]
+fn dangling_pointer() {
+    let mut heap = GcHeap::default();
+
+    let mut root = heap.alloc(Node::new(vec![])).unwrap();
+    let freed = heap.alloc(Node::new(vec![])).unwrap();
+    heap.collect(|tracer| tracer.mark(root));
+
+    root.children.push(freed);
+
+    let address = freed.as_ptr().addr();
+    assert_eq!(
+        heap.verify(|tracer| tracer.mark(root)),
+        Err(HeapError::DanglingPointer(address))
+    );
+
+    // unreachable objects may point anywhere
+    assert_eq!(heap.verify(|_| {}), Ok(()));
+}
+
+#[test]
+fn missing_write_barrier() {
+    let mut heap = GcHeap::default();
+    let mut old = heap.alloc(Node::new(vec![])).unwrap();
+
+    heap.set_nursery_size(BLOCK_SIZE);
+    let young = heap.alloc(Node::new(vec![])).unwrap();
+    old.children.push(young);
+
+    assert_eq!(
+        heap.verify(|tracer| tracer.mark(old)),
+        Err(HeapError::Unremembered {
+            object: old.as_ptr().addr(),
+            young: young.as_ptr().addr(),
+        })
+    );
+
+    heap.write_barrier(old);
+    assert_eq!(heap.verify(|tracer| tracer.mark(old)), Ok(()));
+}
+
+#[test]
+fn broken_headers() {
+    let mut heap = GcHeap::default();
+
+    let mut object = heap.alloc(Integer::new(1)).unwrap();
+    let address = object.as_ptr().addr();
+
+    object.header().is_marked.set(true);
+    assert_eq!(heap.verify(|_| {}), Err(HeapError::Marked(address)));
+    object.header().is_marked.set(false);
+
+    let obj_type = object.header().obj_type;
+    object.header_mut().obj_type = !obj_type;
+    assert_eq!(heap.verify(|_| {}), Err(HeapError::UnknownType(address, !obj_type)));
+    object.header_mut().obj_type = obj_type;
+
+    object.header_mut().is_young = true;
+    assert_eq!(heap.verify(|_| {}), Err(HeapError::WrongGeneration(address)));
+    object.header_mut().is_young = false;
+
+    assert_eq!(heap.verify(|_| {}), Ok(()));
+}
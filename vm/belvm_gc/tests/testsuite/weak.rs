@@ -0,0 +1,85 @@
+//! Weak pointers, which don't keep their object alive.
+
+use belvm_gc::gc::GcHeap;
+use belvm_gc::nursery::BLOCK_SIZE;
+
+use super::{Integer, Node};
+
+#[test]
+fn upgrade_while_alive() {
+    let mut heap = GcHeap::default();
+
+    let object = heap.alloc(Integer::new(7)).unwrap();
+    let weak = heap.downgrade(object);
+
+    heap.collect(|tracer| tracer.mark(object));
+
+    let upgraded = weak.upgrade().unwrap();
+    assert_eq!(upgraded.as_ptr(), object.as_ptr());
+    assert_eq!(upgraded.value, 7);
+}
+
+#[test]
+fn cleared_when_reclaimed() {
+    let mut heap = GcHeap::default();
+
+    let object = heap.alloc(Integer::new(7)).unwrap();
+    let weak = heap.downgrade(object);
+    let copy = weak.clone();
+
+    // a weak pointer isn't a root
+    heap.collect(|_| {});
+    assert_eq!(heap.object_count(), 0);
+    assert!(weak.upgrade().is_none());
+    assert!(copy.upgrade().is_none());
+
+    // the address may be reused, but the weak pointer stays cleared
+    heap.alloc(Integer::new(8)).unwrap();
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn minor_collections() {
+    let mut heap = GcHeap::default();
+    heap.set_nursery_size(BLOCK_SIZE);
+
+    let kept = heap.alloc(Node::new(vec![])).unwrap();
+    let weak_kept = heap.downgrade(kept);
+    let garbage = heap.alloc(Node::new(vec![])).unwrap();
+    let weak_garbage = heap.downgrade(garbage);
+
+    heap.collect_minor(|tracer| tracer.mark(kept));
+    assert!(weak_kept.upgrade().is_some());
+    assert!(weak_garbage.upgrade().is_none());
+
+    // promoted objects aren't reclaimed by minor collections
+    heap.collect_minor(|_| {});
+    assert!(weak_kept.upgrade().is_some());
+
+    heap.collect(|_| {});
+    assert!(weak_kept.upgrade().is_none());
+}
+
+#[test]
+fn outliving_the_heap() {
+    let mut heap = GcHeap::default();
+
+    let object = heap.alloc(Integer::new(7)).unwrap();
+    let weak = heap.downgrade(object);
+
+    drop(heap);
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn verified() {
+    let mut heap = GcHeap::default();
+
+    let object = heap.alloc(Integer::new(7)).unwrap();
+    let _weak = heap.downgrade(object);
+    let _again = heap.downgrade(object);
+    assert_eq!(heap.verify(|_| {}), Ok(()));
+
+    heap.collect(|_| {});
+    assert_eq!(heap.verify(|_| {}), Ok(()));
+}
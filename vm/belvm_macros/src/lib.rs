@@ -1,78 +1,62 @@
-use proc_macro::TokenStream;
-use quote::quote;
-use syn::parse::{Parse, ParseStream};
-use syn::{Fields, ItemStruct, Token, parse_macro_input};
-
-// TODO: add documentation and clear namings
-
-struct BelalangObjectArgs {
-    name: syn::LitStr,
-}
-
-impl Parse for BelalangObjectArgs {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        let mut name = None;
-
-        while !input.is_empty() {
-            let ident: syn::Ident = input.parse()?;
-            input.parse::<Token![=]>()?;
-
-            match ident.to_string().as_str() {
-                "name" => {
-                    if name.is_some() {
-                        return Err(syn::Error::new(ident.span(), "duplicate `name` argument"));
-                    }
-                    name = Some(input.parse()?);
-                },
-                _ => return Err(syn::Error::new(ident.span(), "unknown argument")),
-            }
-
-            if !input.is_empty() {
-                input.parse::<Token![,]>()?;
-            }
-        }
+mod object;
+mod opcodes;
 
-        Ok(BelalangObjectArgs {
-            name: name.ok_or_else(|| syn::Error::new(input.span(), "missing `name` argument"))?,
-        })
-    }
-}
+use proc_macro::TokenStream;
 
+/// Turns a struct into an object that can live on the heap of `belvm_gc`.
+///
+/// The struct gets a `pub header: GcObjectHeader` field in front of its own,
+/// which constructors initialize with `GcObjectHeader::new::<Self>()`, and
+/// implementations of `GcObject`, with `name` as its type name, and `Trace`.
+///
+/// The `Trace` implementation traces every field whose type mentions a
+/// `GcPtr`, like `GcPtr<T>`, `Option<GcPtr<T>>` or `Vec<GcPtr<T>>`. Fields
+/// holding pointers behind other types, like a `Value`, are marked with
+/// `#[trace]`. Their types have to implement `Trace`.
+///
+/// ```rust,ignore
+/// #[belalang_object(name = "Array")]
+/// pub struct ArrayObject {
+///     pub items: Vec<GcPtr<StringObject>>,
+///     #[trace]
+///     pub default: Value,
+///     pub capacity: usize,
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn belalang_object(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(item as ItemStruct);
-    let args = parse_macro_input!(attr as BelalangObjectArgs);
-
-    let Fields::Named(fields) = &input.fields else {
-        return syn::Error::new_spanned(input, "belalang_type attribute only supports structs with named fields")
-            .to_compile_error()
-            .into();
-    };
-
-    let struct_name = &input.ident;
-    let attrs = &input.attrs;
-    let vis = &input.vis;
-    let generics = &input.generics;
-    let named_fields = &fields.named;
-    let type_name = args.name;
-
-    TokenStream::from(quote! {
-        #(#attrs)*
-        #[repr(C)]
-        #[derive(Debug)]
-        #vis struct #struct_name #generics {
-            pub base: crate::BelalangBase,
-            #named_fields
-        }
-
-        impl crate::BelalangObject for #struct_name {
-            fn type_name() -> String {
-                #type_name.into()
-            }
+    object::expand(attr.into(), item.into())
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
 
-            fn as_any(&self) -> &dyn std::any::Any {
-                self
-            }
-        }
-    })
+/// Declares the instruction set of The Belalang VM from a single table.
+///
+/// Each entry is an opcode name, its byte value and an optional list of
+/// operands (`u8`, `u16` or `i16`, encoded big-endian). From the table this
+/// generates, in the invoking module:
+///
+/// * a `pub const` for every opcode, documented with its encoded size
+/// * an encoder function (the lowercased name, a raw identifier such as
+///   `r#move` if that is a keyword) for every opcode with operands
+/// * `info`, returning the mnemonic and operand layout of an opcode byte
+/// * the fieldless `Opcode` enum with `from_byte`, `byte`, `info`, `name` and
+///   `operands`
+///
+/// The generated code expects `OpcodeInfo` and `OperandType` to be in scope.
+///
+/// ```rust,ignore
+/// opcodes! {
+///     /// Stack operation -- Pop from stack
+///     POP = 0x01,
+///
+///     /// Jump operation -- Unconditional jump
+///     JUMP = 0x70 (offset: i16),
+/// }
+/// ```
+#[proc_macro]
+pub fn opcodes(input: TokenStream) -> TokenStream {
+    opcodes::expand(input.into())
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
 }
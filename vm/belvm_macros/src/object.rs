@@ -0,0 +1,137 @@
+//! Implementation of the [`belalang_object`](crate::belalang_object) macro.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::visit::{self, Visit};
+use syn::{Field, Fields, ItemStruct, LitStr, Token, Type};
+
+/// Arguments of the attribute, as in `#[belalang_object(name = "String")]`
+struct ObjectArgs {
+    name: LitStr,
+}
+
+impl Parse for ObjectArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut name = None;
+
+        while !input.is_empty() {
+            let ident: syn::Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+
+            match ident.to_string().as_str() {
+                "name" => {
+                    if name.is_some() {
+                        return Err(syn::Error::new(ident.span(), "duplicate `name` argument"));
+                    }
+                    name = Some(input.parse()?);
+                },
+                _ => return Err(syn::Error::new(ident.span(), "unknown argument")),
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        Ok(ObjectArgs {
+            name: name.ok_or_else(|| syn::Error::new(input.span(), "missing `name` argument"))?,
+        })
+    }
+}
+
+/// Looks for a `GcPtr` anywhere in a type, like in `Vec<Option<GcPtr<T>>>`.
+#[derive(Default)]
+struct FindGcPtr {
+    found: bool,
+}
+
+impl<'ast> Visit<'ast> for FindGcPtr {
+    fn visit_path_segment(&mut self, segment: &'ast syn::PathSegment) {
+        self.found |= segment.ident == "GcPtr";
+        visit::visit_path_segment(self, segment);
+    }
+}
+
+fn holds_gc_ptr(ty: &Type) -> bool {
+    let mut finder = FindGcPtr::default();
+    finder.visit_type(ty);
+    finder.found
+}
+
+/// Returns whether `field` has to be traced, removing its `#[trace]`
+/// attribute, which the compiler wouldn't know about.
+fn take_trace_attr(field: &mut Field) -> syn::Result<bool> {
+    let mut traced = holds_gc_ptr(&field.ty);
+    let mut error = None;
+
+    field.attrs.retain(|attr| {
+        if !attr.path().is_ident("trace") {
+            return true;
+        }
+
+        if let Err(err) = attr.meta.require_path_only() {
+            error.get_or_insert(err);
+        }
+
+        traced = true;
+        false
+    });
+
+    match error {
+        Some(err) => Err(err),
+        None => Ok(traced),
+    }
+}
+
+pub fn expand(attr: TokenStream, item: TokenStream) -> syn::Result<TokenStream> {
+    let args: ObjectArgs = syn::parse2(attr)?;
+    let mut input: ItemStruct = syn::parse2(item)?;
+
+    let Fields::Named(fields) = &mut input.fields else {
+        return Err(syn::Error::new_spanned(
+            input,
+            "belalang_object attribute only supports structs with named fields",
+        ));
+    };
+
+    let mut traced = Vec::new();
+    for field in &mut fields.named {
+        if take_trace_attr(field)? {
+            traced.push(field.ident.clone());
+        }
+    }
+
+    let header: Field = Field::parse_named.parse2(quote! {
+        pub header: ::belvm_gc::gc::GcObjectHeader
+    })?;
+    fields.named.insert(0, header);
+
+    let struct_name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let type_name = args.name;
+
+    Ok(quote! {
+        #input
+
+        impl #impl_generics ::belvm_gc::gc::Trace for #struct_name #ty_generics #where_clause {
+            fn trace(&self, _tracer: &mut ::belvm_gc::gc::Tracer) {
+                #(::belvm_gc::gc::Trace::trace(&self.#traced, _tracer);)*
+            }
+        }
+
+        impl #impl_generics ::belvm_gc::gc::GcObject for #struct_name #ty_generics #where_clause {
+            fn header(&self) -> &::belvm_gc::gc::GcObjectHeader {
+                &self.header
+            }
+
+            fn header_mut(&mut self) -> &mut ::belvm_gc::gc::GcObjectHeader {
+                &mut self.header
+            }
+
+            fn type_name() -> String {
+                #type_name.into()
+            }
+        }
+    })
+}
@@ -0,0 +1,311 @@
+//! Implementation of the [`opcodes!`](crate::opcodes) macro.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Ident, LitInt, Token, parenthesized};
+
+/// Operand types an opcode can take.
+#[derive(Clone, Copy)]
+enum OperandType {
+    U8,
+    U16,
+    I16,
+}
+
+impl OperandType {
+    fn width(self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::U16 | Self::I16 => 2,
+        }
+    }
+
+    fn ty(self) -> TokenStream {
+        match self {
+            Self::U8 => quote!(u8),
+            Self::U16 => quote!(u16),
+            Self::I16 => quote!(i16),
+        }
+    }
+
+    fn variant(self) -> TokenStream {
+        match self {
+            Self::U8 => quote!(OperandType::U8),
+            Self::U16 => quote!(OperandType::U16),
+            Self::I16 => quote!(OperandType::I16),
+        }
+    }
+}
+
+struct Operand {
+    name: Ident,
+    ty: OperandType,
+}
+
+impl Parse for Operand {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+
+        let ty: Ident = input.parse()?;
+        let ty = match ty.to_string().as_str() {
+            "u8" => OperandType::U8,
+            "u16" => OperandType::U16,
+            "i16" => OperandType::I16,
+            _ => {
+                return Err(syn::Error::new(
+                    ty.span(),
+                    "operands must be one of `u8`, `u16` or `i16`",
+                ));
+            },
+        };
+
+        Ok(Operand { name, ty })
+    }
+}
+
+struct Opcode {
+    attrs: Vec<Attribute>,
+    name: Ident,
+    value: LitInt,
+    operands: Vec<Operand>,
+}
+
+impl Parse for Opcode {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let value = input.parse()?;
+
+        let mut operands = Vec::new();
+        if input.peek(syn::token::Paren) {
+            let content;
+            parenthesized!(content in input);
+            operands = Punctuated::<Operand, Token![,]>::parse_terminated(&content)?
+                .into_iter()
+                .collect();
+        }
+
+        Ok(Opcode {
+            attrs,
+            name,
+            value,
+            operands,
+        })
+    }
+}
+
+struct OpcodeTable {
+    opcodes: Vec<Opcode>,
+}
+
+impl Parse for OpcodeTable {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let opcodes = Punctuated::<Opcode, Token![,]>::parse_terminated(input)?
+            .into_iter()
+            .collect();
+
+        Ok(OpcodeTable { opcodes })
+    }
+}
+
+/// `JUMP_IF_FALSE` -> `JumpIfFalse`
+fn to_camel_case(name: &Ident) -> Ident {
+    let camel: String = name
+        .to_string()
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + &chars.as_str().to_ascii_lowercase(),
+                None => String::new(),
+            }
+        })
+        .collect();
+
+    Ident::new(&camel, name.span())
+}
+
+/// `JUMP_IF_FALSE` -> `jump_if_false`
+///
+/// Lowercases `name`, escaping it as a raw identifier if the result is a
+/// keyword, such as `move` or `true`.
+fn to_snake_case(name: &Ident) -> Ident {
+    let lower = name.to_string().to_ascii_lowercase();
+
+    match syn::parse_str::<Ident>(&lower) {
+        Ok(_) => Ident::new(&lower, name.span()),
+        Err(_) => Ident::new_raw(&lower, name.span()),
+    }
+}
+
+fn size_doc(opcode: &Opcode) -> String {
+    let len: usize = 1 + opcode.operands.iter().map(|operand| operand.ty.width()).sum::<usize>();
+
+    if opcode.operands.is_empty() {
+        return " (1 byte)".into();
+    }
+
+    let operands = opcode
+        .operands
+        .iter()
+        .map(|operand| {
+            let sign = if matches!(operand.ty, OperandType::I16) {
+                "signed "
+            } else {
+                ""
+            };
+            format!("{sign}{}-bit {}", operand.ty.width() * 8, operand.name)
+        })
+        .collect::<Vec<_>>()
+        .join(" + ");
+
+    format!(" ({len} bytes: opcode + {operands})")
+}
+
+pub fn expand(input: TokenStream) -> syn::Result<TokenStream> {
+    let table: OpcodeTable = syn::parse2(input)?;
+
+    let mut constants = Vec::new();
+    let mut encoders = Vec::new();
+    let mut variants = Vec::new();
+    let mut infos = Vec::new();
+    let mut from_byte_arms = Vec::new();
+
+    for opcode in &table.opcodes {
+        let Opcode {
+            attrs,
+            name,
+            value,
+            operands,
+        } = opcode;
+
+        let mnemonic = name.to_string();
+        let size_doc = size_doc(opcode);
+        let variant = to_camel_case(name);
+        let len: usize = 1 + operands.iter().map(|operand| operand.ty.width()).sum::<usize>();
+
+        constants.push(quote! {
+            #(#attrs)*
+            #[doc = #size_doc]
+            pub const #name: u8 = #value;
+        });
+
+        let operand_types: Vec<_> = operands.iter().map(|operand| operand.ty.variant()).collect();
+        infos.push(quote! {
+            #name => Some(OpcodeInfo {
+                name: #mnemonic,
+                operands: &[#(#operand_types),*],
+            }),
+        });
+
+        let variant_doc = format!(" [`{mnemonic}`]");
+        variants.push(quote! {
+            #[doc = #variant_doc]
+            #variant = #name
+        });
+        from_byte_arms.push(quote!(#name => Some(Self::#variant),));
+
+        if operands.is_empty() {
+            continue;
+        }
+
+        let names: Vec<_> = operands.iter().map(|operand| &operand.name).collect();
+        let types: Vec<_> = operands.iter().map(|operand| operand.ty.ty()).collect();
+
+        let mut offset = 1usize;
+        let mut writes = Vec::new();
+        for operand in operands {
+            let name = &operand.name;
+            let end = offset + operand.ty.width();
+
+            writes.push(quote! {
+                bytes[#offset..#end].copy_from_slice(&#name.to_be_bytes());
+            });
+
+            offset = end;
+        }
+
+        let encoder = to_snake_case(name);
+        let encoder_doc = format!(" Encodes a [`{mnemonic}`] instruction{size_doc}");
+        let byte_names = operands
+            .iter()
+            .flat_map(|operand| match operand.ty.width() {
+                1 => vec![operand.name.to_string()],
+                _ => vec![format!("{}_hi", operand.name), format!("{}_lo", operand.name)],
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let returns_doc = format!(" {len}-byte array: [[`{mnemonic}`], {byte_names}]");
+
+        encoders.push(quote! {
+            #[doc = #encoder_doc]
+            ///
+            /// # Returns
+            #[doc = #returns_doc]
+            pub fn #encoder(#(#names: #types),*) -> [u8; #len] {
+                let mut bytes = [0u8; #len];
+                bytes[0] = #name;
+                #(#writes)*
+                bytes
+            }
+        });
+    }
+
+    Ok(quote! {
+        #(#constants)*
+
+        #(#encoders)*
+
+        /// Looks up the name and operand layout of an opcode.
+        ///
+        /// Returns `None` if `op` is not a valid opcode.
+        pub fn info(op: u8) -> Option<OpcodeInfo> {
+            match op {
+                #(#infos)*
+                _ => None,
+            }
+        }
+
+        /// Every opcode of the instruction set.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(u8)]
+        pub enum Opcode {
+            #(#variants,)*
+        }
+
+        impl Opcode {
+            /// Returns the opcode encoded as `byte`, or `None` if it is not a
+            /// valid opcode.
+            pub fn from_byte(byte: u8) -> Option<Self> {
+                match byte {
+                    #(#from_byte_arms)*
+                    _ => None,
+                }
+            }
+
+            /// Returns the byte value of the opcode.
+            pub fn byte(self) -> u8 {
+                self as u8
+            }
+
+            /// Returns the name and operand layout of the opcode.
+            pub fn info(self) -> OpcodeInfo {
+                info(self as u8).unwrap()
+            }
+
+            /// Returns the mnemonic of the opcode.
+            pub fn name(self) -> &'static str {
+                self.info().name
+            }
+
+            /// Returns the operands following the opcode.
+            pub fn operands(self) -> &'static [OperandType] {
+                self.info().operands
+            }
+        }
+    })
+}